@@ -11,29 +11,178 @@ CREATE TABLE IF NOT EXISTS Users (
     email TEXT NOT NULL UNIQUE,
     user_role TEXT NOT NULL,
     verified BOOLEAN NOT NULL,
-    verification_token TEXT
+    verification_token TEXT,
+    pending_email TEXT,
+    oidc_subject TEXT,
+    totp_secret TEXT,
+    totp_enabled BOOLEAN NOT NULL DEFAULT false,
+    totp_last_step INTEGER,
+    verification_token_expires_at INTEGER,
+    verification_sent_at INTEGER,
+    created_at INTEGER,
+    disabled BOOLEAN NOT NULL DEFAULT false,
+    deleted_at INTEGER
 );
 
 CREATE TABLE IF NOT EXISTS Reservations (
     user_name TEXT NOT NULL,
     seat_id INTEGER NOT NULL,
-    start_time TEXT NOT NULL,
-    end_time TEXT NOT NULL,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    uid TEXT,
+    sequence INTEGER NOT NULL DEFAULT 0,
+    cancelled_at INTEGER,
+    checked_in_at INTEGER,
     PRIMARY KEY (user_name, start_time, end_time),
     FOREIGN KEY(user_name) REFERENCES Users(user_name),
     FOREIGN KEY(seat_id) REFERENCES Seats(seat_id)
 );
 
+CREATE INDEX IF NOT EXISTS idx_reservations_seat_time ON Reservations (seat_id, start_time, end_time);
+CREATE INDEX IF NOT EXISTS idx_reservations_user_time ON Reservations (user_name, start_time);
+
 CREATE TABLE IF NOT EXISTS UnavailableTimeSlots (
-    start_time TEXT NOT NULL,
-    end_time TEXT NOT NULL,
-    PRIMARY KEY (start_time, end_time)
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    reason TEXT,
+    created_by TEXT,
+    source_uid TEXT,
+    UNIQUE (start_time, end_time)
 );
 
 CREATE TABLE IF NOT EXISTS BlackList (
+    ban_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    user_name TEXT NOT NULL,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    reason TEXT,
+    created_by TEXT,
+    created_at INTEGER,
+    lifted_at INTEGER,
+    lifted_by TEXT,
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+CREATE TABLE IF NOT EXISTS SeatIssues (
+    issue_id INTEGER PRIMARY KEY AUTOINCREMENT,
+    seat_id INTEGER NOT NULL,
+    user_name TEXT NOT NULL,
+    category TEXT NOT NULL,
+    description TEXT NOT NULL,
+    photo_path TEXT,
+    status TEXT NOT NULL,
+    assignee TEXT,
+    resolution TEXT,
+    created_at INTEGER NOT NULL,
+    resolved_at INTEGER,
+    FOREIGN KEY(seat_id) REFERENCES Seats(seat_id),
+    FOREIGN KEY(user_name) REFERENCES Users(user_name),
+    FOREIGN KEY(assignee) REFERENCES Users(user_name)
+);
+
+CREATE TABLE IF NOT EXISTS SeatMaintenance (
+    seat_id INTEGER NOT NULL,
+    start_time INTEGER NOT NULL,
+    end_time INTEGER NOT NULL,
+    issue_id INTEGER,
+    PRIMARY KEY (seat_id, start_time),
+    FOREIGN KEY(seat_id) REFERENCES Seats(seat_id),
+    FOREIGN KEY(issue_id) REFERENCES SeatIssues(issue_id)
+);
+
+CREATE TABLE IF NOT EXISTS OpeningHours (
+    weekday INTEGER PRIMARY KEY,
+    closed BOOLEAN NOT NULL,
+    open_time TEXT,
+    close_time TEXT
+);
+
+CREATE TABLE IF NOT EXISTS OpeningHoursExceptions (
+    date TEXT PRIMARY KEY,
+    closed BOOLEAN NOT NULL,
+    open_time TEXT,
+    close_time TEXT,
+    reason TEXT
+);
+
+CREATE TABLE IF NOT EXISTS TimerRuns (
+    task TEXT PRIMARY KEY,
+    last_run INTEGER NOT NULL,
+    materialized_until TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS CalendarFeeds (
+    user_name TEXT PRIMARY KEY,
+    token TEXT NOT NULL UNIQUE,
+    created_at INTEGER NOT NULL,
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+
+CREATE TABLE IF NOT EXISTS PasswordResetTokens (
+    token_hash TEXT PRIMARY KEY,
     user_name TEXT NOT NULL,
-    start_time TEXT NOT NULL,
-    end_time TEXT NOT NULL,
-    PRIMARY KEY (user_name),
+    expires_at INTEGER NOT NULL,
     FOREIGN KEY(user_name) REFERENCES Users(user_name)
-);
\ No newline at end of file
+);
+
+CREATE TABLE IF NOT EXISTS Sessions (
+    session_id TEXT PRIMARY KEY,
+    user_name TEXT NOT NULL,
+    refresh_token_hash TEXT NOT NULL UNIQUE,
+    previous_refresh_token_hash TEXT,
+    created_at INTEGER NOT NULL,
+    expires_at INTEGER NOT NULL,
+    revoked_at INTEGER,
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+
+CREATE TABLE IF NOT EXISTS LoginThrottles (
+    kind TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    failed_count INTEGER NOT NULL,
+    last_failed_at INTEGER NOT NULL,
+    blocked_until INTEGER,
+    PRIMARY KEY (kind, subject)
+);
+
+CREATE TABLE IF NOT EXISTS OidcStates (
+    state TEXT PRIMARY KEY,
+    nonce TEXT NOT NULL,
+    code_verifier TEXT NOT NULL,
+    expires_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS TotpRecoveryCodes (
+    user_name TEXT NOT NULL,
+    code_hash TEXT NOT NULL,
+    used_at INTEGER,
+    PRIMARY KEY (user_name, code_hash),
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+
+CREATE TABLE IF NOT EXISTS TotpChallenges (
+    challenge_hash TEXT PRIMARY KEY,
+    user_name TEXT NOT NULL,
+    expires_at INTEGER NOT NULL,
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+
+CREATE TABLE IF NOT EXISTS ApiKeys (
+    key_id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    user_name TEXT NOT NULL,
+    scopes TEXT NOT NULL,
+    created_by TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    last_used_at INTEGER,
+    revoked_at INTEGER,
+    FOREIGN KEY(user_name) REFERENCES Users(user_name)
+);
+
+CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user ON PasswordResetTokens (user_name);
+CREATE INDEX IF NOT EXISTS idx_sessions_user ON Sessions (user_name);
+CREATE UNIQUE INDEX IF NOT EXISTS idx_users_oidc_subject ON Users (oidc_subject);
+CREATE INDEX IF NOT EXISTS idx_totp_challenges_user ON TotpChallenges (user_name);
+CREATE INDEX IF NOT EXISTS idx_users_verification_token ON Users (verification_token);
+CREATE INDEX IF NOT EXISTS idx_blacklist_user_time ON BlackList (user_name, start_time);
@@ -1,12 +1,25 @@
 use crate::{
-  database,
+  calendar, database, jwt,
   model::{constant::*, *},
+  oidc, two_factor,
   utils::*,
 };
 
-use bcrypt::{hash, verify, DEFAULT_COST};
-use rocket::{get, http::Status, post, serde::json::Json, State};
+use jsonwebtoken::jwk::JwkSet;
+use rocket::{
+  data::{Data, ToByteUnit},
+  delete,
+  form::Form,
+  fs::NamedFile,
+  get,
+  http::{ContentType, Status},
+  patch, post,
+  response::Redirect,
+  serde::json::Json,
+  State,
+};
 use sqlx::{Pool, Sqlite};
+use std::path::Path;
 use uuid::Uuid;
 use validator::Validate;
 
@@ -25,9 +38,9 @@ pub async fn register(
 
   log::info!("Handling registration for user: {}", user_name);
 
-  let password_hash = handle(hash(password, DEFAULT_COST), "Hashing password")?;
-  let verification_token = Uuid::new_v4().to_string();
-  let user_role = user::UserRole::RegularUser;
+  let password_hash = hash_password(&password)?;
+  let verification_token = Uuid::new_v4().simple().to_string();
+  let user_role = get_email_domain_role(&email);
   let verified = false;
 
   let user_info = user::UserInfo {
@@ -36,47 +49,61 @@ pub async fn register(
     password_hash: password_hash,
     user_role: user_role,
     verified: verified,
-    verification_token: verification_token.clone(),
+    verification_token: Some(hash_token(&verification_token)),
   };
 
   database::user::insert_new_user_info(pool.inner(), user_info).await?;
 
-  send_verification_email(&email, &verification_token)?;
-
-  let token = create_resend_verification_token(&email, &verification_token, false)
-    .map_err(|_| Status::InternalServerError)?;
+  // 寄信會阻塞，改在 blocking thread 執行，寄送失敗仍回傳錯誤
+  handle(
+    tokio::task::spawn_blocking(move || send_verification_email(&email, &verification_token)).await,
+    "Sending verification email",
+  )??;
 
   log::info!("Finished registration for user: {}", user_name);
 
-  Ok(token)
+  Ok("Registration successful, please check your email to verify your account.".to_string())
 }
 
-#[get("/api/resend_verification")]
+// 重新寄送驗證信
+/*
+不論 email 是否存在都回傳相同結果，避免洩漏使用者是否註冊
+同一 email 在 VERIFICATION_RESEND_COOLDOWN_SECONDS 內只會寄送一次，舊的驗證連結隨即失效
+*/
+#[post("/api/resend_verification", format = "json", data = "<request>")]
 pub async fn resend_verification_email(
-  claims: token::ResendVerificationClaim,
-) -> Result<String, Status> {
+  pool: &State<Pool<Sqlite>>,
+  request: Json<user::ResendVerificationRequest>,
+) -> Result<(), Status> {
   log::info!("Starting to process the resend verification email request");
 
-  let email = claims.email;
-  let verification_token = claims.verification_token;
-  let expiration = claims.expiration;
-  let now: i64 = get_now().timestamp();
+  handle_validator(request.validate())?;
 
-  // 超過規定時間才可以重發驗證郵件
-  if now <= expiration {
-    log::warn!("Not yet reached the time limit for resending verification email, request denied");
-    return Err(Status::BadRequest);
-  }
+  let verification_token = Uuid::new_v4().simple().to_string();
 
-  // 重發驗證信
-  send_verification_email(&email, &verification_token)?;
+  let email = database::user::renew_verification_token(
+    pool.inner(),
+    &request.email,
+    &hash_token(&verification_token),
+  )
+  .await?;
 
-  // 重新生成JWT token(is_resend設定為true) -> 重新計算重發驗證信的冷卻時間
-  let token = create_resend_verification_token(&email, &verification_token, true)
-    .map_err(|_| Status::InternalServerError)?;
+  match email {
+    Some(email) => {
+      tokio::task::spawn_blocking(move || {
+        if send_verification_email(&email, &verification_token).is_err() {
+          log::error!("Failed to send verification email");
+        }
+      });
 
-  log::info!("Resend verification email to address: {}", &email);
-  Ok(token)
+      log::info!("Resending verification email");
+    }
+    None => {
+      log::warn!("No pending verification for the email, or resent too recently");
+    }
+  }
+
+  Ok(())
 }
 
 #[get("/api/verify?<verification_token>")]
@@ -84,442 +111,2006 @@ pub async fn email_verify(
   pool: &State<Pool<Sqlite>>,
   verification_token: String,
 ) -> Result<String, Status> {
-  log::info!("Verifying email for token: {}", verification_token);
+  log::info!("Verifying email");
 
-  database::user::update_user_verified_by_token(pool.inner(), &verification_token).await?;
+  database::user::update_user_verified_by_token(pool.inner(), &hash_token(&verification_token))
+    .await?;
 
-  log::info!(
-    "Email verification completed for token: {}",
-    verification_token
-  );
+  log::info!("Email verification completed");
 
   Ok("Your email has been successfully verified.".to_string())
 }
 
 // 登入
+/*
+同一帳號或 IP 連續登入失敗時，延遲或鎖定期間內直接回傳 429，不進行密碼驗證
+帳號達到鎖定次數時寄信通知帳號擁有者
+*/
 #[post("/api/login", data = "<creds>")]
 pub async fn login(
   pool: &State<Pool<Sqlite>>,
+  client_ip: token::ClientIp,
   creds: Json<user::LoginRequest>,
-) -> Result<String, Status> {
+) -> Result<Json<token::LoginResponse>, token::LoginError> {
   handle_validator(creds.validate())?;
-  let user_info = database::user::get_user_info(pool.inner(), &creds.user_name).await?;
+
+  let client_ip = client_ip.0.map(|ip| ip.to_string());
+
+  if let Some(ip) = &client_ip {
+    let blocked_until =
+      database::login_throttle::get_blocked_until(pool.inner(), user::LoginThrottleKind::Ip, ip)
+        .await?;
+
+    if let Some(blocked_until) = blocked_until {
+      log::warn!("Login from IP: {} is throttled until {}", ip, blocked_until);
+      return Err(Status::TooManyRequests.into());
+    }
+  }
+
+  let blocked_until = database::login_throttle::get_blocked_until(
+    pool.inner(),
+    user::LoginThrottleKind::Account,
+    &creds.user_name,
+  )
+  .await?;
+
+  if let Some(blocked_until) = blocked_until {
+    log::warn!(
+      "Login for user: {} is throttled until {}",
+      creds.user_name,
+      blocked_until
+    );
+    return Err(Status::TooManyRequests.into());
+  }
+
+  let user_info = match database::user::get_user_info(pool.inner(), &creds.user_name).await {
+    Ok(user_info) => user_info,
+    Err(status) => {
+      // 不存在的帳號只以 IP 計算失敗次數，避免以任意使用者名稱寫入大量紀錄
+      if status == Status::NotFound {
+        if let Some(ip) = &client_ip {
+          database::login_throttle::record_login_failure(
+            pool.inner(),
+            user::LoginThrottleKind::Ip,
+            ip,
+            LOGIN_IP_BACKOFF_FREE_ATTEMPTS,
+            None,
+          )
+          .await?;
+        }
+      }
+
+      return Err(status.into());
+    }
+  };
 
   log::info!(
     "Processing the login request for user: {}",
     &user_info.user_name
   );
 
-  let password_matches =
-    verify(&creds.password, &user_info.password_hash).map_err(|_| Status::InternalServerError)?;
+  // 只以 OIDC 登入或已刪除的帳號沒有密碼
+  let password_matches = !user_info.password_hash.is_empty()
+    && verify_password_hash(&creds.password, &user_info.password_hash)?;
 
   if !password_matches {
     log::warn!("Password is incorrect");
-    return Err(Status::Unauthorized);
+
+    if let Some(ip) = &client_ip {
+      database::login_throttle::record_login_failure(
+        pool.inner(),
+        user::LoginThrottleKind::Ip,
+        ip,
+        LOGIN_IP_BACKOFF_FREE_ATTEMPTS,
+        None,
+      )
+      .await?;
+    }
+
+    let failure = database::login_throttle::record_login_failure(
+      pool.inner(),
+      user::LoginThrottleKind::Account,
+      &user_info.user_name,
+      LOGIN_BACKOFF_FREE_ATTEMPTS,
+      Some(LOGIN_LOCKOUT_FAILURES),
+    )
+    .await?;
+
+    if let (true, Some(locked_until)) = (failure.locked, failure.blocked_until) {
+      log::warn!(
+        "User: {} is locked after {} failed login attempts",
+        user_info.user_name,
+        failure.failed_count
+      );
+
+      let email = user_info.email;
+      let user_name = user_info.user_name;
+
+      tokio::task::spawn_blocking(move || {
+        if send_account_locked_email(&email, &user_name, locked_until).is_err() {
+          log::error!("Failed to send account locked email");
+        }
+      });
+    }
+
+    return Err(Status::Unauthorized.into());
+  }
+
+  database::login_throttle::clear_login_failures(
+    pool.inner(),
+    user::LoginThrottleKind::Account,
+    &user_info.user_name,
+  )
+  .await?;
+
+  // 舊版 bcrypt 或以舊參數雜湊的密碼，登入成功時以目前的設定重新雜湊
+  if password_needs_rehash(&user_info.password_hash) {
+    let password_hash = hash_password(&creds.password)?;
+
+    database::user::rehash_user_password(
+      pool.inner(),
+      &user_info.user_name,
+      &user_info.password_hash,
+      &password_hash,
+    )
+    .await?;
+
+    log::info!("Rehashed password for user: {}", user_info.user_name);
   }
 
   if !&user_info.verified {
     log::warn!("The user's email has not been verified");
-    return Err(Status::BadRequest);
+    return Err(Status::BadRequest.into());
   }
 
-  let is_in_blacklist =
-    database::user::is_user_in_blacklist(pool.inner(), &user_info.user_name).await?;
+  // 停權中的帳號回傳停權的結束時間與原因
+  let ban = database::user::get_active_ban(pool.inner(), &user_info.user_name).await?;
 
-  if is_in_blacklist {
+  if let Some(ban) = ban {
     log::warn!(
       "User '{}' is currently in the blacklist.",
       &user_info.user_name
     );
-    return Err(Status::Forbidden);
+    return Err(token::LoginError::Banned(Json(ban)));
   }
 
-  let token = create_userinfo_token(&user_info.user_name, user_info.user_role)
-    .map_err(|_| Status::InternalServerError)?;
+  let login_response =
+    two_factor::start_login(pool.inner(), &user_info.user_name, user_info.user_role).await?;
 
-  log::info!("User: {} login successful", user_info.user_name);
+  log::info!("User: {} passed password verification", user_info.user_name);
 
-  Ok(token)
+  Ok(Json(login_response))
 }
 
-// 查詢當前所有位置狀態
+// 登入的第二步驟，以驗證器 App 的驗證碼或備用碼完成登入
 /*
-如果座位(Seats)不可用，則該座位的狀態為Unavailable
-如果特定時間被包含在某筆預約中，則則該座位的狀態為Borrowed
-否則為Available
+驗證碼錯誤時與密碼錯誤相同，計入帳號的登入失敗次數
 */
-#[get("/api/show_status")]
-pub async fn show_current_seats_status(pool: &State<Pool<Sqlite>>) -> Result<String, Status> {
-  log::info!("Show current seats status");
+#[post("/api/login/totp", format = "json", data = "<request>")]
+pub async fn login_totp(
+  pool: &State<Pool<Sqlite>>,
+  request: Json<totp::TotpLoginRequest>,
+) -> Result<Json<token::TokenPair>, Status> {
+  log::info!("Handling TOTP login request");
 
-  let now: i64 = get_now().timestamp();
-  let all_seats_status: seat::AllSeatsStatus;
+  handle_validator(request.validate())?;
 
-  if database::timeslot::is_within_unavailable_timeslot(pool.inner(), now).await? {
+  let challenge_hash = hash_token(&request.challenge);
+  let (user_name, user_role) =
+    database::totp::get_totp_challenge_user(pool.inner(), &challenge_hash).await?;
+
+  let blocked_until = database::login_throttle::get_blocked_until(
+    pool.inner(),
+    user::LoginThrottleKind::Account,
+    &user_name,
+  )
+  .await?;
+
+  if let Some(blocked_until) = blocked_until {
     log::warn!(
-      "The time: {} is within an unavailable timeslot",
-      time_to_string(now)?
+      "Login for user: {} is throttled until {}",
+      user_name,
+      blocked_until
     );
+    return Err(Status::TooManyRequests);
+  }
 
-    let mut seats_vec = Vec::<seat::SeatStatus>::new();
-
-    for seat in 1..NUMBER_OF_SEATS {
-      seats_vec.push(seat::SeatStatus {
-        seat_id: seat,
-        status: seat::Status::Unavailable,
-      })
-    }
-    all_seats_status = seat::AllSeatsStatus { seats: seats_vec };
-  } else {
-    all_seats_status = database::seat::get_all_seats_status(pool.inner(), now).await?;
+  if database::user::is_user_disabled(pool.inner(), &user_name).await? {
+    log::warn!("The account of user: {} is disabled", user_name);
+    return Err(Status::Forbidden);
   }
 
-  let json = handle(
-    serde_json::to_string(&all_seats_status),
-    "Serialize the data as a String of JSON",
-  )?;
+  if !two_factor::verify_second_factor(pool.inner(), &user_name, &request.code).await? {
+    log::warn!("TOTP code is incorrect for user: {}", user_name);
 
-  log::info!("Show current seats status successfully");
+    database::login_throttle::record_login_failure(
+      pool.inner(),
+      user::LoginThrottleKind::Account,
+      &user_name,
+      LOGIN_BACKOFF_FREE_ATTEMPTS,
+      Some(LOGIN_LOCKOUT_FAILURES),
+    )
+    .await?;
 
-  Ok(json)
-}
+    return Err(Status::Unauthorized);
+  }
 
-// 查詢當前所有位置狀態 + filter
-/*
-如果給定的時間段中有重疊到被預約的時段則回傳
-Borrowed
-否則回傳
-Available
-*/
-#[get("/api/show_status/<start_time>/<end_time>")]
-pub async fn show_seats_status_in_specific_timeslots(
-  pool: &State<Pool<Sqlite>>,
-  start_time: i64,
-  end_time: i64,
-) -> Result<String, Status> {
-  log::info!("Show seats status by time");
-  validate_datetime(start_time, end_time)?;
+  // 同一個 challenge 同時送出多次時，只有一次能完成登入
+  if !database::totp::delete_totp_challenge(pool.inner(), &challenge_hash).await? {
+    log::warn!("The TOTP challenge has already been used");
+    return Err(Status::Unauthorized);
+  }
 
-  let all_seats_status =
-    database::seat::get_seats_status_in_specific_timeslots(pool.inner(), start_time, end_time)
-      .await?;
+  database::login_throttle::clear_login_failures(
+    pool.inner(),
+    user::LoginThrottleKind::Account,
+    &user_name,
+  )
+  .await?;
 
-  let json = handle(
-    serde_json::to_string(&all_seats_status),
-    "Serialize the data as a String of JSON",
-  )?;
+  let token_pair = database::session::create_session(pool.inner(), &user_name, user_role).await?;
 
-  log::info!("Show seats status by time successfully");
+  log::info!("User: {} login successful", user_name);
 
-  Ok(json)
+  Ok(Json(token_pair))
 }
 
-// 查詢當前特定位置預約狀態
-#[get("/api/show_reservations/<seat_id>/<start_time>/<end_time>")]
-pub async fn show_seat_reservations(
+// 以 refresh token 換發新的 access token 與 refresh token
+#[post("/api/refresh", format = "json", data = "<request>")]
+pub async fn refresh_token(
   pool: &State<Pool<Sqlite>>,
-  seat_id: u16,
-  start_time: i64,
-  end_time: i64,
-) -> Result<String, Status> {
-  log::info!("Show reservations of seat: {}", seat_id);
+  request: Json<token::RefreshRequest>,
+) -> Result<Json<token::TokenPair>, Status> {
+  log::info!("Handling refresh token request");
 
-  validate_seat_id(seat_id)?;
+  handle_validator(request.validate())?;
 
-  let timeslots =
-    database::seat::get_seat_reservations(pool.inner(), start_time, end_time, seat_id).await?;
+  let refresh_token = Uuid::new_v4().simple().to_string();
 
-  let json = handle(
-    serde_json::to_string(&timeslots),
-    "Serialize the data as a String of JSON",
-  )?;
+  let (session_id, user_name, user_role) = database::session::rotate_refresh_token(
+    pool.inner(),
+    &hash_token(&request.refresh_token),
+    &hash_token(&refresh_token),
+  )
+  .await?;
 
-  log::info!("Show reservations of seat: {} successfully", seat_id);
+  if database::user::is_user_in_blacklist(pool.inner(), &user_name).await? {
+    log::warn!("User '{}' is currently in the blacklist.", user_name);
+    return Err(Status::Forbidden);
+  }
 
-  Ok(json)
+  let access_token = create_userinfo_token(&user_name, user_role, &session_id)?;
+
+  log::info!("Refreshed tokens for user: {}", user_name);
+
+  Ok(Json(token::TokenPair {
+    access_token,
+    refresh_token,
+    expires_in: ACCESS_TOKEN_MINUTES * 60,
+  }))
 }
 
-// 預約座位
-#[post("/api/reserve", format = "json", data = "<insert_reservation>")]
-pub async fn reserve_seat(
+// 登出，撤銷目前的 session
+#[post("/api/logout")]
+pub async fn logout(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-  insert_reservation: Json<reservation::InsertReservationRequest>,
 ) -> Result<(), Status> {
-  handle_validator(insert_reservation.validate())?;
-
-  let data: reservation::InsertReservationRequest = insert_reservation.into_inner();
-  let seat_id = data.seat_id;
-  let start_time = data.start_time;
-  let end_time = data.end_time;
-  let user_name = claims.user;
+  log::info!("Logging out user: {}", claims.user);
 
-  log::info!("Reserving a seat :{} for user: {}", seat_id, user_name);
-
-  if !database::seat::is_seat_available(pool.inner(), seat_id).await? {
-    log::warn!("The seat: {} is unavailable", seat_id);
-    return Err(Status::BadRequest);
-  }
+  database::session::revoke_session(pool.inner(), &claims.sid).await?;
 
-  if database::timeslot::is_overlapping_with_unavailable_timeslot(
-    pool.inner(),
-    start_time,
-    end_time,
-  )
-  .await?
-  {
-    log::warn!(
-      "The start_time: {} end_time: {} is overlapping unavailable timeslot",
-      start_time,
-      end_time
-    );
-    return Err(Status::BadRequest);
-  }
+  log::info!("User: {} logged out successfully", claims.user);
 
-  let date = timestamp_to_naive_datetime(start_time)?.date();
+  Ok(())
+}
 
-  if database::reservation::check_unfinished_reservations(pool.inner(), &user_name, date).await? {
-    log::warn!("Found ongoing reservation not yet completed");
-    return Err(Status::BadRequest);
-  }
+// 登出所有裝置，撤銷使用者所有的 session
+#[post("/api/logout_all")]
+pub async fn logout_all(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+) -> Result<(), Status> {
+  log::info!("Logging out all sessions of user: {}", claims.user);
 
-  database::reservation::reserve_seat(pool.inner(), &user_name, seat_id, start_time, end_time)
-    .await?;
+  let revoked = database::session::revoke_user_sessions(pool.inner(), &claims.user, None).await?;
 
   log::info!(
-    "Seat: {} reserved successfully for user: {}",
-    seat_id,
-    user_name
+    "Revoked {} sessions of user: {} successfully",
+    revoked,
+    claims.user
   );
 
   Ok(())
 }
 
-// 修改預約時段
-#[post(
-  "/api/update_reservation",
-  format = "json",
-  data = "<update_reservation>"
-)]
-pub async fn update_reservation(
+// OIDC 登入，導向身分提供者的授權頁面
+/*
+state 防止 CSRF，nonce 綁定 id_token，並使用 PKCE 保護授權碼
+未設定 OIDC 時回傳 404
+*/
+#[get("/api/oidc/login")]
+pub async fn oidc_login(
   pool: &State<Pool<Sqlite>>,
-  claims: token::UserInfoClaim,
-  update_reservation: Json<reservation::UpdateReservationRequest>,
-) -> Result<(), Status> {
-  handle_validator(update_reservation.validate())?;
+  oidc_config: &State<Option<oidc::OidcConfig>>,
+) -> Result<Redirect, Status> {
+  log::info!("Handling OIDC login request");
 
-  let data: reservation::UpdateReservationRequest = update_reservation.into_inner();
-  let start_time = data.start_time;
-  let end_time = data.end_time;
-  let new_start_time = data.new_start_time;
-  let new_end_time = data.new_end_time;
-  let user_name = claims.user;
+  let config = oidc_config.as_ref().ok_or_else(|| {
+    log::warn!("OIDC login is not configured");
+    Status::NotFound
+  })?;
 
-  log::info!("Updating reservation for user: {}", user_name);
+  let state = Uuid::new_v4().simple().to_string();
+  let nonce = Uuid::new_v4().simple().to_string();
+  let code_verifier = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+  let expires_at = get_now_timestamp() + OIDC_STATE_MINUTES * 60;
 
-  if database::timeslot::is_overlapping_with_unavailable_timeslot(
-    pool.inner(),
-    new_start_time,
-    new_end_time,
-  )
-  .await?
-  {
-    return Err(Status::BadRequest);
-  }
+  database::oidc::insert_oidc_state(pool.inner(), &state, &nonce, &code_verifier, expires_at)
+    .await?;
 
-  database::reservation::update_reservation_time(
-    pool.inner(),
-    &user_name,
-    start_time,
-    end_time,
-    new_start_time,
-    new_end_time,
+  let authorization_url = oidc::build_authorization_url(
+    config,
+    &state,
+    &nonce,
+    &oidc::create_code_challenge(&code_verifier),
   )
   .await?;
 
-  log::info!("Reservation for user: {} updated successfully", user_name);
+  log::info!("Redirecting to the OIDC provider");
 
-  Ok(())
+  Ok(Redirect::to(authorization_url))
 }
 
-// 刪除預約時段
-#[post(
-  "/api/delete_reservation",
-  format = "json",
-  data = "<delete_reservation>"
-)]
-pub async fn delete_reservation_time(
+// OIDC 登入的回呼，以授權碼換取 id_token 後登入，首次登入時自動建立帳號
+#[get("/api/oidc/callback?<code>&<state>&<error>")]
+pub async fn oidc_callback(
   pool: &State<Pool<Sqlite>>,
-  claims: token::UserInfoClaim,
-  delete_reservation: Json<reservation::DeleteReservationRequest>,
-) -> Result<(), Status> {
-  handle_validator(delete_reservation.validate())?;
+  oidc_config: &State<Option<oidc::OidcConfig>>,
+  code: Option<String>,
+  state: Option<String>,
+  error: Option<String>,
+) -> Result<Json<token::LoginResponse>, Status> {
+  log::info!("Handling OIDC callback");
+
+  let config = oidc_config.as_ref().ok_or_else(|| {
+    log::warn!("OIDC login is not configured");
+    Status::NotFound
+  })?;
+
+  if let Some(error) = error {
+    log::warn!("The OIDC provider returned an error: {}", error);
+    return Err(Status::Unauthorized);
+  }
 
-  let data: reservation::DeleteReservationRequest = delete_reservation.into_inner();
-  let start_time = data.start_time;
-  let end_time = data.end_time;
-  let user_name = claims.user;
+  let (code, state) = match (code, state) {
+    (Some(code), Some(state)) => (code, state),
+    _ => {
+      log::warn!("The OIDC callback is missing code or state");
+      return Err(Status::BadRequest);
+    }
+  };
 
-  log::info!("Deleting reservation for user: {}", user_name);
+  let (nonce, code_verifier) = database::oidc::take_oidc_state(pool.inner(), &state).await?;
 
-  database::reservation::delete_reservation_time(pool.inner(), &user_name, start_time, end_time)
-    .await?;
+  let claims = oidc::exchange_code(config, &code, &code_verifier, &nonce).await?;
 
-  log::info!("Reservation for user: {} deleted successfully", user_name);
+  let user_info = oidc::find_or_create_user(pool.inner(), &claims).await?;
+
+  if database::user::is_user_in_blacklist(pool.inner(), &user_info.user_name).await? {
+    log::warn!(
+      "User '{}' is currently in the blacklist.",
+      &user_info.user_name
+    );
+    return Err(Status::Forbidden);
+  }
+
+  let login_response =
+    two_factor::start_login(pool.inner(), &user_info.user_name, user_info.user_role).await?;
+
+  log::info!("User: {} passed OIDC verification", user_info.user_name);
+
+  Ok(Json(login_response))
+}
+
+// 公開驗證 access token 用的公鑰，供校內其他服務驗證本系統簽發的 token
+#[get("/.well-known/jwks.json")]
+pub async fn show_jwks() -> Json<JwkSet> {
+  log::info!("Showing JWKS");
+
+  Json(jwt::get_jwks())
+}
+
+// 忘記密碼
+/*
+不論 email 是否存在都回傳相同結果，避免洩漏使用者是否註冊
+郵件在背景寄送，回應時間也不會因 email 是否存在而不同
+*/
+#[post("/api/password/forgot", format = "json", data = "<request>")]
+pub async fn forgot_password(
+  pool: &State<Pool<Sqlite>>,
+  request: Json<user::ForgotPasswordRequest>,
+) -> Result<(), Status> {
+  log::info!("Handling forgot password request");
+
+  handle_validator(request.validate())?;
+
+  let email = request.into_inner().email;
+
+  let user_name = match database::user::get_user_name_by_email(pool.inner(), &email).await? {
+    Some(user_name) => user_name,
+    None => {
+      log::warn!("No user found for the forgot password request");
+      return Ok(());
+    }
+  };
+
+  let reset_token = Uuid::new_v4().simple().to_string();
+  let expires_at = get_now_timestamp() + PASSWORD_RESET_TOKEN_MINUTES * 60;
+
+  database::user::insert_password_reset_token(
+    pool.inner(),
+    &user_name,
+    &hash_token(&reset_token),
+    expires_at,
+  )
+  .await?;
+
+  tokio::task::spawn_blocking(move || {
+    if send_password_reset_email(&email, &reset_token).is_err() {
+      log::error!("Failed to send password reset email");
+    }
+  });
+
+  log::info!("Issued password reset token for user: {}", user_name);
 
   Ok(())
 }
 
-// 顯示使用者預約時段
-#[get("/api/user_reservations")]
-pub async fn display_user_reservations(
+// 重設密碼
+#[post("/api/password/reset", format = "json", data = "<request>")]
+pub async fn reset_password(
+  pool: &State<Pool<Sqlite>>,
+  request: Json<user::ResetPasswordRequest>,
+) -> Result<(), Status> {
+  log::info!("Handling reset password request");
+
+  handle_validator(request.validate())?;
+
+  let request = request.into_inner();
+  let password_hash = hash_password(&request.new_password)?;
+
+  let user_name = database::user::reset_password_by_token(
+    pool.inner(),
+    &hash_token(&request.token),
+    &password_hash,
+  )
+  .await?;
+
+  // 重設密碼後，所有已登入的裝置都需要重新登入
+  database::session::revoke_user_sessions(pool.inner(), &user_name, None).await?;
+
+  log::info!("Successfully reset password for user: {}", user_name);
+
+  Ok(())
+}
+
+// 查詢自己的帳號資訊
+#[get("/api/me")]
+pub async fn show_user_profile(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-) -> Result<Json<Vec<reservation::Reservation>>, Status> {
-  let user_name = claims.user;
+) -> Result<Json<user::UserProfile>, Status> {
+  log::info!("Showing profile for user: {}", claims.user);
 
-  log::info!("Displaying the user's reservations");
+  let profile = database::user::get_user_profile(pool.inner(), &claims.user).await?;
 
-  let reservations = database::reservation::get_user_reservations(pool.inner(), &user_name).await?;
+  log::info!("Showing profile for user: {} successfully", claims.user);
 
-  log::info!("Displaying the user's reservations successfully");
+  Ok(Json(profile))
+}
 
-  Ok(Json(reservations))
+// 變更密碼，需要提供目前的密碼
+#[patch("/api/me/password", format = "json", data = "<request>")]
+pub async fn change_password(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+  request: Json<user::ChangePasswordRequest>,
+) -> Result<(), Status> {
+  log::info!("Changing password for user: {}", claims.user);
+
+  handle_validator(request.validate())?;
+
+  let request = request.into_inner();
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  verify_password(&request.current_password, &user_info.password_hash)?;
+
+  let password_hash = hash_password(&request.new_password)?;
+  database::user::update_user_password(pool.inner(), &claims.user, &password_hash).await?;
+
+  // 變更密碼後，除了目前的裝置外都需要重新登入
+  database::session::revoke_user_sessions(pool.inner(), &claims.user, Some(&claims.sid)).await?;
+
+  log::info!("Changed password for user: {} successfully", claims.user);
+
+  Ok(())
 }
 
-// 設定不可預約時間
-#[post("/api/set_timeslots", format = "json", data = "<time_slot>")]
-pub async fn set_unavailable_timeslots(
+// 變更 email
+/*
+新的 email 先暫存為 pending_email，並寄送驗證信到新的 email
+使用者點擊驗證連結後才會取代原本的 email
+*/
+#[patch("/api/me/email", format = "json", data = "<request>")]
+pub async fn change_email(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-  time_slot: Json<timeslot::TimeSlot>,
+  request: Json<user::ChangeEmailRequest>,
 ) -> Result<(), Status> {
-  handle_validator(time_slot.validate())?;
+  log::info!("Changing email for user: {}", claims.user);
 
-  let user_name = claims.user;
-  if claims.role != user::UserRole::Admin {
-    log::warn!(
-      "Unauthorized attempt to set unavailable timeslots by user: {}",
-      &user_name
-    );
-    return Err(Status::Unauthorized);
+  handle_validator(request.validate())?;
+
+  let request = request.into_inner();
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  let session_created_at =
+    database::session::get_session_created_at(pool.inner(), &claims.sid).await?;
+  verify_reauthentication(
+    &request.password,
+    &user_info.password_hash,
+    session_created_at,
+  )?;
+
+  if database::user::get_user_name_by_email(pool.inner(), &request.new_email)
+    .await?
+    .is_some()
+  {
+    log::warn!("The email is already in use");
+    return Err(Status::Conflict);
   }
 
-  let start_time = time_slot.start_time;
-  let end_time = time_slot.end_time;
+  let verification_token = Uuid::new_v4().simple().to_string();
+  database::user::update_user_pending_email(
+    pool.inner(),
+    &claims.user,
+    &request.new_email,
+    &hash_token(&verification_token),
+  )
+  .await?;
+
+  let new_email = request.new_email;
+  handle(
+    tokio::task::spawn_blocking(move || send_verification_email(&new_email, &verification_token))
+      .await,
+    "Sending verification email",
+  )??;
 
   log::info!(
-    "Setting unavailable timeslot start_time: {:?}, end_time: {:?}",
-    start_time,
-    end_time
+    "Sent verification email for the new email of user: {}",
+    claims.user
   );
 
-  database::timeslot::insert_unavailable_timeslot(pool.inner(), start_time, end_time).await?;
-
-  log::info!("Unavailable timeslot set successfully");
   Ok(())
 }
 
-// 設定不可使用座位
-#[post(
-  "/api/set_seat_availability",
-  format = "json",
-  data = "<seat_availability>"
-)]
-pub async fn set_seat_availability(
+// 變更使用者名稱，回傳使用新名稱的 access token，refresh token 不變
+#[patch("/api/me/user_name", format = "json", data = "<request>")]
+pub async fn change_user_name(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-  seat_availability: Json<seat::SeatAvailabilityRequest>,
-) -> Result<(), Status> {
-  handle_validator(seat_availability.validate())?;
+  request: Json<user::ChangeUserNameRequest>,
+) -> Result<String, Status> {
+  log::info!("Changing user name for user: {}", claims.user);
 
-  let user_name = claims.user;
-  if claims.role != user::UserRole::Admin {
-    log::warn!(
-      "Unauthorized attempt to set seat availability by user: {}",
-      &user_name
-    );
-    return Err(Status::Unauthorized);
-  }
+  handle_validator(request.validate())?;
 
-  let seat_id = seat_availability.seat_id;
-  let available = seat_availability.available;
+  let request = request.into_inner();
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  let session_created_at =
+    database::session::get_session_created_at(pool.inner(), &claims.sid).await?;
+  verify_reauthentication(
+    &request.password,
+    &user_info.password_hash,
+    session_created_at,
+  )?;
+
+  database::user::update_user_name(pool.inner(), &claims.user, &request.new_user_name).await?;
+
+  let token = create_userinfo_token(&request.new_user_name, user_info.user_role, &claims.sid)?;
 
   log::info!(
-    "Setting seat availability seat_id: {:?}, available: {:?}",
-    seat_id,
-    available
+    "Changed user name from: {} to: {} successfully",
+    claims.user,
+    request.new_user_name
   );
 
-  database::seat::update_seat_availability(pool.inner(), seat_id, available).await?;
+  Ok(token)
+}
 
-  log::info!("Seat availability set successfully");
-  Ok(())
+// 匯出自己的個人資料，包含預約、停權與登入紀錄
+#[get("/api/me/export")]
+pub async fn export_user_data(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+) -> Result<Json<user::UserDataExport>, Status> {
+  log::info!("Exporting data of user: {}", claims.user);
+
+  let pool = pool.inner();
+  let user_name = &claims.user;
+
+  let export = user::UserDataExport {
+    exported_at: get_now_timestamp(),
+    profile: database::user::get_user_profile(pool, user_name).await?,
+    reservations: database::reservation::get_user_reservation_events(pool, user_name, i64::MIN)
+      .await?,
+    bans: database::user::get_user_bans(pool, user_name).await?,
+    seat_issues: database::issue::get_user_seat_issues(pool, user_name).await?,
+    sessions: database::session::get_user_sessions(pool, user_name).await?,
+    api_keys: database::api_key::get_user_api_keys(pool, user_name).await?,
+    issued_bans: database::user::get_bans_issued_by_user(pool, user_name).await?,
+  };
+
+  log::info!("Exported data of user: {} successfully", claims.user);
+
+  Ok(Json(export))
 }
 
-// 設定黑名單
-#[post("/api/set_blacklist", format = "json", data = "<ban_request>")]
-pub async fn add_user_to_blacklist(
+// 刪除帳號，需要提供目前的密碼
+/*
+預約統計需要保留，因此不直接刪除 Users，而是將帳號匿名化
+*/
+#[delete("/api/me", format = "json", data = "<request>")]
+pub async fn delete_account(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-  ban_request: Json<user::BanRequest>,
+  request: Json<user::DeleteAccountRequest>,
 ) -> Result<(), Status> {
-  handle_validator(ban_request.validate())?;
+  log::info!("Deleting account of user: {}", claims.user);
 
-  let user_name = claims.user;
-  if claims.role != user::UserRole::Admin {
-    log::warn!(
-      "Unauthorized attempt to add user to blacklist by user: {}",
-      &user_name
-    );
-    return Err(Status::Unauthorized);
-  }
+  handle_validator(request.validate())?;
 
-  let user_name_to_ban = &ban_request.user_name;
-  let start_time = ban_request.start_time;
-  let end_time = ban_request.end_time;
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  let session_created_at =
+    database::session::get_session_created_at(pool.inner(), &claims.sid).await?;
+  verify_reauthentication(
+    &request.password,
+    &user_info.password_hash,
+    session_created_at,
+  )?;
+
+  let anonymous_name = format!("deleted_{}", Uuid::new_v4().simple());
+  database::user::anonymize_user(pool.inner(), &claims.user, &anonymous_name).await?;
 
   log::info!(
-    "Adding user to blacklist with start_time: {:?}, end_time: {:?}",
-    start_time,
-    end_time
+    "Deleted account of user: {}, anonymized as: {}",
+    claims.user,
+    anonymous_name
   );
 
-  let is_user_in_blacklist =
-    database::user::is_user_in_blacklist(pool.inner(), user_name_to_ban).await?;
-  if is_user_in_blacklist {
-    database::user::delete_user_from_blacklist(pool.inner(), user_name_to_ban).await?;
+  Ok(())
+}
+
+// 開始設定兩步驟驗證，回傳密鑰與供驗證器 App 掃描的 URI
+/*
+密鑰在以驗證碼確認前不會啟用，重新呼叫會產生新的密鑰
+*/
+#[post("/api/me/totp/setup", format = "json", data = "<request>")]
+pub async fn setup_totp(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+  request: Json<totp::TotpSetupRequest>,
+) -> Result<Json<totp::TotpSetup>, Status> {
+  log::info!("Setting up TOTP for user: {}", claims.user);
+
+  handle_validator(request.validate())?;
+
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  let session_created_at =
+    database::session::get_session_created_at(pool.inner(), &claims.sid).await?;
+  verify_reauthentication(
+    &request.password,
+    &user_info.password_hash,
+    session_created_at,
+  )?;
+
+  let secret = two_factor::generate_secret();
+  database::totp::set_totp_secret(pool.inner(), &claims.user, &secret).await?;
+
+  let provisioning_uri = two_factor::get_provisioning_uri(&claims.user, &secret)?;
+
+  log::info!("Created TOTP secret for user: {} successfully", claims.user);
+
+  Ok(Json(totp::TotpSetup {
+    secret,
+    provisioning_uri,
+  }))
+}
+
+// 以驗證碼確認後啟用兩步驟驗證，回傳備用碼
+/*
+啟用後所有 session 都會被撤銷，需重新以兩步驟驗證登入
+*/
+#[post("/api/me/totp/enable", format = "json", data = "<request>")]
+pub async fn enable_totp(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+  request: Json<totp::TotpCodeRequest>,
+) -> Result<Json<totp::TotpRecoveryCodes>, Status> {
+  log::info!("Enabling TOTP for user: {}", claims.user);
+
+  handle_validator(request.validate())?;
+
+  let (secret, enabled) = database::totp::get_totp_secret(pool.inner(), &claims.user).await?;
+
+  let secret = match (secret, enabled) {
+    (Some(secret), false) => secret,
+    (_, true) => {
+      log::warn!("TOTP is already enabled for user: {}", claims.user);
+      return Err(Status::Conflict);
+    }
+    (None, false) => {
+      log::warn!("TOTP has not been set up for user: {}", claims.user);
+      return Err(Status::BadRequest);
+    }
+  };
+
+  let now = get_now_timestamp();
+  let step = two_factor::verify_code(&secret, &request.code, now).ok_or_else(|| {
+    log::warn!("TOTP code is incorrect for user: {}", claims.user);
+    Status::Unauthorized
+  })?;
+
+  let recovery_codes = two_factor::generate_recovery_codes();
+  let code_hashes: Vec<String> = recovery_codes
+    .iter()
+    .map(|code| two_factor::hash_recovery_code(code))
+    .collect();
+
+  database::totp::enable_totp(pool.inner(), &claims.user, step, &code_hashes).await?;
+  database::session::revoke_user_sessions(pool.inner(), &claims.user, None).await?;
+
+  log::info!("Enabled TOTP for user: {} successfully", claims.user);
+
+  Ok(Json(totp::TotpRecoveryCodes { recovery_codes }))
+}
+
+// 重新產生備用碼，舊的備用碼全部失效
+#[post("/api/me/totp/recovery_codes", format = "json", data = "<request>")]
+pub async fn regenerate_totp_recovery_codes(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+  request: Json<totp::TotpCodeRequest>,
+) -> Result<Json<totp::TotpRecoveryCodes>, Status> {
+  log::info!("Regenerating TOTP recovery codes for user: {}", claims.user);
+
+  handle_validator(request.validate())?;
+
+  if !two_factor::verify_second_factor(pool.inner(), &claims.user, &request.code).await? {
+    log::warn!("TOTP code is incorrect for user: {}", claims.user);
+    return Err(Status::Unauthorized);
   }
 
-  database::user::insert_user_to_blacklist(pool.inner(), user_name_to_ban, start_time, end_time)
-    .await?;
+  let recovery_codes = two_factor::generate_recovery_codes();
+  let code_hashes: Vec<String> = recovery_codes
+    .iter()
+    .map(|code| two_factor::hash_recovery_code(code))
+    .collect();
 
-  log::info!("Add user to blacklist successfully");
-  Ok(())
+  database::totp::reset_recovery_codes(pool.inner(), &claims.user, &code_hashes).await?;
+
+  log::info!(
+    "Regenerated TOTP recovery codes for user: {} successfully",
+    claims.user
+  );
+
+  Ok(Json(totp::TotpRecoveryCodes { recovery_codes }))
 }
 
-// 將使用者移除黑名單
-#[post("/api/remove_blacklist", format = "json", data = "<unban_request>")]
-pub async fn remove_user_from_blacklist(
+// 停用兩步驟驗證，需要提供密碼與驗證碼(或備用碼)
+#[delete("/api/me/totp", format = "json", data = "<request>")]
+pub async fn disable_totp(
   pool: &State<Pool<Sqlite>>,
   claims: token::UserInfoClaim,
-  unban_request: Json<user::UnBanRequest>,
+  request: Json<totp::DisableTotpRequest>,
 ) -> Result<(), Status> {
-  let user_name = claims.user;
-  if claims.role != user::UserRole::Admin {
-    log::warn!(
-      "Unauthorized attempt to remove user from blacklist by user: {}",
-      &user_name
-    );
+  log::info!("Disabling TOTP for user: {}", claims.user);
+
+  handle_validator(request.validate())?;
+
+  let user_info = database::user::get_user_info(pool.inner(), &claims.user).await?;
+
+  let session_created_at =
+    database::session::get_session_created_at(pool.inner(), &claims.sid).await?;
+  verify_reauthentication(
+    &request.password,
+    &user_info.password_hash,
+    session_created_at,
+  )?;
+
+  if !two_factor::verify_second_factor(pool.inner(), &claims.user, &request.code).await? {
+    log::warn!("TOTP code is incorrect for user: {}", claims.user);
     return Err(Status::Unauthorized);
   }
 
-  let user_name_to_unban = &unban_request.user_name;
+  database::totp::disable_totp(pool.inner(), &claims.user).await?;
 
-  log::info!("Removing user from blacklist");
+  log::info!("Disabled TOTP for user: {} successfully", claims.user);
 
-  database::user::delete_user_from_blacklist(pool.inner(), user_name_to_unban).await?;
+  Ok(())
+}
 
-  log::info!("Remove user from blacklist successfully");
+// 查詢當前所有位置狀態
+/*
+如果座位(Seats)不可用，則該座位的狀態為Unavailable
+如果特定時間被包含在某筆預約中，則則該座位的狀態為Borrowed
+否則為Available
+*/
+#[get("/api/show_status")]
+pub async fn show_current_seats_status(pool: &State<Pool<Sqlite>>) -> Result<String, Status> {
+  log::info!("Show current seats status");
+
+  let now: i64 = get_now_timestamp();
+  let all_seats_status: seat::AllSeatsStatus;
+
+  if database::timeslot::is_within_unavailable_timeslot(pool.inner(), now).await? {
+    log::warn!(
+      "The time: {} is within an unavailable timeslot",
+      time_to_string(now)?
+    );
+
+    let mut seats_vec = Vec::<seat::SeatStatus>::new();
+
+    for seat in 1..NUMBER_OF_SEATS {
+      seats_vec.push(seat::SeatStatus {
+        seat_id: seat,
+        status: seat::Status::Unavailable,
+      })
+    }
+    all_seats_status = seat::AllSeatsStatus { seats: seats_vec };
+  } else {
+    all_seats_status = database::seat::get_all_seats_status(pool.inner(), now).await?;
+  }
+
+  let json = handle(
+    serde_json::to_string(&all_seats_status),
+    "Serialize the data as a String of JSON",
+  )?;
+
+  log::info!("Show current seats status successfully");
+
+  Ok(json)
+}
+
+// 查詢當前所有位置狀態 + filter
+/*
+如果給定的時間段中有重疊到被預約的時段則回傳
+Borrowed
+否則回傳
+Available
+*/
+#[get("/api/show_status/<start_time>/<end_time>")]
+pub async fn show_seats_status_in_specific_timeslots(
+  pool: &State<Pool<Sqlite>>,
+  start_time: i64,
+  end_time: i64,
+) -> Result<String, Status> {
+  log::info!("Show seats status by time");
+  validate_datetime(start_time, end_time)?;
+
+  let all_seats_status =
+    database::seat::get_seats_status_in_specific_timeslots(pool.inner(), start_time, end_time)
+      .await?;
+
+  let json = handle(
+    serde_json::to_string(&all_seats_status),
+    "Serialize the data as a String of JSON",
+  )?;
+
+  log::info!("Show seats status by time successfully");
+
+  Ok(json)
+}
+
+// 查詢當前特定位置預約狀態
+#[get("/api/show_reservations/<seat_id>/<start_time>/<end_time>")]
+pub async fn show_seat_reservations(
+  pool: &State<Pool<Sqlite>>,
+  seat_id: u16,
+  start_time: i64,
+  end_time: i64,
+) -> Result<String, Status> {
+  log::info!("Show reservations of seat: {}", seat_id);
+
+  validate_seat_id(seat_id)?;
+
+  let timeslots =
+    database::seat::get_seat_reservations(pool.inner(), start_time, end_time, seat_id).await?;
+
+  let json = handle(
+    serde_json::to_string(&timeslots),
+    "Serialize the data as a String of JSON",
+  )?;
+
+  log::info!("Show reservations of seat: {} successfully", seat_id);
+
+  Ok(json)
+}
+
+// 預約座位
+#[post("/api/reserve", format = "json", data = "<insert_reservation>")]
+pub async fn reserve_seat(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::Reserve>,
+  insert_reservation: Json<reservation::InsertReservationRequest>,
+) -> Result<(), Status> {
+  handle_validator(insert_reservation.validate())?;
+
+  let data: reservation::InsertReservationRequest = insert_reservation.into_inner();
+  let seat_id = data.seat_id;
+  let start_time = data.start_time;
+  let end_time = data.end_time;
+  let user_name = guard.user_name;
+
+  log::info!("Reserving a seat :{} for user: {}", seat_id, user_name);
+
+  // 停權期間內(包含尚未開始的停權)不可預約
+  if database::user::is_user_banned_during(pool.inner(), &user_name, start_time, end_time).await? {
+    log::warn!("User: {} is banned during the reservation", user_name);
+    return Err(Status::Forbidden);
+  }
+
+  if !database::seat::is_seat_available(pool.inner(), seat_id).await? {
+    log::warn!("The seat: {} is unavailable", seat_id);
+    return Err(Status::BadRequest);
+  }
+
+  if database::seat::is_seat_under_maintenance(pool.inner(), seat_id, start_time, end_time).await? {
+    log::warn!("The seat: {} is under maintenance", seat_id);
+    return Err(Status::BadRequest);
+  }
+
+  if database::timeslot::is_overlapping_with_unavailable_timeslot(
+    pool.inner(),
+    start_time,
+    end_time,
+  )
+  .await?
+  {
+    log::warn!(
+      "The start_time: {} end_time: {} is overlapping unavailable timeslot",
+      start_time,
+      end_time
+    );
+    return Err(Status::BadRequest);
+  }
+
+  let date = timestamp_to_naive_datetime(start_time)?.date();
+
+  if database::reservation::check_unfinished_reservations(pool.inner(), &user_name, date).await? {
+    log::warn!("Found ongoing reservation not yet completed");
+    return Err(Status::BadRequest);
+  }
+
+  database::reservation::reserve_seat(pool.inner(), &user_name, seat_id, start_time, end_time)
+    .await?;
+
+  log::info!(
+    "Seat: {} reserved successfully for user: {}",
+    seat_id,
+    user_name
+  );
+
+  Ok(())
+}
+
+// 修改預約時段
+#[post(
+  "/api/update_reservation",
+  format = "json",
+  data = "<update_reservation>"
+)]
+pub async fn update_reservation(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::Reserve>,
+  update_reservation: Json<reservation::UpdateReservationRequest>,
+) -> Result<(), Status> {
+  handle_validator(update_reservation.validate())?;
+
+  let data: reservation::UpdateReservationRequest = update_reservation.into_inner();
+  let start_time = data.start_time;
+  let end_time = data.end_time;
+  let new_start_time = data.new_start_time;
+  let new_end_time = data.new_end_time;
+  let user_name = guard.user_name;
+
+  log::info!("Updating reservation for user: {}", user_name);
+
+  if database::user::is_user_banned_during(pool.inner(), &user_name, new_start_time, new_end_time)
+    .await?
+  {
+    log::warn!("User: {} is banned during the reservation", user_name);
+    return Err(Status::Forbidden);
+  }
+
+  if database::timeslot::is_overlapping_with_unavailable_timeslot(
+    pool.inner(),
+    new_start_time,
+    new_end_time,
+  )
+  .await?
+  {
+    return Err(Status::BadRequest);
+  }
+
+  database::reservation::update_reservation_time(
+    pool.inner(),
+    &user_name,
+    start_time,
+    end_time,
+    new_start_time,
+    new_end_time,
+  )
+  .await?;
+
+  log::info!("Reservation for user: {} updated successfully", user_name);
+
+  Ok(())
+}
+
+// 刪除預約時段
+#[post(
+  "/api/delete_reservation",
+  format = "json",
+  data = "<delete_reservation>"
+)]
+pub async fn delete_reservation_time(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::Reserve>,
+  delete_reservation: Json<reservation::DeleteReservationRequest>,
+) -> Result<(), Status> {
+  handle_validator(delete_reservation.validate())?;
+
+  let data: reservation::DeleteReservationRequest = delete_reservation.into_inner();
+  let start_time = data.start_time;
+  let end_time = data.end_time;
+  let user_name = guard.user_name;
+
+  log::info!("Deleting reservation for user: {}", user_name);
+
+  database::reservation::delete_reservation_time(pool.inner(), &user_name, start_time, end_time)
+    .await?;
+
+  log::info!("Reservation for user: {} deleted successfully", user_name);
+
+  Ok(())
+}
+
+// 現場報到，給查詢裝置或門禁系統以 API key 呼叫
+/*
+報到進行中或 CHECK_IN_EARLY_MINUTES 分鐘內開始的預約，沒有可報到的預約時回傳 404
+*/
+#[post("/api/check_in", format = "json", data = "<request>")]
+pub async fn check_in(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::CheckIn>,
+  request: Json<reservation::CheckInRequest>,
+) -> Result<Json<reservation::Reservation>, Status> {
+  handle_validator(request.validate())?;
+
+  log::info!("Checking in user: {}", request.user_name);
+
+  // 停權中的使用者不可報到
+  if database::user::is_user_in_blacklist(pool.inner(), &request.user_name).await? {
+    log::warn!("User: {} is currently in the blacklist", request.user_name);
+    return Err(Status::Forbidden);
+  }
+
+  let reservation = database::reservation::check_in_reservation(pool.inner(), &request.user_name)
+    .await?
+    .ok_or_else(|| {
+      log::warn!("No reservation to check in for user: {}", request.user_name);
+      Status::NotFound
+    })?;
+
+  log::info!(
+    "User: {} checked in to seat: {}",
+    request.user_name,
+    reservation.seat_id
+  );
+
+  Ok(Json(reservation))
+}
+
+// 顯示使用者預約時段
+#[get("/api/user_reservations")]
+pub async fn display_user_reservations(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+) -> Result<Json<Vec<reservation::Reservation>>, Status> {
+  let user_name = claims.user;
+
+  log::info!("Displaying the user's reservations");
+
+  let reservations = database::reservation::get_user_reservations(pool.inner(), &user_name).await?;
+
+  log::info!("Displaying the user's reservations successfully");
+
+  Ok(Json(reservations))
+}
+
+// 建立或更換行事曆訂閱連結，舊的連結會失效
+#[post("/api/calendar_feed")]
+pub async fn create_calendar_feed(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+) -> Result<Json<reservation::CalendarFeed>, Status> {
+  let user_name = claims.user;
+
+  log::info!("Creating calendar feed for user: {}", user_name);
+
+  let token = Uuid::new_v4().simple().to_string();
+  database::calendar_feed::upsert_calendar_feed_token(pool.inner(), &user_name, &token).await?;
+
+  let url = format!("{}/api/calendar_feed/{}.ics", get_base_url(), token);
+
+  log::info!("Calendar feed for user: {} created successfully", user_name);
+
+  Ok(Json(reservation::CalendarFeed { url }))
+}
+
+// 停用行事曆訂閱連結
+#[delete("/api/calendar_feed")]
+pub async fn revoke_calendar_feed(
+  pool: &State<Pool<Sqlite>>,
+  claims: token::UserInfoClaim,
+) -> Result<(), Status> {
+  let user_name = claims.user;
+
+  log::info!("Revoking calendar feed for user: {}", user_name);
+
+  database::calendar_feed::delete_calendar_feed_token(pool.inner(), &user_name).await?;
+
+  log::info!("Calendar feed for user: {} revoked successfully", user_name);
+
+  Ok(())
+}
+
+// 行事曆訂閱，以連結中的 token 辨識使用者，不需要登入
+#[get("/api/calendar_feed/<token>")]
+pub async fn show_calendar_feed(
+  pool: &State<Pool<Sqlite>>,
+  token: &str,
+) -> Result<(ContentType, String), Status> {
+  log::info!("Showing calendar feed");
+
+  let token = token.strip_suffix(".ics").unwrap_or(token);
+  let user_name = database::calendar_feed::get_calendar_feed_user(pool.inner(), token).await?;
+
+  let since = get_now_timestamp() - CALENDAR_FEED_PAST_DAYS * 24 * 60 * 60;
+  let events =
+    database::reservation::get_user_reservation_events(pool.inner(), &user_name, since).await?;
+
+  let content = calendar::build_reservation_calendar(&events, &get_venue_name())?;
+
+  log::info!("Calendar feed for user: {} shown successfully", user_name);
+
+  Ok((ContentType::Calendar, content))
+}
+
+// 設定不可預約時間
+#[post("/api/set_timeslots", format = "json", data = "<time_slot>")]
+pub async fn set_unavailable_timeslots(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageSchedule>,
+  time_slot: Json<timeslot::TimeSlot>,
+) -> Result<(), Status> {
+  handle_validator(time_slot.validate())?;
+
+  let user_name = guard.user_name;
+
+  let data: timeslot::TimeSlot = time_slot.into_inner();
+  let start_time = data.start_time;
+  let end_time = data.end_time;
+
+  log::info!(
+    "Setting unavailable timeslot start_time: {:?}, end_time: {:?}",
+    start_time,
+    end_time
+  );
+
+  database::timeslot::insert_unavailable_timeslot(
+    pool.inner(),
+    start_time,
+    end_time,
+    data.reason.as_deref(),
+    &user_name,
+  )
+  .await?;
+
+  log::info!("Unavailable timeslot set successfully");
+  Ok(())
+}
+
+// 顯示不可預約時間，可依時間範圍篩選
+#[get("/api/timeslots?<start_time>&<end_time>")]
+pub async fn show_unavailable_timeslots(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  start_time: Option<i64>,
+  end_time: Option<i64>,
+) -> Result<Json<Vec<timeslot::UnavailableTimeSlot>>, Status> {
+  log::info!("Showing unavailable timeslots");
+
+  let timeslots =
+    database::timeslot::get_unavailable_timeslots(pool.inner(), start_time, end_time).await?;
+
+  log::info!("Unavailable timeslots shown successfully");
+  Ok(Json(timeslots))
+}
+
+// 修改不可預約時間
+#[patch("/api/timeslots/<id>", format = "json", data = "<update_timeslot>")]
+pub async fn update_unavailable_timeslot(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  id: i64,
+  update_timeslot: Json<timeslot::UpdateTimeSlotRequest>,
+) -> Result<(), Status> {
+  handle_validator(update_timeslot.validate())?;
+
+  log::info!("Updating unavailable timeslot: {}", id);
+
+  let data: timeslot::UpdateTimeSlotRequest = update_timeslot.into_inner();
+  let current = database::timeslot::get_unavailable_timeslot(pool.inner(), id).await?;
+
+  // 未提供的欄位沿用原本的設定
+  let start_time = data.start_time.unwrap_or(current.start_time);
+  let end_time = data.end_time.unwrap_or(current.end_time);
+  let reason = match data.reason {
+    Some(reason) => reason,
+    None => current.reason,
+  };
+
+  // 只有修改時間時才需要檢查，避免無法修改進行中的時段原因
+  if data.start_time.is_some() || data.end_time.is_some() {
+    validate_datetime(start_time, end_time)?;
+  }
+
+  database::timeslot::update_unavailable_timeslot(
+    pool.inner(),
+    id,
+    start_time,
+    end_time,
+    reason.as_deref(),
+  )
+  .await?;
+
+  log::info!("Unavailable timeslot: {} updated successfully", id);
+  Ok(())
+}
+
+// 刪除不可預約時間
+#[delete("/api/timeslots/<id>")]
+pub async fn delete_unavailable_timeslot(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  id: i64,
+) -> Result<(), Status> {
+  log::info!("Deleting unavailable timeslot: {}", id);
+
+  database::timeslot::delete_unavailable_timeslot(pool.inner(), id).await?;
+
+  log::info!("Unavailable timeslot: {} deleted successfully", id);
+  Ok(())
+}
+
+// 顯示即將到來的休館時段
+#[get("/api/closures")]
+pub async fn show_upcoming_closures(
+  pool: &State<Pool<Sqlite>>,
+) -> Result<Json<Vec<timeslot::Closure>>, Status> {
+  log::info!("Showing upcoming closures");
+
+  let now = get_now_timestamp();
+  let closures = database::timeslot::get_upcoming_closures(pool.inner(), now).await?;
+
+  log::info!("Upcoming closures shown successfully");
+  Ok(Json(closures))
+}
+
+// 匯入 iCalendar 檔案為不可預約時間
+#[post(
+  "/api/import_timeslots",
+  format = "text/calendar",
+  data = "<calendar_file>"
+)]
+pub async fn import_unavailable_timeslots(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageSchedule>,
+  calendar_file: Data<'_>,
+) -> Result<Json<timeslot::ImportCalendarResult>, Status> {
+  let user_name = guard.user_name;
+
+  log::info!("Importing unavailable timeslots from iCalendar");
+
+  let content = handle(
+    calendar_file
+      .open(MAX_CALENDAR_SIZE.bytes())
+      .into_string()
+      .await,
+    "Reading iCalendar file",
+  )?;
+
+  if !content.is_complete() {
+    log::warn!("The iCalendar file exceeds the size limit");
+    return Err(Status::PayloadTooLarge);
+  }
+
+  let result = calendar::import_calendar(pool.inner(), &content, &user_name).await?;
+
+  log::info!("Unavailable timeslots imported successfully: {:?}", result);
+  Ok(Json(result))
+}
+
+// 設定不可使用座位
+#[post(
+  "/api/set_seat_availability",
+  format = "json",
+  data = "<seat_availability>"
+)]
+pub async fn set_seat_availability(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSeats>,
+  seat_availability: Json<seat::SeatAvailabilityRequest>,
+) -> Result<(), Status> {
+  handle_validator(seat_availability.validate())?;
+
+  let seat_id = seat_availability.seat_id;
+  let available = seat_availability.available;
+
+  log::info!(
+    "Setting seat availability seat_id: {:?}, available: {:?}",
+    seat_id,
+    available
+  );
+
+  database::seat::update_seat_availability(pool.inner(), seat_id, available).await?;
+
+  log::info!("Seat availability set successfully");
+  Ok(())
+}
+
+// 設定黑名單
+/*
+尚未結束的停權會被新的停權取代，舊的紀錄仍保留在停權歷史中
+cancel_reservations 為 true 時取消停權期間內尚未開始的預約，並回傳取消的數量
+*/
+#[post("/api/set_blacklist", format = "json", data = "<ban_request>")]
+pub async fn add_user_to_blacklist(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageUsers>,
+  ban_request: Json<user::BanRequest>,
+) -> Result<Json<user::BanResult>, Status> {
+  handle_validator(ban_request.validate())?;
+
+  let user_name_to_ban = &ban_request.user_name;
+  let start_time = ban_request.start_time;
+  let end_time = ban_request.end_time;
+
+  log::info!(
+    "Adding user to blacklist with start_time: {:?}, end_time: {:?}",
+    start_time,
+    end_time
+  );
+
+  // 確認被停權的帳號存在
+  database::user::get_user_info(pool.inner(), user_name_to_ban).await?;
+
+  let cancelled_reservations = database::user::insert_user_to_blacklist(
+    pool.inner(),
+    user_name_to_ban,
+    start_time,
+    end_time,
+    ban_request.reason.as_deref(),
+    &guard.user_name,
+    ban_request.cancel_reservations,
+  )
+  .await?;
+
+  log::info!(
+    "Add user to blacklist successfully, cancelled {} reservations",
+    cancelled_reservations
+  );
+  Ok(Json(user::BanResult {
+    cancelled_reservations,
+  }))
+}
+
+// 將使用者移除黑名單
+#[post("/api/remove_blacklist", format = "json", data = "<unban_request>")]
+pub async fn remove_user_from_blacklist(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageUsers>,
+  unban_request: Json<user::UnBanRequest>,
+) -> Result<(), Status> {
+  let user_name_to_unban = &unban_request.user_name;
+
+  log::info!("Removing user from blacklist");
+
+  database::user::lift_user_bans(pool.inner(), user_name_to_unban, &guard.user_name).await?;
+
+  log::info!("Remove user from blacklist successfully");
+  Ok(())
+}
+
+// 查詢停權紀錄，active 為 true 時只列出目前生效中的停權
+#[get("/api/admin/bans?<user_name>&<active>&<page>&<page_size>")]
+pub async fn show_bans(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: Option<String>,
+  active: Option<bool>,
+  page: Option<i64>,
+  page_size: Option<i64>,
+) -> Result<Json<user::BanPage>, Status> {
+  log::info!("Showing bans");
+
+  let page = page.unwrap_or(1);
+  let page_size = page_size.unwrap_or(DEFAULT_USER_PAGE_SIZE);
+
+  if page < 1 || !(1..=MAX_USER_PAGE_SIZE).contains(&page_size) {
+    log::warn!(
+      "Invalid pagination, page: {}, page_size: {}",
+      page,
+      page_size
+    );
+    return Err(Status::BadRequest);
+  }
+
+  let bans =
+    database::user::get_bans(pool.inner(), user_name.as_deref(), active, page, page_size).await?;
+
+  log::info!("Showing bans successfully");
+
+  Ok(Json(bans))
+}
+
+// 解除帳號因連續登入失敗造成的鎖定
+#[post("/api/unlock_account", format = "json", data = "<unlock_request>")]
+pub async fn unlock_account(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  unlock_request: Json<user::UnlockAccountRequest>,
+) -> Result<(), Status> {
+  handle_validator(unlock_request.validate())?;
+
+  let user_name_to_unlock = &unlock_request.user_name;
+
+  log::info!("Unlocking account: {}", user_name_to_unlock);
+
+  let affected_rows = database::login_throttle::clear_login_failures(
+    pool.inner(),
+    user::LoginThrottleKind::Account,
+    user_name_to_unlock,
+  )
+  .await?;
+
+  if affected_rows == 0 {
+    log::warn!("No login failures found for user: {}", user_name_to_unlock);
+    return Err(Status::NotFound);
+  }
+
+  log::info!("Unlock account: {} successfully", user_name_to_unlock);
+  Ok(())
+}
+
+// 建立 API key，key 只在此時回傳一次
+/*
+給自助機、門禁系統等沒有人員登入的服務使用，不會像 JWT 一樣過期，需由管理員撤銷
+*/
+#[post("/api/api_keys", format = "json", data = "<request>")]
+pub async fn create_api_key(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageUsers>,
+  request: Json<api_key::CreateApiKeyRequest>,
+) -> Result<Json<api_key::ApiKeyCreated>, Status> {
+  log::info!("Creating API key: {}", request.name);
+
+  handle_validator(request.validate())?;
+
+  // 確認 key 所屬的帳號存在，且 scopes 不超出該帳號身分的權限
+  let owner = database::user::get_user_info(pool.inner(), &request.user_name).await?;
+
+  if let Some(scope) = request
+    .scopes
+    .iter()
+    .find(|scope| !owner.user_role.has_permission(**scope))
+  {
+    log::warn!(
+      "User: {} with role: {} cannot be granted scope: {:?}",
+      request.user_name,
+      owner.user_role.to_string(),
+      scope
+    );
+    return Err(Status::BadRequest);
+  }
+
+  let key_id = Uuid::new_v4().simple().to_string();
+  let api_key = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+
+  database::api_key::insert_api_key(
+    pool.inner(),
+    &key_id,
+    &request.name,
+    &hash_token(&api_key),
+    &request.user_name,
+    &request.scopes,
+    &guard.user_name,
+  )
+  .await?;
+
+  log::info!("Created API key: {} successfully", key_id);
+
+  Ok(Json(api_key::ApiKeyCreated { key_id, api_key }))
+}
+
+#[get("/api/api_keys")]
+pub async fn show_api_keys(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+) -> Result<Json<Vec<api_key::ApiKey>>, Status> {
+  log::info!("Showing API keys");
+
+  let api_keys = database::api_key::get_api_keys(pool.inner()).await?;
+
+  log::info!("Showing API keys successfully");
+
+  Ok(Json(api_keys))
+}
+
+#[delete("/api/api_keys/<key_id>")]
+pub async fn revoke_api_key(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  key_id: String,
+) -> Result<(), Status> {
+  log::info!("Revoking API key: {}", key_id);
+
+  database::api_key::revoke_api_key(pool.inner(), &key_id).await?;
+
+  log::info!("Revoked API key: {} successfully", key_id);
+
+  Ok(())
+}
+
+// 管理員查詢使用者列表，search 比對使用者名稱或 email
+#[get("/api/admin/users?<search>&<page>&<page_size>")]
+pub async fn show_users(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  search: Option<String>,
+  page: Option<i64>,
+  page_size: Option<i64>,
+) -> Result<Json<user::UserPage>, Status> {
+  log::info!("Showing users");
+
+  let page = page.unwrap_or(1);
+  let page_size = page_size.unwrap_or(DEFAULT_USER_PAGE_SIZE);
+
+  if page < 1 || !(1..=MAX_USER_PAGE_SIZE).contains(&page_size) {
+    log::warn!(
+      "Invalid pagination, page: {}, page_size: {}",
+      page,
+      page_size
+    );
+    return Err(Status::BadRequest);
+  }
+
+  let search = search.filter(|search| !search.is_empty());
+
+  let users = database::user::get_users(pool.inner(), search.as_deref(), page, page_size).await?;
+
+  log::info!("Showing users successfully");
+
+  Ok(Json(users))
+}
+
+// 管理員查詢使用者詳細資料，包含預約與停權紀錄
+#[get("/api/admin/users/<user_name>")]
+pub async fn show_user_detail(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<Json<user::UserDetail>, Status> {
+  log::info!("Showing detail of user: {}", user_name);
+
+  let pool = pool.inner();
+  let profile = database::user::get_user_profile(pool, &user_name).await?;
+  let (disabled, created_at) = database::user::get_user_account_status(pool, &user_name).await?;
+
+  let detail = user::UserDetail {
+    profile,
+    disabled,
+    created_at,
+    reservations: database::reservation::get_user_reservation_events(pool, &user_name, i64::MIN)
+      .await?,
+    bans: database::user::get_user_bans(pool, &user_name).await?,
+  };
+
+  log::info!("Showing detail of user: {} successfully", user_name);
+
+  Ok(Json(detail))
+}
+
+// 變更使用者身分，已登入的裝置需重新登入以取得新身分的 token
+#[patch(
+  "/api/admin/users/<user_name>/role",
+  format = "json",
+  data = "<request>"
+)]
+pub async fn change_user_role(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+  request: Json<user::ChangeUserRoleRequest>,
+) -> Result<(), Status> {
+  log::info!(
+    "Changing role of user: {} to {:?}",
+    user_name,
+    request.user_role
+  );
+
+  // 避免管理員移除自己的權限後無法復原
+  if user_name == guard.user_name {
+    log::warn!("Admin can not change their own role");
+    return Err(Status::BadRequest);
+  }
+
+  let user_role = request.into_inner().user_role;
+
+  database::user::update_user_role(pool.inner(), &user_name, user_role).await?;
+  database::session::revoke_user_sessions(pool.inner(), &user_name, None).await?;
+
+  log::info!("Changed role of user: {} successfully", user_name);
+
+  Ok(())
+}
+
+// 管理員直接將帳號設為已驗證
+#[post("/api/admin/users/<user_name>/verify")]
+pub async fn force_verify_user(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<(), Status> {
+  log::info!("Force verifying user: {}", user_name);
+
+  database::user::force_verify_user(pool.inner(), &user_name).await?;
+
+  log::info!("Force verified user: {} successfully", user_name);
+
+  Ok(())
+}
+
+// 管理員替使用者重新寄送驗證信，帳號已驗證時回傳 400
+#[post("/api/admin/users/<user_name>/resend_verification")]
+pub async fn resend_user_verification_email(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<(), Status> {
+  log::info!("Resending verification email for user: {}", user_name);
+
+  // 確認帳號存在
+  database::user::get_user_info(pool.inner(), &user_name).await?;
+
+  let verification_token = Uuid::new_v4().simple().to_string();
+
+  let email = database::user::renew_verification_token_by_user_name(
+    pool.inner(),
+    &user_name,
+    &hash_token(&verification_token),
+  )
+  .await?
+  .ok_or_else(|| {
+    log::warn!("User: {} has no pending verification", user_name);
+    Status::BadRequest
+  })?;
+
+  tokio::task::spawn_blocking(move || {
+    if send_verification_email(&email, &verification_token).is_err() {
+      log::error!("Failed to send verification email");
+    }
+  });
+
+  log::info!("Resent verification email for user: {}", user_name);
+
+  Ok(())
+}
+
+// 停用帳號，停用期間無法登入，已登入的裝置與 API key 隨即失效
+#[post("/api/admin/users/<user_name>/disable")]
+pub async fn disable_user(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<(), Status> {
+  log::info!("Disabling user: {}", user_name);
+
+  if user_name == guard.user_name {
+    log::warn!("Admin can not disable their own account");
+    return Err(Status::BadRequest);
+  }
+
+  database::user::update_user_disabled(pool.inner(), &user_name, true).await?;
+  database::session::revoke_user_sessions(pool.inner(), &user_name, None).await?;
+
+  log::info!("Disabled user: {} successfully", user_name);
+
+  Ok(())
+}
+
+#[post("/api/admin/users/<user_name>/enable")]
+pub async fn enable_user(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<(), Status> {
+  log::info!("Enabling user: {}", user_name);
+
+  database::user::update_user_disabled(pool.inner(), &user_name, false).await?;
+
+  log::info!("Enabled user: {} successfully", user_name);
+
+  Ok(())
+}
+
+// 管理員替使用者寄送重設密碼信，不會直接得知或設定新密碼
+#[post("/api/admin/users/<user_name>/reset_password")]
+pub async fn send_user_password_reset(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageUsers>,
+  user_name: String,
+) -> Result<(), Status> {
+  log::info!("Sending password reset email for user: {}", user_name);
+
+  let email = database::user::get_user_info(pool.inner(), &user_name)
+    .await?
+    .email;
+
+  let reset_token = Uuid::new_v4().simple().to_string();
+  let expires_at = get_now_timestamp() + PASSWORD_RESET_TOKEN_MINUTES * 60;
+
+  database::user::insert_password_reset_token(
+    pool.inner(),
+    &user_name,
+    &hash_token(&reset_token),
+    expires_at,
+  )
+  .await?;
+
+  tokio::task::spawn_blocking(move || {
+    if send_password_reset_email(&email, &reset_token).is_err() {
+      log::error!("Failed to send password reset email");
+    }
+  });
+
+  log::info!("Issued password reset token for user: {}", user_name);
+
+  Ok(())
+}
+
+// 回報座位問題
+#[post("/api/report_issue", data = "<report>")]
+pub async fn report_seat_issue(
+  pool: &State<Pool<Sqlite>>,
+  guard: token::RequirePermission<permission::ReportIssue>,
+  report: Form<issue::ReportIssueForm<'_>>,
+) -> Result<Json<issue::ReportIssueResponse>, Status> {
+  handle_validator(report.validate())?;
+
+  let mut data: issue::ReportIssueForm = report.into_inner();
+  let user_name = guard.user_name;
+
+  log::info!(
+    "Reporting an issue of seat: {} by user: {}",
+    data.seat_id,
+    user_name
+  );
+
+  let photo_path = match data.photo.as_mut() {
+    Some(photo) => Some(save_issue_photo(photo).await?),
+    None => None,
+  };
+
+  let issue_id = database::issue::insert_seat_issue(
+    pool.inner(),
+    &user_name,
+    data.seat_id,
+    data.category,
+    &data.description,
+    photo_path.as_deref(),
+  )
+  .await?;
+
+  log::info!("Seat issue: {} reported successfully", issue_id);
+
+  Ok(Json(issue::ReportIssueResponse { issue_id }))
+}
+
+// 查看座位問題回報
+#[get("/api/issues?<status>")]
+pub async fn show_seat_issues(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSeats>,
+  status: Option<issue::IssueStatus>,
+) -> Result<Json<Vec<issue::SeatIssue>>, Status> {
+  log::info!("Showing seat issues with status: {:?}", status);
+
+  let issues = database::issue::get_seat_issues(pool.inner(), status).await?;
+
+  log::info!("Showing seat issues successfully");
+
+  Ok(Json(issues))
+}
+
+// 查看座位問題回報的照片
+#[get("/api/issue_photo/<issue_id>")]
+pub async fn show_seat_issue_photo(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSeats>,
+  issue_id: i64,
+) -> Result<NamedFile, Status> {
+  let file_name = database::issue::get_seat_issue_photo_path(pool.inner(), issue_id)
+    .await?
+    .ok_or_else(|| {
+      log::warn!("Seat issue: {} has no photo", issue_id);
+      Status::NotFound
+    })?;
+
+  let path = Path::new(&get_issue_photo_dir()).join(file_name);
+
+  handle(NamedFile::open(path).await, "Opening seat issue photo")
+}
+
+// 指派座位問題回報給可管理座位的管理員或館員
+#[post("/api/assign_issue", format = "json", data = "<assign_request>")]
+pub async fn assign_seat_issue(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSeats>,
+  assign_request: Json<issue::AssignIssueRequest>,
+) -> Result<(), Status> {
+  handle_validator(assign_request.validate())?;
+
+  let issue_id = assign_request.issue_id;
+  let assignee = &assign_request.assignee;
+
+  log::info!("Assigning seat issue: {} to: {}", issue_id, assignee);
+
+  let assignee_info = database::user::get_user_info(pool.inner(), assignee).await?;
+  if !assignee_info
+    .user_role
+    .has_permission(permission::Permission::ManageSeats)
+  {
+    log::warn!("The assignee: {} cannot manage seats", assignee);
+    return Err(Status::BadRequest);
+  }
+
+  database::issue::update_seat_issue_assignee(pool.inner(), issue_id, assignee).await?;
+
+  log::info!("Seat issue assigned successfully");
+  Ok(())
+}
+
+// 處理座位問題回報
+#[post("/api/resolve_issue", format = "json", data = "<resolve_request>")]
+pub async fn resolve_seat_issue(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSeats>,
+  resolve_request: Json<issue::ResolveIssueRequest>,
+) -> Result<(), Status> {
+  handle_validator(resolve_request.validate())?;
+
+  let issue_id = resolve_request.issue_id;
+  let resolution = resolve_request.resolution;
+
+  log::info!(
+    "Resolving seat issue: {} with resolution: {}",
+    issue_id,
+    resolution
+  );
+
+  database::issue::resolve_seat_issue(
+    pool.inner(),
+    issue_id,
+    resolution,
+    resolve_request.maintenance_end_time,
+  )
+  .await?;
+
+  log::info!("Seat issue resolved successfully");
+  Ok(())
+}
+
+// 查詢開放時間
+#[get("/api/opening_hours")]
+pub async fn show_opening_hours(
+  pool: &State<Pool<Sqlite>>,
+) -> Result<Json<opening_hours::OpeningHoursCalendar>, Status> {
+  log::info!("Showing opening hours");
+
+  let weekly = database::opening_hours::get_weekly_opening_hours(pool.inner()).await?;
+  let exceptions =
+    database::opening_hours::get_opening_hours_exceptions(pool.inner(), get_today()).await?;
+
+  log::info!("Showing opening hours successfully");
+
+  Ok(Json(opening_hours::OpeningHoursCalendar {
+    weekly,
+    exceptions,
+  }))
+}
+
+// 設定每週開放時間
+#[post("/api/set_opening_hours", format = "json", data = "<rule>")]
+pub async fn set_opening_hours(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  rule: Json<opening_hours::OpeningHoursRule>,
+) -> Result<(), Status> {
+  handle_validator(rule.validate())?;
+
+  log::info!("Setting opening hours: {:?}", rule);
+
+  database::opening_hours::upsert_weekly_opening_hours(pool.inner(), &rule).await?;
+
+  log::info!("Opening hours set successfully");
+  Ok(())
+}
+
+// 設定特定日期的開放時間或休館日
+#[post(
+  "/api/set_opening_hours_exception",
+  format = "json",
+  data = "<exception>"
+)]
+pub async fn set_opening_hours_exception(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  exception: Json<opening_hours::OpeningHoursException>,
+) -> Result<(), Status> {
+  handle_validator(exception.validate())?;
+
+  log::info!("Setting opening hours exception: {:?}", exception);
+
+  database::opening_hours::upsert_opening_hours_exception(pool.inner(), &exception).await?;
+
+  log::info!("Opening hours exception set successfully");
+  Ok(())
+}
+
+// 刪除特定日期的開放時間例外
+#[post(
+  "/api/delete_opening_hours_exception",
+  format = "json",
+  data = "<delete_request>"
+)]
+pub async fn delete_opening_hours_exception(
+  pool: &State<Pool<Sqlite>>,
+  _guard: token::RequirePermission<permission::ManageSchedule>,
+  delete_request: Json<opening_hours::DeleteOpeningHoursExceptionRequest>,
+) -> Result<(), Status> {
+  let date = delete_request.date;
+
+  log::info!("Deleting opening hours exception of date: {}", date);
+
+  database::opening_hours::delete_opening_hours_exception(pool.inner(), date).await?;
+
+  log::info!("Opening hours exception deleted successfully");
   Ok(())
 }
@@ -0,0 +1,576 @@
+use crate::{
+  database,
+  model::{reservation, timeslot},
+  utils::*,
+};
+use chrono::{Duration, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use ical::{parser::ical::component::IcalEvent, property::Property, IcalParser};
+use rrule::{RRuleSet, Tz};
+use sqlx::{Pool, Sqlite};
+use std::{collections::BTreeMap, io::BufReader, str::FromStr};
+
+// 匯入的重複事件最多展開到一年後
+const IMPORT_HORIZON_DAYS: i64 = 366;
+const MAX_OCCURRENCES: u16 = 1000;
+// 行事曆訂閱的 PRODID 與事件 UID 的網域
+const PRODID: &str = "-//SSR//Study Seat Reserve//EN";
+const UID_DOMAIN: &str = "study-seat-reserve";
+
+#[derive(Debug)]
+pub struct CalendarEvent {
+  pub uid: String,
+  pub summary: Option<String>,
+  pub time_slots: Vec<(i64, i64)>,
+}
+
+// 一個 VEVENT 解析後的內容，同一個 UID 可能有多個(重複事件的例外)
+struct ParsedEvent {
+  summary: Option<String>,
+  start: i64,
+  end: i64,
+  cancelled: bool,
+  recurrence: Option<String>,
+  recurrence_id: Option<i64>,
+}
+
+// 匯入 iCalendar 檔案中的事件為不可預約時段
+/*
+只新增或更新檔案中有的事件，之前匯入但已從檔案移除的事件不會被刪除，需另外以 API 刪除對應的時段
+*/
+pub async fn import_calendar(
+  pool: &Pool<Sqlite>,
+  content: &str,
+  created_by: &str,
+) -> Result<timeslot::ImportCalendarResult, Status> {
+  let events = parse_calendar(content)?;
+  let mut result = timeslot::ImportCalendarResult {
+    events: events.len(),
+    ..Default::default()
+  };
+
+  for event in events.iter() {
+    log::info!(
+      "Importing calendar event: {} ({:?}) with {} time slots",
+      event.uid,
+      event.summary,
+      event.time_slots.len()
+    );
+
+    let (outcome, conflicts) = database::timeslot::replace_calendar_event_timeslots(
+      pool,
+      &event.uid,
+      &event.time_slots,
+      event.summary.as_deref(),
+      created_by,
+    )
+    .await?;
+
+    match outcome {
+      timeslot::ImportOutcome::Created => result.created += 1,
+      timeslot::ImportOutcome::Updated => result.updated += 1,
+      timeslot::ImportOutcome::Unchanged => result.unchanged += 1,
+    }
+
+    result.conflicts += conflicts;
+  }
+
+  Ok(result)
+}
+
+// 產生使用者預約的 iCalendar 訂閱內容，已取消的預約以 STATUS:CANCELLED 輸出
+pub fn build_reservation_calendar(
+  events: &[reservation::ReservationEvent],
+  venue: &str,
+) -> Result<String, Status> {
+  let now = format_utc(Utc::now().timestamp())?;
+  let mut lines = vec![
+    "BEGIN:VCALENDAR".to_string(),
+    "VERSION:2.0".to_string(),
+    format!("PRODID:{}", PRODID),
+    "CALSCALE:GREGORIAN".to_string(),
+    format!(
+      "X-WR-CALNAME:{}",
+      escape_text(&format!("{} reservations", venue))
+    ),
+  ];
+
+  for event in events.iter() {
+    let status = if event.cancelled {
+      "CANCELLED"
+    } else {
+      "CONFIRMED"
+    };
+
+    lines.extend([
+      "BEGIN:VEVENT".to_string(),
+      format!("UID:{}@{}", event.uid, UID_DOMAIN),
+      format!("DTSTAMP:{}", now),
+      format!("DTSTART:{}", format_utc(event.start_time)?),
+      format!("DTEND:{}", format_utc(event.end_time)?),
+      format!("SEQUENCE:{}", event.sequence),
+      format!("STATUS:{}", status),
+      format!(
+        "SUMMARY:{}",
+        escape_text(&format!("Seat {}", event.seat_id))
+      ),
+      format!(
+        "LOCATION:{}",
+        escape_text(&format!("{}, Seat {}", venue, event.seat_id))
+      ),
+      "END:VEVENT".to_string(),
+    ]);
+  }
+
+  lines.push("END:VCALENDAR".to_string());
+
+  let content: String = lines.iter().map(|line| fold_line(line) + "\r\n").collect();
+
+  Ok(content)
+}
+
+// 解析 iCalendar 檔案，將事件轉換為不可預約時段(依日期切割)
+pub fn parse_calendar(content: &str) -> Result<Vec<CalendarEvent>, Status> {
+  let now = get_now_timestamp();
+  let horizon = now + Duration::days(IMPORT_HORIZON_DAYS).num_seconds();
+
+  let mut events_by_uid: BTreeMap<String, Vec<ParsedEvent>> = BTreeMap::new();
+
+  for calendar in IcalParser::new(BufReader::new(content.as_bytes())) {
+    let calendar = calendar.map_err(|e| {
+      log::error!("Parsing iCalendar failed with error: {:?}", e);
+      Status::UnprocessableEntity
+    })?;
+
+    for event in calendar.events.iter() {
+      let uid = get_property(event, "UID")
+        .and_then(|property| property.value.clone())
+        .ok_or_else(|| {
+          log::error!("Found VEVENT without UID");
+          Status::UnprocessableEntity
+        })?;
+
+      let parsed_event = parse_event(event)?;
+      events_by_uid.entry(uid).or_default().push(parsed_event);
+    }
+  }
+
+  let mut calendar_events = Vec::new();
+
+  for (uid, parsed_events) in events_by_uid.into_iter() {
+    let mut summary = None;
+    let mut occurrences: Vec<(i64, i64)> = Vec::new();
+
+    // 被個別修改過的重複事件實例，需從主事件展開的結果中排除
+    let overridden: Vec<i64> = parsed_events
+      .iter()
+      .filter_map(|event| event.recurrence_id)
+      .collect();
+
+    for event in parsed_events.iter() {
+      if event.recurrence_id.is_none() {
+        summary = event.summary.clone();
+      }
+
+      if event.cancelled {
+        continue;
+      }
+
+      match &event.recurrence {
+        Some(recurrence) if event.recurrence_id.is_none() => {
+          // 從仍未結束的實例開始展開，避免很久以前開始的重複事件在過去的日期就用完展開上限
+          let from = now - (event.end - event.start);
+
+          for start in expand_recurrence(event.start, recurrence, from, horizon)? {
+            if !overridden.contains(&start) {
+              occurrences.push((start, start + event.end - event.start));
+            }
+          }
+        }
+        _ => occurrences.push((event.start, event.end)),
+      }
+    }
+
+    let mut time_slots = Vec::new();
+
+    for (start, end) in occurrences.into_iter() {
+      if end <= now || start >= horizon {
+        continue;
+      }
+
+      time_slots.extend(split_by_day(start, end)?);
+    }
+
+    time_slots.sort();
+    time_slots.dedup();
+
+    calendar_events.push(CalendarEvent {
+      uid,
+      summary,
+      time_slots,
+    });
+  }
+
+  Ok(calendar_events)
+}
+
+fn parse_event(event: &IcalEvent) -> Result<ParsedEvent, Status> {
+  let dtstart = get_property(event, "DTSTART").ok_or_else(|| {
+    log::error!("Found VEVENT without DTSTART");
+    Status::UnprocessableEntity
+  })?;
+  let (start, all_day) = parse_datetime_property(dtstart)?;
+
+  let end = match (
+    get_property(event, "DTEND"),
+    get_property(event, "DURATION"),
+  ) {
+    (Some(dtend), _) => parse_datetime_property(dtend)?.0,
+    (None, Some(duration)) => start + parse_duration(property_value(duration)?)?,
+    // 沒有結束時間的全天事件持續一天
+    (None, None) if all_day => start + Duration::days(1).num_seconds(),
+    (None, None) => start,
+  };
+
+  if end < start {
+    log::error!("Found VEVENT whose DTEND is earlier than DTSTART");
+    return Err(Status::UnprocessableEntity);
+  }
+
+  let cancelled = get_property(event, "STATUS")
+    .and_then(|property| property.value.as_deref())
+    .is_some_and(|status| status.eq_ignore_ascii_case("CANCELLED"));
+
+  let recurrence_id = match get_property(event, "RECURRENCE-ID") {
+    Some(property) => Some(parse_datetime_property(property)?.0),
+    None => None,
+  };
+
+  Ok(ParsedEvent {
+    summary: get_property(event, "SUMMARY").and_then(|property| property.value.clone()),
+    start,
+    end,
+    cancelled,
+    recurrence: build_recurrence(event)?,
+    recurrence_id,
+  })
+}
+
+// 將 RRULE / RDATE / EXDATE 轉換為以 UTC 表示的字串，供 rrule 展開
+fn build_recurrence(event: &IcalEvent) -> Result<Option<String>, Status> {
+  let mut lines = Vec::new();
+
+  for property in event.properties.iter() {
+    match property.name.as_str() {
+      "RRULE" => {
+        let mut parts = Vec::new();
+
+        for part in property_value(property)?.split(';') {
+          match part.split_once('=') {
+            Some(("UNTIL", until)) => {
+              let until = parse_datetime(until, get_param(property, "TZID"), false)?;
+              parts.push(format!("UNTIL={}", format_utc(until)?));
+            }
+            _ => parts.push(part.to_string()),
+          }
+        }
+
+        lines.push(format!("RRULE:{}", parts.join(";")));
+      }
+      "RDATE" | "EXDATE" => {
+        let value_is_date = get_param(property, "VALUE") == Some("DATE");
+        let mut dates = Vec::new();
+
+        for value in property_value(property)?.split(',') {
+          let timestamp = parse_datetime(value, get_param(property, "TZID"), value_is_date)?;
+          dates.push(format_utc(timestamp)?);
+        }
+
+        lines.push(format!("{}:{}", property.name, dates.join(",")));
+      }
+      _ => {}
+    }
+  }
+
+  if lines.iter().any(|line| line.starts_with("RRULE:")) {
+    Ok(Some(lines.join("\n")))
+  } else {
+    Ok(None)
+  }
+}
+
+// 展開重複事件在 from ~ horizon 之間開始的實例
+fn expand_recurrence(
+  start: i64,
+  recurrence: &str,
+  from: i64,
+  horizon: i64,
+) -> Result<Vec<i64>, Status> {
+  let rrule_set = format!("DTSTART:{}\n{}", format_utc(start)?, recurrence);
+
+  let rrule_set = RRuleSet::from_str(&rrule_set).map_err(|e| {
+    log::error!("Parsing recurrence rule failed with error: {}", e);
+    Status::UnprocessableEntity
+  })?;
+
+  let to_rrule_datetime = |timestamp: i64| {
+    Utc
+      .timestamp_opt(timestamp, 0)
+      .single()
+      .map(|datetime| datetime.with_timezone(&Tz::UTC))
+      .ok_or(Status::InternalServerError)
+  };
+
+  let occurrences = rrule_set
+    .after(to_rrule_datetime(from)?)
+    .before(to_rrule_datetime(horizon)?)
+    .all(MAX_OCCURRENCES)
+    .dates
+    .into_iter()
+    .map(|date| date.timestamp())
+    .collect();
+
+  Ok(occurrences)
+}
+
+// 將跨日的事件切割為每天一段，與開放時間產生的時段格式一致
+fn split_by_day(start: i64, end: i64) -> Result<Vec<(i64, i64)>, Status> {
+  let first_date = timestamp_to_naive_datetime(start)?.date();
+  let last_date = timestamp_to_naive_datetime(end)?.date();
+
+  let mut time_slots = Vec::new();
+
+  for date in first_date.iter_days().take_while(|date| *date <= last_date) {
+    let day_start = naive_date_to_timestamp(date, 0, 0, 0)?;
+    let day_end = naive_date_to_timestamp(date, 23, 59, 59)?;
+
+    let slot_start = start.max(day_start);
+    let slot_end = end.min(day_end);
+
+    if slot_start < slot_end {
+      time_slots.push((slot_start, slot_end));
+    }
+  }
+
+  Ok(time_slots)
+}
+
+fn get_property<'a>(event: &'a IcalEvent, name: &str) -> Option<&'a Property> {
+  event
+    .properties
+    .iter()
+    .find(|property| property.name == name)
+}
+
+fn get_param<'a>(property: &'a Property, name: &str) -> Option<&'a str> {
+  property
+    .params
+    .as_ref()?
+    .iter()
+    .find(|(param, _)| param == name)
+    .and_then(|(_, values)| values.first())
+    .map(|value| value.as_str())
+}
+
+fn property_value(property: &Property) -> Result<&str, Status> {
+  property.value.as_deref().ok_or_else(|| {
+    log::error!("Property {} has no value", property.name);
+    Status::UnprocessableEntity
+  })
+}
+
+// 回傳 (timestamp, 是否為全天事件)
+fn parse_datetime_property(property: &Property) -> Result<(i64, bool), Status> {
+  let value = property_value(property)?;
+  let all_day = get_param(property, "VALUE") == Some("DATE") || value.len() == 8;
+
+  let timestamp = parse_datetime(value, get_param(property, "TZID"), all_day)?;
+
+  Ok((timestamp, all_day))
+}
+
+fn parse_datetime(value: &str, tzid: Option<&str>, is_date: bool) -> Result<i64, Status> {
+  // 全天事件以本地時間的午夜為準
+  if is_date || value.len() == 8 {
+    let date = handle(
+      NaiveDate::parse_from_str(value, "%Y%m%d"),
+      &format!("Parsing iCalendar date '{}'", value),
+    )
+    .map_err(|_| Status::UnprocessableEntity)?;
+
+    return naive_date_to_timestamp(date, 0, 0, 0);
+  }
+
+  if let Some(value) = value.strip_suffix('Z') {
+    let datetime = parse_naive_datetime(value)?;
+
+    return Ok(datetime.timestamp());
+  }
+
+  let datetime = parse_naive_datetime(value)?;
+
+  match tzid {
+    Some(tzid) => {
+      let tz = chrono_tz::Tz::from_str(tzid).map_err(|e| {
+        log::error!("Unknown TZID '{}': {}", tzid, e);
+        Status::UnprocessableEntity
+      })?;
+
+      let datetime = tz
+        .from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| {
+          log::error!("Invalid local time '{}' in TZID '{}'", value, tzid);
+          Status::UnprocessableEntity
+        })?;
+
+      Ok(datetime.timestamp())
+    }
+    // 沒有時區資訊的時間視為本地時間
+    None => naive_datetime_to_timestamp(datetime),
+  }
+}
+
+fn parse_naive_datetime(value: &str) -> Result<NaiveDateTime, Status> {
+  handle(
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S"),
+    &format!("Parsing iCalendar datetime '{}'", value),
+  )
+  .map_err(|_| Status::UnprocessableEntity)
+}
+
+// 解析 DURATION，例如 P1D、PT1H30M、P1W
+fn parse_duration(value: &str) -> Result<i64, Status> {
+  let invalid = || {
+    log::error!("Invalid iCalendar duration '{}'", value);
+    Status::UnprocessableEntity
+  };
+
+  let (sign, rest) = match value.strip_prefix('-') {
+    Some(rest) => (-1, rest),
+    None => (1, value.strip_prefix('+').unwrap_or(value)),
+  };
+  let rest = rest.strip_prefix('P').ok_or_else(invalid)?;
+
+  let mut seconds: i64 = 0;
+  let mut number = String::new();
+
+  for c in rest.chars() {
+    match c {
+      '0'..='9' => number.push(c),
+      'T' => continue,
+      'W' | 'D' | 'H' | 'M' | 'S' => {
+        let n: i64 = number.parse().map_err(|_| invalid())?;
+        number.clear();
+
+        seconds += n
+          * match c {
+            'W' => 7 * 24 * 3600,
+            'D' => 24 * 3600,
+            'H' => 3600,
+            'M' => 60,
+            _ => 1,
+          };
+      }
+      _ => return Err(invalid()),
+    }
+  }
+
+  Ok(sign * seconds)
+}
+
+fn format_utc(timestamp: i64) -> Result<String, Status> {
+  let datetime = NaiveDateTime::from_timestamp_opt(timestamp, 0).ok_or_else(|| {
+    log::error!("Invalid timestamp");
+    Status::InternalServerError
+  })?;
+
+  Ok(datetime.format("%Y%m%dT%H%M%SZ").to_string())
+}
+
+// 跳脫 TEXT 型別中的特殊字元
+fn escape_text(text: &str) -> String {
+  text
+    .replace('\\', "\\\\")
+    .replace(';', "\\;")
+    .replace(',', "\\,")
+    .replace('\n', "\\n")
+}
+
+// 每行超過 75 bytes 時折行，折行以空白開頭
+fn fold_line(line: &str) -> String {
+  let mut folded = String::new();
+  let mut length = 0;
+
+  for c in line.chars() {
+    if length + c.len_utf8() > 75 {
+      folded.push_str("\r\n ");
+      length = 1;
+    }
+
+    folded.push(c);
+    length += c.len_utf8();
+  }
+
+  folded
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DAY: i64 = 24 * 60 * 60;
+
+  // 2024-01-01 09:00:00 UTC
+  const START: i64 = 1704099600;
+
+  #[test]
+  fn expands_occurrences_within_window() {
+    let occurrences = expand_recurrence(
+      START,
+      "RRULE:FREQ=DAILY;COUNT=5",
+      START - 1,
+      START + 10 * DAY,
+    )
+    .unwrap();
+
+    assert_eq!(
+      occurrences,
+      (0..5).map(|day| START + day * DAY).collect::<Vec<_>>()
+    );
+  }
+
+  #[test]
+  fn skips_occurrences_before_from_and_after_horizon() {
+    let occurrences = expand_recurrence(
+      START,
+      "RRULE:FREQ=DAILY",
+      START + 2 * DAY - 60,
+      START + 4 * DAY + 60,
+    )
+    .unwrap();
+
+    assert_eq!(
+      occurrences,
+      vec![START + 2 * DAY, START + 3 * DAY, START + 4 * DAY]
+    );
+  }
+
+  #[test]
+  fn excludes_exdates() {
+    let recurrence = format!(
+      "RRULE:FREQ=DAILY;COUNT=3\nEXDATE:{}",
+      format_utc(START + DAY).unwrap()
+    );
+    let occurrences = expand_recurrence(START, &recurrence, START - 1, START + 10 * DAY).unwrap();
+
+    assert_eq!(occurrences, vec![START, START + 2 * DAY]);
+  }
+
+  #[test]
+  fn rejects_invalid_recurrence() {
+    assert_eq!(
+      expand_recurrence(START, "RRULE:FREQ=SOMETIMES", START, START + DAY).unwrap_err(),
+      Status::UnprocessableEntity
+    );
+  }
+}
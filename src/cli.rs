@@ -0,0 +1,37 @@
+use crate::{calendar, model::constant::SYSTEM_USER};
+use sqlx::{Pool, Sqlite};
+use std::fs;
+
+// 命令列工具，例如: study_seat_reserve import-ics closures.ics
+pub async fn run(pool: &Pool<Sqlite>, args: &[String]) {
+  match args.first().map(String::as_str) {
+    Some("import-ics") if args.len() == 2 => import_ics(pool, &args[1]).await,
+    _ => {
+      eprintln!("Usage: study_seat_reserve import-ics <path>");
+      std::process::exit(2);
+    }
+  }
+}
+
+async fn import_ics(pool: &Pool<Sqlite>, path: &str) {
+  log::info!("Importing iCalendar file: {}", path);
+
+  let content = fs::read_to_string(path).unwrap_or_else(|e| {
+    log::error!("Reading file '{}' failed with err: {:?}", path, e);
+    std::process::exit(1);
+  });
+
+  match calendar::import_calendar(pool, &content, SYSTEM_USER).await {
+    Ok(result) => {
+      log::info!("Imported iCalendar file: {}", path);
+      println!(
+        "events: {}, created: {}, updated: {}, unchanged: {}, conflicts: {}",
+        result.events, result.created, result.updated, result.unchanged, result.conflicts
+      );
+    }
+    Err(status) => {
+      log::error!("Importing iCalendar file failed with status: {}", status);
+      std::process::exit(1);
+    }
+  }
+}
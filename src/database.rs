@@ -1,6 +1,51 @@
+pub mod api_key;
+pub mod calendar_feed;
 mod common;
 pub mod init;
+pub mod issue;
+pub mod login_throttle;
+pub mod oidc;
+pub mod opening_hours;
 pub mod reservation;
 pub mod seat;
+pub mod session;
+pub mod timer;
 pub mod timeslot;
+pub mod totp;
 pub mod user;
+
+// 測試用的資料庫，每次建立新的暫存檔案並初始化資料表
+#[cfg(test)]
+pub async fn connect_test_pool() -> sqlx::Pool<sqlx::Sqlite> {
+  let pool = create_test_pool().await;
+
+  init::init_db(&pool).await;
+
+  pool
+}
+
+// 尚未初始化的測試用資料庫，用於測試舊版資料表的遷移
+#[cfg(test)]
+pub async fn create_test_pool() -> sqlx::Pool<sqlx::Sqlite> {
+  let dir = std::env::temp_dir().join("study_seat_reserve_test");
+  std::fs::create_dir_all(&dir).expect("Failed to create test directory");
+
+  // 測試平行執行，環境變數只在第一次建立時設定一次
+  static SET_ENV: std::sync::Once = std::sync::Once::new();
+  SET_ENV.call_once(|| {
+    std::env::set_var("ROOT", &dir);
+    std::env::set_var("SECRET_KEY", "test-secret-key");
+    std::env::set_var("ADMIN_PASSWORD", "adminpass123");
+    std::env::set_var("ADMIN_EMAIL", "admin@example.com");
+    std::env::set_var("BASE_URL", "http://localhost");
+  });
+
+  let path = dir.join(format!("{}.db3", uuid::Uuid::new_v4().simple()));
+
+  let pool = sqlx::pool::PoolOptions::new()
+    .connect(&format!("sqlite:{}?mode=rwc", path.display()))
+    .await
+    .expect("Failed to create test pool");
+
+  pool
+}
@@ -0,0 +1,137 @@
+use super::common::*;
+use crate::model::{api_key, permission::Permission};
+
+pub async fn insert_api_key(
+  pool: &Pool<Sqlite>,
+  key_id: &str,
+  name: &str,
+  key_hash: &str,
+  user_name: &str,
+  scopes: &[Permission],
+  created_by: &str,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+  let scopes = api_key::format_scopes(scopes);
+
+  handle_sqlx(
+    query!(
+      "INSERT INTO ApiKeys
+        (key_id, name, key_hash, user_name, scopes, created_by, created_at)
+      VALUES
+        (?, ?, ?, ?, ?, ?, ?)",
+      key_id,
+      name,
+      key_hash,
+      user_name,
+      scopes,
+      created_by,
+      now,
+    )
+    .execute(pool)
+    .await,
+    "Inserting API key",
+  )?;
+
+  Ok(())
+}
+
+pub async fn get_api_keys(pool: &Pool<Sqlite>) -> Result<Vec<api_key::ApiKey>, Status> {
+  let api_keys = handle_sqlx(
+    query_as::<_, api_key::ApiKey>(
+      "SELECT
+        key_id, name, user_name, scopes, created_by, created_at, last_used_at, revoked_at
+      FROM
+        ApiKeys
+      ORDER BY
+        created_at",
+    )
+    .fetch_all(pool)
+    .await,
+    "Selecting API keys",
+  )?;
+
+  Ok(api_keys)
+}
+
+// 查詢使用者擁有或建立的 API key
+pub async fn get_user_api_keys(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Vec<api_key::ApiKey>, Status> {
+  let api_keys = handle_sqlx(
+    query_as::<_, api_key::ApiKey>(
+      "SELECT
+        key_id, name, user_name, scopes, created_by, created_at, last_used_at, revoked_at
+      FROM
+        ApiKeys
+      WHERE
+        user_name = ?1 OR
+        created_by = ?1
+      ORDER BY
+        created_at",
+    )
+    .bind(user_name)
+    .fetch_all(pool)
+    .await,
+    "Selecting API keys of the user",
+  )?;
+
+  Ok(api_keys)
+}
+
+pub async fn revoke_api_key(pool: &Pool<Sqlite>, key_id: &str) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE ApiKeys
+      SET
+        revoked_at = ?
+      WHERE
+        key_id = ? AND
+        revoked_at IS NULL",
+      now,
+      key_id,
+    )
+    .execute(pool)
+    .await,
+    "Revoking API key",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No active API key found with key id: {}", key_id);
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 以 API key 的雜湊值查詢未撤銷的 key，並記錄最後使用時間
+pub async fn use_api_key(
+  pool: &Pool<Sqlite>,
+  key_hash: &str,
+) -> Result<Option<api_key::ApiKey>, Status> {
+  let now = get_now_timestamp();
+
+  let api_key = handle_sqlx(
+    query_as::<_, api_key::ApiKey>(
+      "UPDATE ApiKeys
+      SET
+        last_used_at = ?
+      WHERE
+        key_hash = ? AND
+        revoked_at IS NULL AND
+        user_name NOT IN (SELECT user_name FROM Users WHERE disabled = true)
+      RETURNING
+        key_id, name, user_name, scopes, created_by, created_at, last_used_at, revoked_at",
+    )
+    .bind(now)
+    .bind(key_hash)
+    .fetch_optional(pool)
+    .await,
+    "Using API key",
+  )?;
+
+  Ok(api_key)
+}
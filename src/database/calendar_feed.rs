@@ -0,0 +1,61 @@
+use super::common::*;
+
+// 建立或更換使用者的行事曆訂閱 token，舊的連結會失效
+pub async fn upsert_calendar_feed_token(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  token: &str,
+) -> Result<(), Status> {
+  let created_at = get_now_timestamp();
+
+  handle_sqlx(
+    query!(
+      "INSERT OR REPLACE INTO CalendarFeeds
+        (user_name, token, created_at)
+      VALUES
+        (?, ?, ?)",
+      user_name,
+      token,
+      created_at,
+    )
+    .execute(pool)
+    .await,
+    "Upserting calendar feed token",
+  )?;
+
+  Ok(())
+}
+
+pub async fn delete_calendar_feed_token(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!("DELETE FROM CalendarFeeds WHERE user_name = ?", user_name)
+      .execute(pool)
+      .await,
+    "Deleting calendar feed token",
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，此次操作無作用到任何資料
+  if affected_rows == 0 {
+    log::warn!("No calendar feed found for deletion");
+
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+pub async fn get_calendar_feed_user(pool: &Pool<Sqlite>, token: &str) -> Result<String, Status> {
+  let user_name = handle_sqlx(
+    query_scalar::<_, String>("SELECT user_name FROM CalendarFeeds WHERE token = ?")
+      .bind(token)
+      .fetch_one(pool)
+      .await,
+    "Selecting calendar feed user",
+  )?;
+
+  Ok(user_name)
+}
@@ -1,11 +1,85 @@
+use sqlx::Connection;
 use std::env;
 
-use super::{
-  common::*,
-  timeslot::{insert_unavailable_timeslot, is_overlapping_with_unavailable_timeslot},
-};
-use bcrypt::{hash, DEFAULT_COST};
-use chrono::Datelike;
+use super::{common::*, opening_hours::materialize_closures};
+
+const CREATE_RESERVATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS Reservations (
+  user_name TEXT NOT NULL,
+  seat_id INTEGER NOT NULL,
+  start_time INTEGER NOT NULL,
+  end_time INTEGER NOT NULL,
+  uid TEXT,
+  sequence INTEGER NOT NULL DEFAULT 0,
+  cancelled_at INTEGER,
+  checked_in_at INTEGER,
+  PRIMARY KEY (user_name, start_time, end_time),
+  FOREIGN KEY(user_name) REFERENCES Users(user_name),
+  FOREIGN KEY(seat_id) REFERENCES Seats(seat_id)
+)";
+
+const CREATE_UNAVAILABLE_TIMESLOTS_TABLE: &str = "CREATE TABLE IF NOT EXISTS UnavailableTimeSlots (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  start_time INTEGER NOT NULL,
+  end_time INTEGER NOT NULL,
+  reason TEXT,
+  created_by TEXT,
+  source_uid TEXT,
+  UNIQUE (start_time, end_time)
+)";
+
+// 每次停權一筆紀錄，解除或被新的停權取代時記錄 lifted_at，保留完整的停權歷史
+const CREATE_BLACKLIST_TABLE: &str = "CREATE TABLE IF NOT EXISTS BlackList (
+  ban_id INTEGER PRIMARY KEY AUTOINCREMENT,
+  user_name TEXT NOT NULL,
+  start_time INTEGER NOT NULL,
+  end_time INTEGER NOT NULL,
+  reason TEXT,
+  created_by TEXT,
+  created_at INTEGER,
+  lifted_at INTEGER,
+  lifted_by TEXT,
+  FOREIGN KEY(user_name) REFERENCES Users(user_name)
+)";
+
+const CREATE_SEAT_MAINTENANCE_TABLE: &str = "CREATE TABLE IF NOT EXISTS SeatMaintenance (
+  seat_id INTEGER NOT NULL,
+  start_time INTEGER NOT NULL,
+  end_time INTEGER NOT NULL,
+  issue_id INTEGER,
+  PRIMARY KEY (seat_id, start_time),
+  FOREIGN KEY(seat_id) REFERENCES Seats(seat_id),
+  FOREIGN KEY(issue_id) REFERENCES SeatIssues(issue_id)
+)";
+
+const CREATE_SEAT_ISSUES_TABLE: &str = "CREATE TABLE IF NOT EXISTS SeatIssues (
+  issue_id INTEGER PRIMARY KEY AUTOINCREMENT,
+  seat_id INTEGER NOT NULL,
+  user_name TEXT NOT NULL,
+  category TEXT NOT NULL,
+  description TEXT NOT NULL,
+  photo_path TEXT,
+  status TEXT NOT NULL,
+  assignee TEXT,
+  resolution TEXT,
+  created_at INTEGER NOT NULL,
+  resolved_at INTEGER,
+  FOREIGN KEY(seat_id) REFERENCES Seats(seat_id),
+  FOREIGN KEY(user_name) REFERENCES Users(user_name),
+  FOREIGN KEY(assignee) REFERENCES Users(user_name)
+)";
+
+const CREATE_TIMER_RUNS_TABLE: &str = "CREATE TABLE IF NOT EXISTS TimerRuns (
+  task TEXT PRIMARY KEY,
+  last_run INTEGER NOT NULL,
+  materialized_until TEXT NOT NULL
+)";
+
+const CREATE_CALENDAR_FEEDS_TABLE: &str = "CREATE TABLE IF NOT EXISTS CalendarFeeds (
+  user_name TEXT PRIMARY KEY,
+  token TEXT NOT NULL UNIQUE,
+  created_at INTEGER NOT NULL,
+  FOREIGN KEY(user_name) REFERENCES Users(user_name)
+)";
 
 pub async fn init_db(pool: &Pool<Sqlite>) {
   log::info!("Initializing db");
@@ -42,56 +116,343 @@ pub async fn init_db(pool: &Pool<Sqlite>) {
     panic!("Failed to create Users table");
   });
 
+  // 變更 email 時，新的 email 在驗證前先暫存於此
+  add_column_if_not_exists(pool, "Users", "pending_email", "TEXT").await;
+  // 以 OIDC 登入時，識別身分提供者帳號的 subject
+  add_column_if_not_exists(pool, "Users", "oidc_subject", "TEXT").await;
+  // 兩步驟驗證(TOTP)的密鑰、是否啟用，以及最後使用的時間步數(防止同一組驗證碼重複使用)
+  add_column_if_not_exists(pool, "Users", "totp_secret", "TEXT").await;
+  add_column_if_not_exists(
+    pool,
+    "Users",
+    "totp_enabled",
+    "BOOLEAN NOT NULL DEFAULT false",
+  )
+  .await;
+  add_column_if_not_exists(pool, "Users", "totp_last_step", "INTEGER").await;
+  // email 驗證 token 的期限與最後寄送時間(限制重寄頻率)，以及註冊時間(清除未驗證的帳號)
+  add_column_if_not_exists(pool, "Users", "verification_token_expires_at", "INTEGER").await;
+  add_column_if_not_exists(pool, "Users", "verification_sent_at", "INTEGER").await;
+  add_column_if_not_exists(pool, "Users", "created_at", "INTEGER").await;
+  // 被管理員停用的帳號無法登入，直到重新啟用
+  add_column_if_not_exists(pool, "Users", "disabled", "BOOLEAN NOT NULL DEFAULT false").await;
+  // 刪除帳號(匿名化)的時間，已刪除的帳號不會被當成未驗證的帳號
+  add_column_if_not_exists(pool, "Users", "deleted_at", "INTEGER").await;
+
+  sqlx::query(CREATE_RESERVATIONS_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create Reservations table: {}", e);
+      panic!("Failed to create Reservations table");
+    });
+
+  // 行事曆訂閱使用的事件 UID、修改次數與取消時間，取消的預約保留資料以通知行事曆
+  add_column_if_not_exists(pool, "Reservations", "uid", "TEXT").await;
+  add_column_if_not_exists(
+    pool,
+    "Reservations",
+    "sequence",
+    "INTEGER NOT NULL DEFAULT 0",
+  )
+  .await;
+  add_column_if_not_exists(pool, "Reservations", "cancelled_at", "INTEGER").await;
+  // 在現場報到的時間
+  add_column_if_not_exists(pool, "Reservations", "checked_in_at", "INTEGER").await;
+
+  sqlx::query("UPDATE Reservations SET uid = lower(hex(randomblob(16))) WHERE uid IS NULL")
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to fill uid of Reservations: {}", e);
+      panic!("Failed to fill uid of Reservations");
+    });
+
+  sqlx::query(CREATE_UNAVAILABLE_TIMESLOTS_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create UnavailableTimeSlots table: {}", e);
+      panic!("Failed to create UnavailableTimeSlots table");
+    });
+
+  // 匯入 iCalendar 的事件 UID，用於重新匯入時更新而非重複新增
+  add_column_if_not_exists(pool, "UnavailableTimeSlots", "source_uid", "TEXT").await;
+  migrate_unavailable_timeslots(pool).await;
+
+  sqlx::query(CREATE_BLACKLIST_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create BlackList table: {}", e);
+      panic!("Failed to create BlackList table");
+    });
+
+  sqlx::query(CREATE_SEAT_ISSUES_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create SeatIssues table: {}", e);
+      panic!("Failed to create SeatIssues table");
+    });
+
+  sqlx::query(CREATE_SEAT_MAINTENANCE_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create SeatMaintenance table: {}", e);
+      panic!("Failed to create SeatMaintenance table");
+    });
+
   sqlx::query(
-    "CREATE TABLE IF NOT EXISTS Reservations (
-            user_name TEXT NOT NULL,
-            seat_id INTEGER NOT NULL,
-            start_time TEXT NOT NULL,
-            end_time TEXT NOT NULL,
-            PRIMARY KEY (user_name, start_time, end_time),
-            FOREIGN KEY(user_name) REFERENCES Users(user_name),
-            FOREIGN KEY(seat_id) REFERENCES Seats(seat_id)
-          )",
+    "CREATE TABLE IF NOT EXISTS OpeningHours (
+      weekday INTEGER PRIMARY KEY,
+      closed BOOLEAN NOT NULL,
+      open_time TEXT,
+      close_time TEXT
+    )",
   )
   .execute(pool)
   .await
   .unwrap_or_else(|e| {
-    log::error!("Failed to create Reservations table: {}", e);
-    panic!("Failed to create Reservations table");
+    log::error!("Failed to create OpeningHours table: {}", e);
+    panic!("Failed to create OpeningHours table");
   });
 
   sqlx::query(
-    "CREATE TABLE IF NOT EXISTS UnavailableTimeSlots (
-      start_time TEXT NOT NULL,
-      end_time TEXT NOT NULL,
-      PRIMARY KEY (start_time, end_time)
+    "CREATE TABLE IF NOT EXISTS OpeningHoursExceptions (
+      date TEXT PRIMARY KEY,
+      closed BOOLEAN NOT NULL,
+      open_time TEXT,
+      close_time TEXT,
+      reason TEXT
     )",
   )
   .execute(pool)
   .await
   .unwrap_or_else(|e| {
-    log::error!("Failed to create UnavailableTimeSlots table: {}", e);
-    panic!("Failed to create UnavailableTimeSlots table");
+    log::error!("Failed to create OpeningHoursExceptions table: {}", e);
+    panic!("Failed to create OpeningHoursExceptions table");
   });
 
+  // 記錄排程最後一次成功執行的時間，以及已產生不可預約時段到哪一天
+  sqlx::query(CREATE_TIMER_RUNS_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create TimerRuns table: {}", e);
+      panic!("Failed to create TimerRuns table");
+    });
+
+  sqlx::query(CREATE_CALENDAR_FEEDS_TABLE)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to create CalendarFeeds table: {}", e);
+      panic!("Failed to create CalendarFeeds table");
+    });
+
   sqlx::query(
-    "CREATE TABLE IF NOT EXISTS BlackList (
+    "CREATE TABLE IF NOT EXISTS PasswordResetTokens (
+      token_hash TEXT PRIMARY KEY,
       user_name TEXT NOT NULL,
-      start_time TEXT NOT NULL,
-      end_time TEXT NOT NULL,
-      PRIMARY KEY (user_name),
+      expires_at INTEGER NOT NULL,
       FOREIGN KEY(user_name) REFERENCES Users(user_name)
     )",
   )
   .execute(pool)
   .await
   .unwrap_or_else(|e| {
-    log::error!("Failed to create BlackList table: {}", e);
-    panic!("Failed to create BlackList table");
+    log::error!("Failed to create PasswordResetTokens table: {}", e);
+    panic!("Failed to create PasswordResetTokens table");
   });
 
+  // 登入的 session，refresh token 只儲存雜湊值，每次換發後舊的 token 即失效
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS Sessions (
+      session_id TEXT PRIMARY KEY,
+      user_name TEXT NOT NULL,
+      refresh_token_hash TEXT NOT NULL UNIQUE,
+      previous_refresh_token_hash TEXT,
+      created_at INTEGER NOT NULL,
+      expires_at INTEGER NOT NULL,
+      revoked_at INTEGER,
+      FOREIGN KEY(user_name) REFERENCES Users(user_name)
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create Sessions table: {}", e);
+    panic!("Failed to create Sessions table");
+  });
+
+  // 依帳號與 IP 記錄連續登入失敗的次數，以及在何時之前拒絕登入
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS LoginThrottles (
+      kind TEXT NOT NULL,
+      subject TEXT NOT NULL,
+      failed_count INTEGER NOT NULL,
+      last_failed_at INTEGER NOT NULL,
+      blocked_until INTEGER,
+      PRIMARY KEY (kind, subject)
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create LoginThrottles table: {}", e);
+    panic!("Failed to create LoginThrottles table");
+  });
+
+  // OIDC 登入流程中尚未完成的授權請求
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS OidcStates (
+      state TEXT PRIMARY KEY,
+      nonce TEXT NOT NULL,
+      code_verifier TEXT NOT NULL,
+      expires_at INTEGER NOT NULL
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create OidcStates table: {}", e);
+    panic!("Failed to create OidcStates table");
+  });
+
+  // 兩步驟驗證的備用碼，只儲存雜湊值，每組只能使用一次
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS TotpRecoveryCodes (
+      user_name TEXT NOT NULL,
+      code_hash TEXT NOT NULL,
+      used_at INTEGER,
+      PRIMARY KEY (user_name, code_hash),
+      FOREIGN KEY(user_name) REFERENCES Users(user_name)
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create TotpRecoveryCodes table: {}", e);
+    panic!("Failed to create TotpRecoveryCodes table");
+  });
+
+  // 已通過密碼驗證、等待輸入兩步驟驗證碼的登入
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS TotpChallenges (
+      challenge_hash TEXT PRIMARY KEY,
+      user_name TEXT NOT NULL,
+      expires_at INTEGER NOT NULL,
+      FOREIGN KEY(user_name) REFERENCES Users(user_name)
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create TotpChallenges table: {}", e);
+    panic!("Failed to create TotpChallenges table");
+  });
+
+  // 管理員建立給自助機、門禁系統等使用的 API key，只儲存雜湊值
+  sqlx::query(
+    "CREATE TABLE IF NOT EXISTS ApiKeys (
+      key_id TEXT PRIMARY KEY,
+      name TEXT NOT NULL,
+      key_hash TEXT NOT NULL UNIQUE,
+      user_name TEXT NOT NULL,
+      scopes TEXT NOT NULL,
+      created_by TEXT NOT NULL,
+      created_at INTEGER NOT NULL,
+      last_used_at INTEGER,
+      revoked_at INTEGER,
+      FOREIGN KEY(user_name) REFERENCES Users(user_name)
+    )",
+  )
+  .execute(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to create ApiKeys table: {}", e);
+    panic!("Failed to create ApiKeys table");
+  });
+
+  // 舊版以本地時間文字儲存的時間欄位，轉換為 UTC timestamp
+  migrate_time_columns(
+    pool,
+    "Reservations",
+    CREATE_RESERVATIONS_TABLE,
+    &["start_time", "end_time", "cancelled_at"],
+  )
+  .await;
+  migrate_time_columns(
+    pool,
+    "UnavailableTimeSlots",
+    CREATE_UNAVAILABLE_TIMESLOTS_TABLE,
+    &["start_time", "end_time"],
+  )
+  .await;
+  migrate_time_columns(
+    pool,
+    "BlackList",
+    CREATE_BLACKLIST_TABLE,
+    &["start_time", "end_time"],
+  )
+  .await;
+  migrate_time_columns(
+    pool,
+    "SeatMaintenance",
+    CREATE_SEAT_MAINTENANCE_TABLE,
+    &["start_time", "end_time"],
+  )
+  .await;
+  migrate_time_columns(
+    pool,
+    "SeatIssues",
+    CREATE_SEAT_ISSUES_TABLE,
+    &["created_at", "resolved_at"],
+  )
+  .await;
+  migrate_time_columns(pool, "TimerRuns", CREATE_TIMER_RUNS_TABLE, &["last_run"]).await;
+  migrate_time_columns(
+    pool,
+    "CalendarFeeds",
+    CREATE_CALENDAR_FEEDS_TABLE,
+    &["created_at"],
+  )
+  .await;
+  migrate_blacklist(pool).await;
+
+  // 查詢座位時段是否重疊、使用者的預約紀錄、重設密碼 token、session、OIDC 帳號、停權紀錄使用的索引
+  let indexes = [
+    "CREATE INDEX IF NOT EXISTS idx_reservations_seat_time
+      ON Reservations (seat_id, start_time, end_time)",
+    "CREATE INDEX IF NOT EXISTS idx_reservations_user_time
+      ON Reservations (user_name, start_time)",
+    "CREATE INDEX IF NOT EXISTS idx_password_reset_tokens_user
+      ON PasswordResetTokens (user_name)",
+    "CREATE INDEX IF NOT EXISTS idx_sessions_user
+      ON Sessions (user_name)",
+    "CREATE UNIQUE INDEX IF NOT EXISTS idx_users_oidc_subject
+      ON Users (oidc_subject)",
+    "CREATE INDEX IF NOT EXISTS idx_totp_challenges_user
+      ON TotpChallenges (user_name)",
+    "CREATE INDEX IF NOT EXISTS idx_users_verification_token
+      ON Users (verification_token)",
+    "CREATE INDEX IF NOT EXISTS idx_blacklist_user_time
+      ON BlackList (user_name, start_time)",
+  ];
+
+  for sql in indexes {
+    query(sql).execute(pool).await.unwrap_or_else(|e| {
+      log::error!("Failed to create index: {}", e);
+      panic!("Failed to create index: {}", e);
+    });
+  }
+
   init_seat_info(&pool).await;
 
+  init_opening_hours(pool).await;
+
   init_unavailable_timeslots(&pool).await;
 
   insert_admin(&pool).await;
@@ -99,6 +460,310 @@ pub async fn init_db(pool: &Pool<Sqlite>) {
   log::info!("Successfully initialized db");
 }
 
+// 為既有的資料庫補上新增的欄位
+async fn add_column_if_not_exists(
+  pool: &Pool<Sqlite>,
+  table_name: &str,
+  column_name: &str,
+  column_definition: &str,
+) {
+  let exists: bool =
+    query_scalar("SELECT EXISTS(SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2)")
+      .bind(table_name)
+      .bind(column_name)
+      .fetch_one(pool)
+      .await
+      .unwrap_or_else(|e| {
+        log::error!("Failed to query columns of {} table: {}", table_name, e);
+        panic!("Failed to query columns of {} table: {}", table_name, e);
+      });
+
+  if exists {
+    return;
+  }
+
+  log::info!("Adding column {} to {} table", column_name, table_name);
+
+  let sql = format!(
+    "ALTER TABLE {} ADD COLUMN {} {}",
+    table_name, column_name, column_definition
+  );
+
+  query(&sql).execute(pool).await.unwrap_or_else(|e| {
+    log::error!(
+      "Failed to add column {} to {} table: {}",
+      column_name,
+      table_name,
+      e
+    );
+    panic!(
+      "Failed to add column {} to {} table: {}",
+      column_name, table_name, e
+    );
+  });
+}
+
+// 舊版的 UnavailableTimeSlots 以 (start_time, end_time) 為主鍵，
+// 重建資料表以加入 id、原因與建立者
+async fn migrate_unavailable_timeslots(pool: &Pool<Sqlite>) {
+  let has_id: bool = query_scalar(
+    "SELECT EXISTS(SELECT 1 FROM pragma_table_info('UnavailableTimeSlots') WHERE name = 'id')",
+  )
+  .fetch_one(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!(
+      "Failed to query columns of UnavailableTimeSlots table: {}",
+      e
+    );
+    panic!(
+      "Failed to query columns of UnavailableTimeSlots table: {}",
+      e
+    );
+  });
+
+  if has_id {
+    return;
+  }
+
+  log::info!("Migrating UnavailableTimeSlots table");
+
+  let statements = [
+    "ALTER TABLE UnavailableTimeSlots RENAME TO UnavailableTimeSlotsOld",
+    "CREATE TABLE UnavailableTimeSlots (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      start_time TEXT NOT NULL,
+      end_time TEXT NOT NULL,
+      reason TEXT,
+      created_by TEXT,
+      source_uid TEXT,
+      UNIQUE (start_time, end_time)
+    )",
+    "INSERT INTO UnavailableTimeSlots
+      (start_time, end_time, source_uid)
+    SELECT
+      start_time, end_time, source_uid
+    FROM
+      UnavailableTimeSlotsOld",
+    "DROP TABLE UnavailableTimeSlotsOld",
+  ];
+
+  let mut tx = pool.begin().await.unwrap_or_else(|e| {
+    log::error!("Failed to start transaction: {}", e);
+    panic!("Failed to start transaction: {}", e);
+  });
+
+  for sql in statements {
+    query(sql).execute(&mut *tx).await.unwrap_or_else(|e| {
+      log::error!("Failed to migrate UnavailableTimeSlots table: {}", e);
+      panic!("Failed to migrate UnavailableTimeSlots table: {}", e);
+    });
+  }
+
+  tx.commit().await.unwrap_or_else(|e| {
+    log::error!("Failed to migrate UnavailableTimeSlots table: {}", e);
+    panic!("Failed to migrate UnavailableTimeSlots table: {}", e);
+  });
+}
+
+// 舊版的 BlackList 以 user_name 為主鍵，每位使用者只有一筆紀錄，
+// 重建資料表以保留停權歷史、原因與執行的管理員
+/*
+時間欄位仍是文字的舊資料表已在 migrate_time_columns 中以新的結構重建，這裡只需處理 INTEGER 的舊資料表
+*/
+async fn migrate_blacklist(pool: &Pool<Sqlite>) {
+  let has_ban_id: bool = query_scalar(
+    "SELECT EXISTS(SELECT 1 FROM pragma_table_info('BlackList') WHERE name = 'ban_id')",
+  )
+  .fetch_one(pool)
+  .await
+  .unwrap_or_else(|e| {
+    log::error!("Failed to query columns of BlackList table: {}", e);
+    panic!("Failed to query columns of BlackList table: {}", e);
+  });
+
+  if has_ban_id {
+    return;
+  }
+
+  log::info!("Migrating BlackList table");
+
+  let statements = [
+    "ALTER TABLE BlackList RENAME TO BlackListOld",
+    CREATE_BLACKLIST_TABLE,
+    "INSERT INTO BlackList
+      (user_name, start_time, end_time)
+    SELECT
+      user_name, start_time, end_time
+    FROM
+      BlackListOld",
+    "DROP TABLE BlackListOld",
+  ];
+
+  let mut tx = pool.begin().await.unwrap_or_else(|e| {
+    log::error!("Failed to start transaction: {}", e);
+    panic!("Failed to start transaction: {}", e);
+  });
+
+  for sql in statements {
+    query(sql).execute(&mut *tx).await.unwrap_or_else(|e| {
+      log::error!("Failed to migrate BlackList table: {}", e);
+      panic!("Failed to migrate BlackList table: {}", e);
+    });
+  }
+
+  tx.commit().await.unwrap_or_else(|e| {
+    log::error!("Failed to migrate BlackList table: {}", e);
+    panic!("Failed to migrate BlackList table: {}", e);
+  });
+}
+
+// 重建資料表，將以本地時間文字儲存的時間欄位轉換為 UTC timestamp
+async fn migrate_time_columns(
+  pool: &Pool<Sqlite>,
+  table_name: &str,
+  create_table_sql: &str,
+  time_columns: &[&str],
+) {
+  let column_type: String = query_scalar("SELECT type FROM pragma_table_info(?1) WHERE name = ?2")
+    .bind(table_name)
+    .bind(time_columns[0])
+    .fetch_one(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to query columns of {} table: {}", table_name, e);
+      panic!("Failed to query columns of {} table: {}", table_name, e);
+    });
+
+  if column_type != "TEXT" {
+    return;
+  }
+
+  log::info!("Migrating time columns of {} table", table_name);
+
+  let new_table_name = format!("{}New", table_name);
+  let columns: Vec<String> = query_scalar("SELECT name FROM pragma_table_info(?)")
+    .bind(table_name)
+    .fetch_all(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to query columns of {} table: {}", table_name, e);
+      panic!("Failed to query columns of {} table: {}", table_name, e);
+    });
+  let columns = columns.join(", ");
+
+  // 其他資料表可能以外鍵參照此資料表(例如 SeatMaintenance 參照 SeatIssues)，
+  // 依 SQLite 建議的步驟，暫時關閉外鍵檢查，建立新的資料表後再改回原本的名稱，讓外鍵仍指向此資料表
+  let mut conn = pool.acquire().await.unwrap_or_else(|e| {
+    log::error!("Failed to acquire connection: {}", e);
+    panic!("Failed to acquire connection: {}", e);
+  });
+
+  query("PRAGMA foreign_keys = OFF")
+    .execute(&mut *conn)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to disable foreign keys: {}", e);
+      panic!("Failed to disable foreign keys: {}", e);
+    });
+
+  let mut tx = conn.begin().await.unwrap_or_else(|e| {
+    log::error!("Failed to start transaction: {}", e);
+    panic!("Failed to start transaction: {}", e);
+  });
+
+  let statements = [
+    create_table_sql.replacen(table_name, &new_table_name, 1),
+    format!(
+      "INSERT INTO {} ({}) SELECT {} FROM {}",
+      new_table_name, columns, columns, table_name
+    ),
+    format!("DROP TABLE {}", table_name),
+    format!("ALTER TABLE {} RENAME TO {}", new_table_name, table_name),
+  ];
+
+  for sql in statements.iter() {
+    query(sql).execute(&mut *tx).await.unwrap_or_else(|e| {
+      log::error!("Failed to migrate {} table: {}", table_name, e);
+      panic!("Failed to migrate {} table: {}", table_name, e);
+    });
+  }
+
+  // 複製過來的時間仍是文字，逐筆以舊版寫入時的 UTC+8 轉換
+  for column in time_columns {
+    let select_sql = format!(
+      "SELECT rowid, {} FROM {} WHERE typeof({}) = 'text'",
+      column, table_name, column
+    );
+    let rows: Vec<(i64, String)> = query_as(&select_sql)
+      .fetch_all(&mut *tx)
+      .await
+      .unwrap_or_else(|e| {
+        log::error!("Failed to select {} of {} table: {}", column, table_name, e);
+        panic!("Failed to select {} of {} table: {}", column, table_name, e);
+      });
+
+    let update_sql = format!("UPDATE {} SET {} = ? WHERE rowid = ?", table_name, column);
+
+    for (rowid, db_time) in rows {
+      let timestamp = db_time_to_timestamp(&db_time).unwrap_or_else(|e| {
+        log::error!(
+          "Failed to convert '{}' of {} table: {}",
+          db_time,
+          table_name,
+          e
+        );
+        panic!(
+          "Failed to convert '{}' of {} table: {}",
+          db_time, table_name, e
+        );
+      });
+
+      query(&update_sql)
+        .bind(timestamp)
+        .bind(rowid)
+        .execute(&mut *tx)
+        .await
+        .unwrap_or_else(|e| {
+          log::error!("Failed to update {} of {} table: {}", column, table_name, e);
+          panic!("Failed to update {} of {} table: {}", column, table_name, e);
+        });
+    }
+  }
+
+  let violations: Vec<(String,)> = query_as("PRAGMA foreign_key_check")
+    .fetch_all(&mut *tx)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to check foreign keys: {}", e);
+      panic!("Failed to check foreign keys: {}", e);
+    });
+
+  if !violations.is_empty() {
+    log::error!(
+      "Foreign key violations after migrating {} table",
+      table_name
+    );
+    panic!(
+      "Foreign key violations after migrating {} table",
+      table_name
+    );
+  }
+
+  tx.commit().await.unwrap_or_else(|e| {
+    log::error!("Failed to migrate {} table: {}", table_name, e);
+    panic!("Failed to migrate {} table: {}", table_name, e);
+  });
+
+  query("PRAGMA foreign_keys = ON")
+    .execute(&mut *conn)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to enable foreign keys: {}", e);
+      panic!("Failed to enable foreign keys: {}", e);
+    });
+}
+
 async fn init_seat_info(pool: &Pool<Sqlite>) {
   let count: u16 = query_as::<_, (u16,)>("SELECT COUNT(*) FROM Seats")
     .fetch_one(pool)
@@ -130,73 +795,62 @@ async fn init_seat_info(pool: &Pool<Sqlite>) {
   }
 }
 
-async fn init_unavailable_timeslots(pool: &Pool<Sqlite>) {
-  log::info!("Setting unavailable timeslots");
-
-  let today = get_today();
-  let mut time_slots: Vec<(i64, i64)> = Vec::new();
-
-  for i in 0..=3 {
-    let future_date = today + chrono::Duration::days(i);
-    let weekday = future_date.weekday();
-    let is_holiday = weekday == chrono::Weekday::Sat || weekday == chrono::Weekday::Sun;
-
-    if is_holiday {
-      let start_time: i64 =
-        naive_date_to_timestamp(future_date, 0, 0, 0).expect("Invalid timestamp");
-      let end_time: i64 = naive_date_to_timestamp(future_date, 9, 0, 0).expect("Invalid timestamp");
+// 預設開放時間：平日 08:00 ~ 22:00，週末 09:00 ~ 17:00
+async fn init_opening_hours(pool: &Pool<Sqlite>) {
+  log::info!("Initializing opening hours");
 
-      time_slots.push((start_time, end_time));
-
-      let start_time: i64 =
-        naive_date_to_timestamp(future_date, 17, 0, 0).expect("Invalid timestamp");
-      let end_time: i64 =
-        naive_date_to_timestamp(future_date, 23, 59, 59).expect("Invalid timestamp");
-
-      time_slots.push((start_time, end_time));
+  for weekday in 0..7 {
+    let (open_time, close_time) = if weekday >= 5 {
+      ("09:00:00", "17:00:00")
     } else {
-      let start_time: i64 =
-        naive_date_to_timestamp(future_date, 0, 0, 0).expect("Invalid timestamp");
-      let end_time: i64 = naive_date_to_timestamp(future_date, 8, 0, 0).expect("Invalid timestamp");
-
-      time_slots.push((start_time, end_time));
-
-      let start_time: i64 =
-        naive_date_to_timestamp(future_date, 22, 0, 0).expect("Invalid timestamp");
-      let end_time: i64 =
-        naive_date_to_timestamp(future_date, 23, 59, 59).expect("Invalid timestamp");
-
-      time_slots.push((start_time, end_time));
-    }
+      ("08:00:00", "22:00:00")
+    };
+
+    query(
+      "INSERT OR IGNORE INTO OpeningHours
+        (weekday, closed, open_time, close_time)
+      VALUES
+        (?1, ?2, ?3, ?4)",
+    )
+    .bind(weekday)
+    .bind(false)
+    .bind(open_time)
+    .bind(close_time)
+    .execute(pool)
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to initialize OpeningHours table: {}", e);
+      panic!("Failed to initialize OpeningHours table: {}", e);
+    });
   }
+}
 
-  for (start_time, end_time) in time_slots.into_iter() {
-    let overlapping = is_overlapping_with_unavailable_timeslot(&pool, start_time, end_time)
-      .await
-      .unwrap_or_else(|e| {
-        log::error!(
-          "Failed to check overlapping with unavailable timeslot: {}",
-          e
-        );
-        panic!(
-          "Failed to check overlapping with unavailable timeslot: {}",
-          e
-        );
-      });
+async fn init_unavailable_timeslots(pool: &Pool<Sqlite>) {
+  log::info!("Setting unavailable timeslots");
 
-    if !overlapping {
-      insert_unavailable_timeslot(pool, start_time, end_time)
-        .await
-        .unwrap_or_else(|e| {
-          log::error!("Failed to insert unavailable timeslots: {}", e);
-          panic!("Failed to insert unavailable timeslots: {}", e);
-        });
-    }
-  }
+  materialize_closures(pool, get_today(), get_closure_horizon_days())
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to materialize closures: {}", e);
+      panic!("Failed to materialize closures: {}", e);
+    });
 }
 
 pub async fn clear_table(pool: &Pool<Sqlite>) {
   let table_names = [
+    "ApiKeys",
+    "TotpChallenges",
+    "TotpRecoveryCodes",
+    "OidcStates",
+    "LoginThrottles",
+    "Sessions",
+    "PasswordResetTokens",
+    "CalendarFeeds",
+    "TimerRuns",
+    "OpeningHoursExceptions",
+    "OpeningHours",
+    "SeatMaintenance",
+    "SeatIssues",
     "BlackList",
     "Reservations",
     "Seats",
@@ -217,7 +871,7 @@ async fn insert_admin(pool: &Pool<Sqlite>) {
 
   let admin_password = env::var("ADMIN_PASSWORD").expect("Failed to get admin password");
 
-  let password_hash = hash(admin_password, DEFAULT_COST).expect("Hashing password failed");
+  let password_hash = hash_password(&admin_password).expect("Hashing password failed");
 
   let admin_email = env::var("ADMIN_EMAIL").expect("Failed to get admin email");
 
@@ -240,3 +894,108 @@ async fn insert_admin(pool: &Pool<Sqlite>) {
     panic!("Failed to insert admin: {}", e);
   });
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database;
+  use sqlx::Executor;
+
+  // 基準版本的資料表，時間以 datetime(?, 'unixepoch', '+8 hours') 的文字儲存
+  const BASELINE_TABLES: &str = "
+    CREATE TABLE Seats (
+      seat_id INTEGER PRIMARY KEY,
+      available BOOLEAN NOT NULL,
+      other_info TEXT
+    );
+    CREATE TABLE Users (
+      id INTEGER PRIMARY KEY AUTOINCREMENT,
+      user_name TEXT NOT NULL UNIQUE,
+      password_hash TEXT NOT NULL,
+      email TEXT NOT NULL UNIQUE,
+      user_role TEXT NOT NULL,
+      verified BOOLEAN NOT NULL,
+      verification_token TEXT
+    );
+    CREATE TABLE Reservations (
+      user_name TEXT NOT NULL,
+      seat_id INTEGER NOT NULL,
+      start_time TEXT NOT NULL,
+      end_time TEXT NOT NULL,
+      PRIMARY KEY (user_name, start_time, end_time),
+      FOREIGN KEY(user_name) REFERENCES Users(user_name),
+      FOREIGN KEY(seat_id) REFERENCES Seats(seat_id)
+    );
+    CREATE TABLE UnavailableTimeSlots (
+      start_time TEXT NOT NULL,
+      end_time TEXT NOT NULL,
+      PRIMARY KEY (start_time, end_time)
+    );
+    CREATE TABLE BlackList (
+      user_name TEXT NOT NULL,
+      start_time TEXT NOT NULL,
+      end_time TEXT NOT NULL,
+      PRIMARY KEY (user_name),
+      FOREIGN KEY(user_name) REFERENCES Users(user_name)
+    );
+    INSERT INTO Seats (seat_id, available) VALUES (1, true);
+    INSERT INTO Users
+      (user_name, password_hash, email, user_role, verified)
+    VALUES
+      ('alice', 'hash', 'alice@example.com', 'RegularUser', true);
+    INSERT INTO Reservations
+      (user_name, seat_id, start_time, end_time)
+    VALUES
+      ('alice', 1, '2024-01-01 09:00:00', '2024-01-01 11:30:00');
+    INSERT INTO UnavailableTimeSlots
+      (start_time, end_time)
+    VALUES
+      ('2024-01-01 00:00:00', '2024-01-01 08:00:00');
+    INSERT INTO BlackList
+      (user_name, start_time, end_time)
+    VALUES
+      ('alice', '2024-01-02 00:00:00', '2024-01-09 00:00:00');";
+
+  // 2024-01-01 00:00:00 UTC+8
+  const JAN_1: i64 = 1704038400;
+  const HOUR: i64 = 60 * 60;
+
+  #[tokio::test]
+  async fn migrates_baseline_text_times_as_utc_plus_8() {
+    let pool = database::create_test_pool().await;
+
+    pool.execute(BASELINE_TABLES).await.unwrap();
+
+    init_db(&pool).await;
+
+    let reservation: (i64, i64) =
+      query_as("SELECT start_time, end_time FROM Reservations WHERE user_name = 'alice'")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(reservation, (JAN_1 + 9 * HOUR, JAN_1 + 11 * HOUR + 30 * 60));
+
+    let timeslot: (i64, i64) =
+      query_as("SELECT start_time, end_time FROM UnavailableTimeSlots WHERE start_time < ?")
+        .bind(JAN_1 + 24 * HOUR)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(timeslot, (JAN_1, JAN_1 + 8 * HOUR));
+
+    let ban: (i64, i64) = query_as("SELECT start_time, end_time FROM BlackList")
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+    assert_eq!(ban, (JAN_1 + 24 * HOUR, JAN_1 + 8 * 24 * HOUR));
+
+    // 再次初始化時不會重複轉換
+    init_db(&pool).await;
+
+    let start_time: i64 = query_scalar("SELECT start_time FROM Reservations")
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+    assert_eq!(start_time, JAN_1 + 9 * HOUR);
+  }
+}
@@ -0,0 +1,239 @@
+use super::common::*;
+use crate::model::issue::{IssueCategory, IssueResolution, IssueStatus, SeatIssue};
+
+const SELECT_SEAT_ISSUE: &str = "
+    SELECT
+      issue_id,
+      seat_id,
+      user_name,
+      category,
+      description,
+      photo_path,
+      status,
+      assignee,
+      resolution,
+      created_at,
+      resolved_at
+    FROM
+      SeatIssues";
+
+// 新增座位問題回報
+pub async fn insert_seat_issue(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  seat_id: u16,
+  category: IssueCategory,
+  description: &str,
+  photo_path: Option<&str>,
+) -> Result<i64, Status> {
+  let created_at = get_now_timestamp();
+
+  let result = handle_sqlx(
+    query!(
+      "INSERT INTO SeatIssues
+        (seat_id, user_name, category, description, photo_path, status, created_at)
+      VALUES
+        (?, ?, ?, ?, ?, ?, ?)",
+      seat_id,
+      user_name,
+      category,
+      description,
+      photo_path,
+      IssueStatus::Open,
+      created_at,
+    )
+    .execute(pool)
+    .await,
+    "Inserting new seat issue",
+  )?;
+
+  Ok(result.last_insert_rowid())
+}
+
+pub async fn get_seat_issues(
+  pool: &Pool<Sqlite>,
+  status: Option<IssueStatus>,
+) -> Result<Vec<SeatIssue>, Status> {
+  /*
+  獲取座位問題回報，可依狀態篩選
+   */
+  let sql = format!(
+    "{}
+    WHERE
+      ?1 IS NULL OR status = ?1
+    ORDER BY
+      created_at DESC",
+    SELECT_SEAT_ISSUE
+  );
+
+  let issues = handle_sqlx(
+    query_as::<_, SeatIssue>(&sql)
+      .bind(status)
+      .fetch_all(pool)
+      .await,
+    "Selecting seat issues",
+  )?;
+
+  Ok(issues)
+}
+
+// 獲取使用者回報的座位問題
+pub async fn get_user_seat_issues(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Vec<SeatIssue>, Status> {
+  let sql = format!(
+    "{}
+    WHERE
+      user_name = ?
+    ORDER BY
+      created_at DESC",
+    SELECT_SEAT_ISSUE
+  );
+
+  let issues = handle_sqlx(
+    query_as::<_, SeatIssue>(&sql)
+      .bind(user_name)
+      .fetch_all(pool)
+      .await,
+    "Selecting seat issues of the user",
+  )?;
+
+  Ok(issues)
+}
+
+pub async fn get_seat_issue_photo_path(
+  pool: &Pool<Sqlite>,
+  issue_id: i64,
+) -> Result<Option<String>, Status> {
+  let photo_path = handle_sqlx(
+    query_scalar!(
+      "SELECT photo_path FROM SeatIssues WHERE issue_id = ?",
+      issue_id
+    )
+    .fetch_one(pool)
+    .await,
+    "Selecting seat issue photo path",
+  )?;
+
+  Ok(photo_path)
+}
+
+pub async fn update_seat_issue_assignee(
+  pool: &Pool<Sqlite>,
+  issue_id: i64,
+  assignee: &str,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE SeatIssues
+      SET
+        assignee = ?,
+        status = ?
+      WHERE
+        issue_id = ? AND
+        status != ?",
+      assignee,
+      IssueStatus::Assigned,
+      issue_id,
+      IssueStatus::Resolved,
+    )
+    .execute(pool)
+    .await,
+    "Updating seat issue assignee",
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，找不到問題回報或已經處理完畢
+  if affected_rows == 0 {
+    log::warn!("No unresolved seat issue found for assignment");
+
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 處理座位問題回報，OutOfService 會同時建立座位停用時段
+pub async fn resolve_seat_issue(
+  pool: &Pool<Sqlite>,
+  issue_id: i64,
+  resolution: IssueResolution,
+  maintenance_end_time: Option<i64>,
+) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let seat_id: Option<i64> = handle_sqlx(
+    query_scalar!(
+      "SELECT seat_id FROM SeatIssues WHERE issue_id = ? AND status != ?",
+      issue_id,
+      IssueStatus::Resolved,
+    )
+    .fetch_optional(&mut *tx)
+    .await,
+    "Selecting unresolved seat issue",
+  )?;
+
+  let seat_id = match seat_id {
+    Some(seat_id) => seat_id,
+    None => {
+      log::warn!("No unresolved seat issue found for resolution");
+
+      // rollback
+      handle_sqlx(tx.rollback().await, "Rolling back")?;
+      return Err(Status::NotFound);
+    }
+  };
+
+  let resolved_at = get_now_timestamp();
+
+  handle_sqlx(
+    query!(
+      "UPDATE SeatIssues
+      SET
+        status = ?,
+        resolution = ?,
+        resolved_at = ?
+      WHERE
+        issue_id = ?",
+      IssueStatus::Resolved,
+      resolution,
+      resolved_at,
+      issue_id,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Resolving seat issue",
+  )?;
+
+  if resolution == IssueResolution::OutOfService {
+    let now = get_now_timestamp();
+    let end_time = maintenance_end_time.ok_or_else(|| {
+      log::error!("Missing maintenance end time for OutOfService resolution");
+      Status::UnprocessableEntity
+    })?;
+
+    // 新增座位停用時段
+    handle_sqlx(
+      query!(
+        "INSERT INTO SeatMaintenance
+          (seat_id, start_time, end_time, issue_id)
+        VALUES
+          (?, ?, ?, ?)",
+        seat_id,
+        now,
+        end_time,
+        issue_id,
+      )
+      .execute(&mut *tx)
+      .await,
+      "Inserting seat maintenance",
+    )?;
+  }
+
+  // 完成整筆transaction
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
@@ -0,0 +1,146 @@
+use super::common::*;
+use crate::model::user::{LoginFailure, LoginThrottleKind};
+
+// 查詢目前是否被拒絕登入，回傳可再次嘗試的時間
+pub async fn get_blocked_until(
+  pool: &Pool<Sqlite>,
+  kind: LoginThrottleKind,
+  subject: &str,
+) -> Result<Option<i64>, Status> {
+  let now = get_now_timestamp();
+
+  let blocked_until: Option<i64> = handle_sqlx(
+    query_scalar::<_, i64>(
+      "SELECT blocked_until FROM LoginThrottles
+      WHERE
+        kind = ? AND
+        subject = ? AND
+        blocked_until > ?",
+    )
+    .bind(kind)
+    .bind(subject)
+    .bind(now)
+    .fetch_optional(pool)
+    .await,
+    "Selecting login throttle",
+  )?;
+
+  Ok(blocked_until)
+}
+
+// 記錄一次登入失敗
+/*
+超過 free_attempts 次後，每次失敗的延遲時間加倍(上限 LOGIN_BACKOFF_MAX_SECONDS)
+若有設定 lockout_failures，失敗達此次數後鎖定 LOGIN_LOCKOUT_MINUTES 分鐘
+距離上次失敗超過 LOGIN_FAILURE_WINDOW_MINUTES 時，失敗次數重新計算
+*/
+pub async fn record_login_failure(
+  pool: &Pool<Sqlite>,
+  kind: LoginThrottleKind,
+  subject: &str,
+  free_attempts: i64,
+  lockout_failures: Option<i64>,
+) -> Result<LoginFailure, Status> {
+  let now = get_now_timestamp();
+  let window_start = now - constant::LOGIN_FAILURE_WINDOW_MINUTES * 60;
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let previous_count: i64 = handle_sqlx(
+    query_scalar::<_, i64>(
+      "SELECT failed_count FROM LoginThrottles
+      WHERE
+        kind = ? AND
+        subject = ? AND
+        last_failed_at > ?",
+    )
+    .bind(kind)
+    .bind(subject)
+    .bind(window_start)
+    .fetch_optional(&mut *tx)
+    .await,
+    "Selecting login failure count",
+  )?
+  .unwrap_or(0);
+
+  let failed_count = previous_count + 1;
+  let is_locked = lockout_failures.is_some_and(|failures| failed_count >= failures);
+
+  let blocked_until = if is_locked {
+    Some(now + constant::LOGIN_LOCKOUT_MINUTES * 60)
+  } else if failed_count > free_attempts {
+    let exponent = (failed_count - free_attempts - 1).min(30) as u32;
+    Some(now + 2_i64.pow(exponent).min(constant::LOGIN_BACKOFF_MAX_SECONDS))
+  } else {
+    None
+  };
+
+  handle_sqlx(
+    query(
+      "INSERT OR REPLACE INTO LoginThrottles
+        (kind, subject, failed_count, last_failed_at, blocked_until)
+      VALUES
+        (?, ?, ?, ?, ?)",
+    )
+    .bind(kind)
+    .bind(subject)
+    .bind(failed_count)
+    .bind(now)
+    .bind(blocked_until)
+    .execute(&mut *tx)
+    .await,
+    "Recording login failure",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(LoginFailure {
+    failed_count,
+    blocked_until,
+    // 只在剛達到鎖定次數時通知，避免持續失敗時重複寄信
+    locked: lockout_failures == Some(failed_count),
+  })
+}
+
+// 登入成功或管理員解除鎖定時，清除失敗紀錄
+pub async fn clear_login_failures(
+  pool: &Pool<Sqlite>,
+  kind: LoginThrottleKind,
+  subject: &str,
+) -> Result<u64, Status> {
+  let affected_rows = handle_sqlx(
+    query("DELETE FROM LoginThrottles WHERE kind = ? AND subject = ?")
+      .bind(kind)
+      .bind(subject)
+      .execute(pool)
+      .await,
+    "Clearing login failures",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows)
+}
+
+// 刪除已過失敗計算期間且未在封鎖中的紀錄
+pub async fn delete_expired_login_throttles(pool: &Pool<Sqlite>) -> Result<u64, Status> {
+  let now = get_now_timestamp();
+  let window_start = now - constant::LOGIN_FAILURE_WINDOW_MINUTES * 60;
+
+  let affected_rows = handle_sqlx(
+    query(
+      "DELETE FROM LoginThrottles
+      WHERE
+        last_failed_at <= ? AND
+        (blocked_until IS NULL OR blocked_until <= ?)",
+    )
+    .bind(window_start)
+    .bind(now)
+    .execute(pool)
+    .await,
+    "Deleting expired login throttles",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows)
+}
@@ -0,0 +1,227 @@
+use super::common::*;
+
+// 開始 OIDC 登入時記錄 state，並順便清除已過期的 state
+pub async fn insert_oidc_state(
+  pool: &Pool<Sqlite>,
+  state: &str,
+  nonce: &str,
+  code_verifier: &str,
+  expires_at: i64,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  handle_sqlx(
+    query!("DELETE FROM OidcStates WHERE expires_at <= ?", now)
+      .execute(pool)
+      .await,
+    "Deleting expired OIDC states",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "INSERT INTO OidcStates
+        (state, nonce, code_verifier, expires_at)
+      VALUES
+        (?, ?, ?, ?)",
+      state,
+      nonce,
+      code_verifier,
+      expires_at,
+    )
+    .execute(pool)
+    .await,
+    "Inserting OIDC state",
+  )?;
+
+  Ok(())
+}
+
+// 取出並刪除 state，每個 state 只能使用一次，回傳 nonce 與 PKCE code verifier
+pub async fn take_oidc_state(pool: &Pool<Sqlite>, state: &str) -> Result<(String, String), Status> {
+  let now = get_now_timestamp();
+
+  let oidc_state = handle_sqlx(
+    query_as::<_, (String, String)>(
+      "DELETE FROM OidcStates
+      WHERE
+        state = ? AND
+        expires_at > ?
+      RETURNING
+        nonce, code_verifier",
+    )
+    .bind(state)
+    .bind(now)
+    .fetch_optional(pool)
+    .await,
+    "Taking OIDC state",
+  )?;
+
+  oidc_state.ok_or_else(|| {
+    log::warn!("The OIDC state is invalid or expired");
+    Status::BadRequest
+  })
+}
+
+pub async fn get_user_info_by_oidc_subject(
+  pool: &Pool<Sqlite>,
+  subject: &str,
+) -> Result<Option<user::UserInfo>, Status> {
+  let sql = "
+    SELECT
+      user_name, password_hash, email, user_role, verified, verification_token
+    FROM
+      Users
+    WHERE
+      oidc_subject = ?";
+
+  let user_info = handle_sqlx(
+    query_as::<_, user::UserInfo>(sql)
+      .bind(subject)
+      .fetch_optional(pool)
+      .await,
+    "Selecting user info by OIDC subject",
+  )?;
+
+  Ok(user_info)
+}
+
+// 將既有帳號(以 email 對應)連結到身分提供者的帳號
+/*
+尚未驗證 email 的帳號可能是他人以此 email 註冊，連結前清除其密碼、兩步驟驗證與登入的 session，
+之後只能以 OIDC 登入(可再以忘記密碼重新設定密碼)
+*/
+pub async fn link_oidc_subject(
+  pool: &Pool<Sqlite>,
+  email: &str,
+  subject: &str,
+) -> Result<Option<user::UserInfo>, Status> {
+  let now = get_now_timestamp();
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let account = handle_sqlx(
+    query_as::<_, (String, bool)>(
+      "SELECT user_name, verified FROM Users
+      WHERE
+        email = ? AND
+        (oidc_subject IS NULL OR oidc_subject = ?) AND
+        deleted_at IS NULL",
+    )
+    .bind(email)
+    .bind(subject)
+    .fetch_optional(&mut *tx)
+    .await,
+    "Selecting user to link OIDC subject",
+  )?;
+
+  let (user_name, verified) = match account {
+    Some(account) => account,
+    None => {
+      handle_sqlx(tx.rollback().await, "Rolling back")?;
+      return Ok(None);
+    }
+  };
+
+  if !verified {
+    log::warn!(
+      "Linking OIDC subject to unverified user: {}, clearing its credentials",
+      user_name
+    );
+
+    handle_sqlx(
+      query!(
+        "UPDATE Sessions
+        SET
+          revoked_at = ?
+        WHERE
+          user_name = ? AND
+          revoked_at IS NULL",
+        now,
+        user_name,
+      )
+      .execute(&mut *tx)
+      .await,
+      "Revoking sessions of the unverified user",
+    )?;
+
+    for table_name in ["PasswordResetTokens", "TotpRecoveryCodes", "TotpChallenges"] {
+      let sql = format!("DELETE FROM {} WHERE user_name = ?", table_name);
+
+      handle_sqlx(
+        query(&sql).bind(&user_name).execute(&mut *tx).await,
+        &format!("Deleting {} of the unverified user", table_name),
+      )?;
+    }
+
+    handle_sqlx(
+      query!(
+        "UPDATE Users
+        SET
+          password_hash = '',
+          totp_secret = NULL,
+          totp_enabled = false,
+          totp_last_step = NULL,
+          verification_token = NULL,
+          verification_token_expires_at = NULL
+        WHERE
+          user_name = ?",
+        user_name,
+      )
+      .execute(&mut *tx)
+      .await,
+      "Clearing credentials of the unverified user",
+    )?;
+  }
+
+  let sql = "
+    UPDATE Users
+    SET
+      oidc_subject = ?,
+      verified = true
+    WHERE
+      user_name = ?
+    RETURNING
+      user_name, password_hash, email, user_role, verified, verification_token";
+
+  let user_info = handle_sqlx(
+    query_as::<_, user::UserInfo>(sql)
+      .bind(subject)
+      .bind(&user_name)
+      .fetch_one(&mut *tx)
+      .await,
+    "Linking OIDC subject",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(Some(user_info))
+}
+
+// 自動建立以 OIDC 登入的帳號，email 已由身分提供者驗證，且沒有密碼
+pub async fn insert_oidc_user(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  email: &str,
+  user_role: user::UserRole,
+  subject: &str,
+) -> Result<bool, Status> {
+  let result = handle_sqlx(
+    query(
+      "INSERT INTO Users
+        (user_name, password_hash, email, user_role, verified, oidc_subject)
+      VALUES
+        (?, '', ?, ?, true, ?)
+      ON CONFLICT(user_name) DO NOTHING",
+    )
+    .bind(user_name)
+    .bind(email)
+    .bind(user_role)
+    .bind(subject)
+    .execute(pool)
+    .await,
+    "Inserting OIDC user",
+  )?;
+
+  Ok(result.rows_affected() != 0)
+}
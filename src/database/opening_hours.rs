@@ -0,0 +1,445 @@
+use super::common::*;
+use super::{
+  timer::{get_materialized_until, update_timer_run},
+  timeslot::{
+    delete_generated_closures, insert_unavailable_timeslot,
+    is_overlapping_with_unavailable_timeslot,
+  },
+};
+use crate::model::opening_hours::{OpeningHoursException, OpeningHoursRule};
+use chrono::{Datelike, Duration, NaiveDate, NaiveTime, Timelike};
+
+// 排程記錄於 TimerRuns 的工作名稱
+const CLOSURE_TIMER_TASK: &str = "closures";
+
+// (closed, open_time, close_time, reason)
+type DayRule = (bool, Option<NaiveTime>, Option<NaiveTime>, Option<String>);
+
+pub async fn get_weekly_opening_hours(
+  pool: &Pool<Sqlite>,
+) -> Result<Vec<OpeningHoursRule>, Status> {
+  let sql = "
+    SELECT
+      weekday, closed, open_time, close_time
+    FROM
+      OpeningHours
+    ORDER BY
+      weekday";
+
+  let rules = handle_sqlx(
+    query_as::<_, OpeningHoursRule>(sql).fetch_all(pool).await,
+    "Selecting weekly opening hours",
+  )?;
+
+  Ok(rules)
+}
+
+pub async fn get_opening_hours_exceptions(
+  pool: &Pool<Sqlite>,
+  from_date: NaiveDate,
+) -> Result<Vec<OpeningHoursException>, Status> {
+  let sql = "
+    SELECT
+      date, closed, open_time, close_time, reason
+    FROM
+      OpeningHoursExceptions
+    WHERE
+      date >= ?
+    ORDER BY
+      date";
+
+  let exceptions = handle_sqlx(
+    query_as::<_, OpeningHoursException>(sql)
+      .bind(from_date)
+      .fetch_all(pool)
+      .await,
+    "Selecting opening hours exceptions",
+  )?;
+
+  Ok(exceptions)
+}
+
+pub async fn upsert_weekly_opening_hours(
+  pool: &Pool<Sqlite>,
+  rule: &OpeningHoursRule,
+) -> Result<(), Status> {
+  let sql = "
+    INSERT OR REPLACE INTO OpeningHours
+      (weekday, closed, open_time, close_time)
+    VALUES
+      (?1, ?2, ?3, ?4)";
+
+  handle_sqlx(
+    query(sql)
+      .bind(rule.weekday.num_days_from_monday())
+      .bind(rule.closed)
+      .bind(rule.open_time)
+      .bind(rule.close_time)
+      .execute(pool)
+      .await,
+    "Upserting weekly opening hours",
+  )?;
+
+  rematerialize_closures(pool, |date| date.weekday() == rule.weekday).await?;
+
+  Ok(())
+}
+
+pub async fn upsert_opening_hours_exception(
+  pool: &Pool<Sqlite>,
+  exception: &OpeningHoursException,
+) -> Result<(), Status> {
+  let sql = "
+    INSERT OR REPLACE INTO OpeningHoursExceptions
+      (date, closed, open_time, close_time, reason)
+    VALUES
+      (?1, ?2, ?3, ?4, ?5)";
+
+  handle_sqlx(
+    query(sql)
+      .bind(exception.date)
+      .bind(exception.closed)
+      .bind(exception.open_time)
+      .bind(exception.close_time)
+      .bind(&exception.reason)
+      .execute(pool)
+      .await,
+    "Upserting opening hours exception",
+  )?;
+
+  rematerialize_closures(pool, |date| date == exception.date).await?;
+
+  Ok(())
+}
+
+pub async fn delete_opening_hours_exception(
+  pool: &Pool<Sqlite>,
+  date: NaiveDate,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query("DELETE FROM OpeningHoursExceptions WHERE date = ?")
+      .bind(date)
+      .execute(pool)
+      .await,
+    "Deleting opening hours exception",
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，此次操作無作用到任何資料
+  if affected_rows == 0 {
+    log::warn!("No opening hours exception found for deletion");
+
+    return Err(Status::NotFound);
+  }
+
+  rematerialize_closures(pool, |other| other == date).await?;
+
+  Ok(())
+}
+
+// 開放時間變更後，重新產生排程已產生過的日期中受影響的不可預約時段
+/*
+排程尚未產生的日期之後會依新的開放時間產生，不需處理
+*/
+async fn rematerialize_closures(
+  pool: &Pool<Sqlite>,
+  is_affected: impl Fn(NaiveDate) -> bool,
+) -> Result<(), Status> {
+  let materialized_until = match get_materialized_until(pool, CLOSURE_TIMER_TASK).await? {
+    Some(materialized_until) => materialized_until,
+    None => return Ok(()),
+  };
+
+  let mut date = get_today();
+
+  while date <= materialized_until {
+    if is_affected(date) {
+      log::info!("Rematerializing closures for date: {}", date);
+
+      let day_start = naive_date_to_timestamp(date, 0, 0, 0)?;
+      let day_end = naive_date_to_timestamp(date, 23, 59, 59)?;
+
+      delete_generated_closures(pool, day_start, day_end).await?;
+      materialize_closures_for_date(pool, date).await?;
+    }
+
+    date += Duration::days(1);
+  }
+
+  Ok(())
+}
+
+// 查詢特定日期的開放時間與例外原因，開放時間為 None 表示整天不開放
+pub async fn get_opening_hours(
+  pool: &Pool<Sqlite>,
+  date: NaiveDate,
+) -> Result<(Option<(NaiveTime, NaiveTime)>, Option<String>), Status> {
+  // 特定日期的例外(例如國定假日、考試週)優先於每週規則
+  let exception = handle_sqlx(
+    query_as::<_, DayRule>(
+      "SELECT
+        closed, open_time, close_time, reason
+      FROM
+        OpeningHoursExceptions
+      WHERE
+        date = ?",
+    )
+    .bind(date)
+    .fetch_optional(pool)
+    .await,
+    "Selecting opening hours exception",
+  )?;
+
+  let rule = match exception {
+    Some(rule) => Some(rule),
+    None => handle_sqlx(
+      query_as::<_, DayRule>(
+        "SELECT
+          closed, open_time, close_time, NULL as reason
+        FROM
+          OpeningHours
+        WHERE
+          weekday = ?",
+      )
+      .bind(date.weekday().num_days_from_monday())
+      .fetch_optional(pool)
+      .await,
+      "Selecting weekly opening hours",
+    )?,
+  };
+
+  match rule {
+    Some((false, Some(open_time), Some(close_time), reason)) => {
+      Ok((Some((open_time, close_time)), reason))
+    }
+    Some((_, _, _, reason)) => Ok((None, reason)),
+    None => Ok((None, None)),
+  }
+}
+
+// 由開放時間推算特定日期的不可預約時段
+pub async fn get_closures_for_date(
+  pool: &Pool<Sqlite>,
+  date: NaiveDate,
+) -> Result<Vec<timeslot::Closure>, Status> {
+  let day_start = naive_date_to_timestamp(date, 0, 0, 0)?;
+  let day_end = naive_date_to_timestamp(date, 23, 59, 59)?;
+
+  let (hours, reason) = get_opening_hours(pool, date).await?;
+
+  let (open_time, close_time) = match hours {
+    Some(hours) => hours,
+    // 整天休館的時段需有原因，才會出現在公開的休館時段列表中
+    None => {
+      return Ok(vec![timeslot::Closure {
+        start_time: day_start,
+        end_time: day_end,
+        reason: reason.or_else(|| Some(constant::CLOSED_DAY_REASON.to_string())),
+      }])
+    }
+  };
+
+  let open = naive_date_to_timestamp(
+    date,
+    open_time.hour(),
+    open_time.minute(),
+    open_time.second(),
+  )?;
+  let close = naive_date_to_timestamp(
+    date,
+    close_time.hour(),
+    close_time.minute(),
+    close_time.second(),
+  )?;
+
+  let mut closures: Vec<timeslot::Closure> = Vec::new();
+
+  if day_start < open {
+    closures.push(timeslot::Closure {
+      start_time: day_start,
+      end_time: open,
+      reason: reason.clone(),
+    });
+  }
+
+  if close < day_end {
+    closures.push(timeslot::Closure {
+      start_time: close,
+      end_time: day_end,
+      reason,
+    });
+  }
+
+  Ok(closures)
+}
+
+// 依開放時間產生特定日期的不可預約時段，已存在重疊的時段則略過
+pub async fn materialize_closures_for_date(
+  pool: &Pool<Sqlite>,
+  date: NaiveDate,
+) -> Result<(), Status> {
+  let closures = get_closures_for_date(pool, date).await?;
+
+  for closure in closures.into_iter() {
+    let is_overlapping =
+      is_overlapping_with_unavailable_timeslot(pool, closure.start_time, closure.end_time).await?;
+
+    if !is_overlapping {
+      insert_unavailable_timeslot(
+        pool,
+        closure.start_time,
+        closure.end_time,
+        closure.reason.as_deref(),
+        constant::SYSTEM_USER,
+      )
+      .await?;
+    }
+  }
+
+  Ok(())
+}
+
+// 補上從上次執行後到 today + horizon_days 之間所有尚未產生的日期，
+// 避免伺服器停機期間跳過的日期整天都可預約
+pub async fn materialize_closures(
+  pool: &Pool<Sqlite>,
+  today: NaiveDate,
+  horizon_days: i64,
+) -> Result<(), Status> {
+  let horizon = today + Duration::days(horizon_days);
+  let materialized_until = get_materialized_until(pool, CLOSURE_TIMER_TASK).await?;
+
+  let mut date = match materialized_until {
+    Some(materialized_until) if materialized_until >= today => {
+      materialized_until + Duration::days(1)
+    }
+    _ => today,
+  };
+
+  while date <= horizon {
+    log::info!("Materializing closures for date: {}", date);
+
+    materialize_closures_for_date(pool, date).await?;
+    // 每完成一天就記錄，中途失敗時下次從失敗的日期繼續
+    update_timer_run(pool, CLOSURE_TIMER_TASK, date).await?;
+
+    date += Duration::days(1);
+  }
+
+  // 沒有需要補上的日期時仍記錄這次執行
+  if let Some(materialized_until) = materialized_until.filter(|date| *date >= horizon) {
+    update_timer_run(pool, CLOSURE_TIMER_TASK, materialized_until).await?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database;
+  use chrono::Weekday;
+
+  fn time(hour: u32) -> Option<NaiveTime> {
+    NaiveTime::from_hms_opt(hour, 0, 0)
+  }
+
+  fn closure(start_time: i64, end_time: i64, reason: Option<&str>) -> (i64, i64, Option<String>) {
+    (start_time, end_time, reason.map(str::to_string))
+  }
+
+  async fn closures_for_date(
+    pool: &Pool<Sqlite>,
+    date: NaiveDate,
+  ) -> Vec<(i64, i64, Option<String>)> {
+    get_closures_for_date(pool, date)
+      .await
+      .unwrap()
+      .into_iter()
+      .map(|closure| (closure.start_time, closure.end_time, closure.reason))
+      .collect()
+  }
+
+  #[tokio::test]
+  async fn closures_follow_weekly_rules_and_exceptions() {
+    let pool = database::connect_test_pool().await;
+
+    // 2024-01-01 為星期一
+    let monday = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+    let day_start = |date| naive_date_to_timestamp(date, 0, 0, 0).unwrap();
+    let day_end = |date| naive_date_to_timestamp(date, 23, 59, 59).unwrap();
+    let at = |date, hour| naive_date_to_timestamp(date, hour, 0, 0).unwrap();
+
+    upsert_weekly_opening_hours(
+      &pool,
+      &OpeningHoursRule {
+        weekday: Weekday::Mon,
+        closed: false,
+        open_time: time(8),
+        close_time: time(22),
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      closures_for_date(&pool, monday).await,
+      vec![
+        closure(day_start(monday), at(monday, 8), None),
+        closure(at(monday, 22), day_end(monday), None),
+      ]
+    );
+
+    // 特定日期的開放時間優先於每週規則
+    upsert_opening_hours_exception(
+      &pool,
+      &OpeningHoursException {
+        date: monday,
+        closed: false,
+        open_time: time(10),
+        close_time: time(18),
+        reason: Some("Exam week".to_string()),
+      },
+    )
+    .await
+    .unwrap();
+
+    assert_eq!(
+      closures_for_date(&pool, monday).await,
+      vec![
+        closure(day_start(monday), at(monday, 10), Some("Exam week")),
+        closure(at(monday, 18), day_end(monday), Some("Exam week")),
+      ]
+    );
+
+    // 整天休館，未提供原因時使用預設的原因
+    let tuesday = monday.succ_opt().unwrap();
+
+    for (reason, expected) in [
+      (Some("Holiday"), "Holiday"),
+      (None, constant::CLOSED_DAY_REASON),
+    ] {
+      upsert_opening_hours_exception(
+        &pool,
+        &OpeningHoursException {
+          date: tuesday,
+          closed: true,
+          open_time: None,
+          close_time: None,
+          reason: reason.map(str::to_string),
+        },
+      )
+      .await
+      .unwrap();
+
+      assert_eq!(
+        closures_for_date(&pool, tuesday).await,
+        vec![closure(
+          day_start(tuesday),
+          day_end(tuesday),
+          Some(expected)
+        )]
+      );
+    }
+  }
+}
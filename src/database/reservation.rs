@@ -1,4 +1,4 @@
-use chrono::NaiveDate;
+use chrono::{Duration, NaiveDate};
 
 use super::common::*;
 
@@ -20,7 +20,8 @@ pub async fn reserve_seat(
         SELECT 1 FROM Reservations
         WHERE 
           seat_id = ? AND 
-          (MAX(datetime(?, 'unixepoch', '+8 hours'), start_time) < MIN(datetime(?, 'unixepoch', '+8 hours'), end_time))
+          cancelled_at IS NULL AND
+          (MAX(?, start_time) < MIN(?, end_time))
     )",
       seat_id,
       start_time,
@@ -45,27 +46,47 @@ pub async fn reserve_seat(
     return Err(Status::Conflict);
   }
 
-  // 新增一筆預約
-  handle_sqlx(
+  let uid = uuid::Uuid::new_v4().simple().to_string();
+
+  // 新增一筆預約，若同時段曾取消過則恢復該筆預約，沿用原本的行事曆事件
+  let affected_rows = handle_sqlx(
     query!(
       "INSERT INTO Reservations 
-        (user_name, seat_id, start_time, end_time) 
+        (user_name, seat_id, start_time, end_time, uid) 
       VALUES 
         (
           ?, 
           ?, 
-          datetime(?, 'unixepoch', '+8 hours'), 
-          datetime(?, 'unixepoch', '+8 hours')
-        )",
+          ?, 
+          ?,
+          ?
+        )
+      ON CONFLICT(user_name, start_time, end_time) DO UPDATE SET
+        seat_id = excluded.seat_id,
+        sequence = sequence + 1,
+        cancelled_at = NULL
+      WHERE
+        cancelled_at IS NOT NULL",
       user_name,
       seat_id,
       start_time,
-      end_time
+      end_time,
+      uid
     )
     .execute(&mut *tx)
     .await,
     "Inserting new Reservation information",
-  )?;
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，使用者在同時段已有其他座位的預約
+  if affected_rows == 0 {
+    log::warn!("The user already has a reservation in the same timeslot");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::Conflict);
+  }
 
   // 完成整筆transaction
   handle_sqlx(tx.commit().await, "Committing transaction")?;
@@ -92,7 +113,8 @@ pub async fn update_reservation_time(
           SELECT 1 FROM Reservations
           WHERE 
             user_name != ? AND 
-            (MAX(datetime(?, 'unixepoch', '+8 hours'), start_time) < MIN(datetime(?, 'unixepoch', '+8 hours'), end_time))
+            cancelled_at IS NULL AND
+            (MAX(?, start_time) < MIN(?, end_time))
       )",
       user_name,
       new_start_time,
@@ -113,17 +135,37 @@ pub async fn update_reservation_time(
     return Err(Status::Conflict);
   }
 
+  // 移除新時段中已取消的預約，避免與主鍵衝突
+  handle_sqlx(
+    query!(
+      "DELETE FROM Reservations 
+      WHERE 
+        user_name = ? AND 
+        start_time = ? AND 
+        end_time = ? AND 
+        cancelled_at IS NOT NULL",
+      user_name,
+      new_start_time,
+      new_end_time,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Deleting cancelled reservation",
+  )?;
+
   // 執行更新
   let affected_rows = handle_sqlx(
     query!(
       "UPDATE Reservations 
       SET 
-        start_time = datetime(?, 'unixepoch', '+8 hours'), 
-        end_time = datetime(?, 'unixepoch', '+8 hours') 
+        start_time = ?, 
+        end_time = ?, 
+        sequence = sequence + 1 
       WHERE 
         user_name = ? AND 
-        start_time = datetime(?, 'unixepoch', '+8 hours') AND 
-        end_time = datetime(?, 'unixepoch', '+8 hours')",
+        start_time = ? AND 
+        end_time = ? AND 
+        cancelled_at IS NULL",
       new_start_time,
       new_end_time,
       user_name,
@@ -160,16 +202,23 @@ pub async fn delete_reservation_time(
 ) -> Result<(), Status> {
   /*
   刪除預約紀錄
+  只標記為已取消，讓行事曆訂閱能通知使用者該預約已取消
    */
+  let cancelled_at = get_now_timestamp();
 
   // 執行刪除
   let affected_rows = handle_sqlx(
     query!(
-      "DELETE FROM Reservations 
+      "UPDATE Reservations 
+      SET 
+          sequence = sequence + 1, 
+          cancelled_at = ? 
       WHERE 
           user_name = ? AND 
-          start_time = datetime(?, 'unixepoch', '+8 hours') AND 
-          end_time = datetime(?, 'unixepoch', '+8 hours');",
+          start_time = ? AND 
+          end_time = ? AND 
+          cancelled_at IS NULL;",
+      cancelled_at,
       user_name,
       start_time,
       end_time,
@@ -191,6 +240,38 @@ pub async fn delete_reservation_time(
   Ok(())
 }
 
+// 使用者報到，記錄進行中或即將開始的預約的報到時間，已報到的預約保留第一次的時間
+pub async fn check_in_reservation(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Option<reservation::Reservation>, Status> {
+  let now = get_now_timestamp();
+  let check_in_from = now + constant::CHECK_IN_EARLY_MINUTES * 60;
+
+  let reservation = handle_sqlx(
+    query_as::<_, reservation::Reservation>(
+      "UPDATE Reservations
+      SET
+        checked_in_at = COALESCE(checked_in_at, ?1)
+      WHERE
+        user_name = ?2 AND
+        start_time <= ?3 AND
+        end_time > ?1 AND
+        cancelled_at IS NULL
+      RETURNING
+        seat_id, start_time, end_time",
+    )
+    .bind(now)
+    .bind(user_name)
+    .bind(check_in_from)
+    .fetch_optional(pool)
+    .await,
+    "Checking in reservation",
+  )?;
+
+  Ok(reservation)
+}
+
 pub async fn get_user_reservations(
   pool: &Pool<Sqlite>,
   user_name: &str,
@@ -198,17 +279,18 @@ pub async fn get_user_reservations(
   /*
   獲取使用者的預約紀錄
    */
-  let now = naive_datetime_to_timestamp(get_now())?;
+  let now = get_now_timestamp();
   let sql = "
     SELECT 
       seat_id, 
-      strftime('%s', start_time, '-8 hours') as start_time, 
-      strftime('%s', end_time, '-8 hours') as end_time
+      start_time, 
+      end_time
     FROM 
       Reservations 
     WHERE 
       user_name = ? AND 
-      end_time > datetime(?, 'unixepoch', '+8 hours')";
+      cancelled_at IS NULL AND 
+      end_time > ?";
 
   // 搜尋使用者今天之後的預約紀錄
   let reservations = handle_sqlx(
@@ -228,16 +310,24 @@ pub async fn check_unfinished_reservations(
   user_name: &str,
   date: NaiveDate,
 ) -> Result<bool, Status> {
+  let now = get_now_timestamp();
+  let day_start = naive_date_to_timestamp(date, 0, 0, 0)?;
+  let next_day_start = naive_date_to_timestamp(date + Duration::days(1), 0, 0, 0)?;
+
   let result = handle_sqlx(
     query_scalar!(
       "SELECT EXISTS(
         SELECT 1 FROM Reservations
         WHERE user_name = ?
-        AND ? = date(start_time)
-        AND datetime('now', '+8 hours') < end_time
+        AND cancelled_at IS NULL
+        AND start_time >= ?
+        AND start_time < ?
+        AND ? < end_time
       )",
       user_name,
-      date,
+      day_start,
+      next_day_start,
+      now,
     )
     .fetch_one(pool)
     .await,
@@ -248,3 +338,39 @@ pub async fn check_unfinished_reservations(
 
   Ok(has_unfinished_reservation)
 }
+
+pub async fn get_user_reservation_events(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  since: i64,
+) -> Result<Vec<reservation::ReservationEvent>, Status> {
+  /*
+  獲取行事曆訂閱使用的預約紀錄，包含已取消的預約
+   */
+  let sql = "
+    SELECT 
+      uid, 
+      seat_id, 
+      start_time, 
+      end_time, 
+      sequence, 
+      cancelled_at IS NOT NULL as cancelled
+    FROM 
+      Reservations 
+    WHERE 
+      user_name = ? AND 
+      end_time > ?
+    ORDER BY 
+      start_time";
+
+  let events = handle_sqlx(
+    query_as::<_, reservation::ReservationEvent>(sql)
+      .bind(user_name)
+      .bind(since)
+      .fetch_all(pool)
+      .await,
+    "Selecting reservation events",
+  )?;
+
+  Ok(events)
+}
@@ -8,12 +8,18 @@ pub async fn get_all_seats_status(
   /*
   查詢所有位置在特定時間點狀態
   */
-
   let sql = "
     SELECT 
       Seats.seat_id,
       CASE
         WHEN Seats.available = 0 THEN 'Unavailable'
+        WHEN EXISTS(
+          SELECT 1 FROM SeatMaintenance
+          WHERE
+            SeatMaintenance.seat_id = Seats.seat_id AND
+            SeatMaintenance.start_time <= ?1 AND
+            SeatMaintenance.end_time > ?1
+        ) THEN 'Unavailable'
         WHEN Reservations.seat_id IS NULL THEN 'Available'
         ELSE 'Borrowed'
       END as status
@@ -21,13 +27,13 @@ pub async fn get_all_seats_status(
       Seats
     LEFT JOIN Reservations ON 
       Seats.seat_id = Reservations.seat_id AND
-      Reservations.start_time <= datetime(?, 'unixepoch', '+8 hours') AND
-      Reservations.end_time > datetime(?, 'unixepoch', '+8 hours')";
+      Reservations.cancelled_at IS NULL AND
+      Reservations.start_time <= ?1 AND
+      Reservations.end_time > ?1";
 
   // 取得每個座位的狀態，回傳為vector包含(座位號碼, 狀態)
   let result: Vec<(u16, String)> = handle_sqlx(
     sqlx::query_as::<_, (u16, String)>(sql)
-      .bind(time)
       .bind(time)
       .fetch_all(pool)
       .await,
@@ -66,6 +72,12 @@ pub async fn get_seats_status_in_specific_timeslots(
       Seats.seat_id,
       CASE
         WHEN Seats.available = 0 THEN 'Unavailable'
+        WHEN EXISTS(
+          SELECT 1 FROM SeatMaintenance
+          WHERE
+            SeatMaintenance.seat_id = Seats.seat_id AND
+            (MAX(?1, SeatMaintenance.start_time) < MIN(?2, SeatMaintenance.end_time))
+        ) THEN 'Unavailable'
         WHEN Reservations.seat_id IS NULL THEN 'Available'
         ELSE 'Borrowed'
       END as status
@@ -73,7 +85,8 @@ pub async fn get_seats_status_in_specific_timeslots(
       Seats
     LEFT JOIN Reservations ON 
       Seats.seat_id = Reservations.seat_id AND
-      (MAX(datetime(?, 'unixepoch', '+8 hours'), start_time) < MIN(datetime(?, 'unixepoch', '+8 hours'), end_time))";
+      Reservations.cancelled_at IS NULL AND
+      (MAX(?1, Reservations.start_time) < MIN(?2, Reservations.end_time))";
 
   let result: Vec<(u16, String)> = handle_sqlx(
     sqlx::query_as::<_, (u16, String)>(sql)
@@ -110,14 +123,15 @@ pub async fn get_seat_reservations(
 ) -> Result<Vec<(i64, i64)>, Status> {
   let sql = "
     SELECT
-      CAST(strftime('%s', start_time, '-8 hours') AS INTEGER) as start_time, 
-      CAST(strftime('%s', end_time, '-8 hours') AS INTEGER) as end_time
+      start_time, 
+      end_time
     FROM 
       Reservations
     WHERE
       seat_id = ? AND 
-      start_time >= datetime(?, 'unixepoch', '+8 hours') AND 
-      end_time <= datetime(?, 'unixepoch', '+8 hours')";
+      cancelled_at IS NULL AND 
+      start_time >= ? AND 
+      end_time <= ?";
 
   let timeslots: Vec<(i64, i64)> = handle_sqlx(
     sqlx::query_as::<_, (i64, i64)>(sql)
@@ -143,6 +157,35 @@ pub async fn is_seat_available(pool: &Pool<Sqlite>, seat_id: u16) -> Result<bool
   Ok(available)
 }
 
+// 查詢座位在特定時間段是否處於停用(維修)狀態
+pub async fn is_seat_under_maintenance(
+  pool: &Pool<Sqlite>,
+  seat_id: u16,
+  start_time: i64,
+  end_time: i64,
+) -> Result<bool, Status> {
+  let result = handle_sqlx(
+    query_scalar!(
+      "SELECT EXISTS(
+        SELECT 1 FROM SeatMaintenance
+        WHERE 
+          seat_id = ? AND 
+          (MAX(?, start_time) < MIN(?, end_time))
+      )",
+      seat_id,
+      start_time,
+      end_time
+    )
+    .fetch_one(pool)
+    .await,
+    "Selecting overlapping seat maintenance",
+  )?;
+
+  let under_maintenance: bool = result.is_some_and(|count| count != 0);
+
+  Ok(under_maintenance)
+}
+
 pub async fn update_seat_availability(
   pool: &Pool<Sqlite>,
   seat_id: u16,
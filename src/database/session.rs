@@ -0,0 +1,287 @@
+use super::common::*;
+use uuid::Uuid;
+
+// 登入時建立新的 session
+pub async fn insert_session(
+  pool: &Pool<Sqlite>,
+  session_id: &str,
+  user_name: &str,
+  refresh_token_hash: &str,
+  expires_at: i64,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  handle_sqlx(
+    query!(
+      "INSERT INTO Sessions
+        (session_id, user_name, refresh_token_hash, created_at, expires_at)
+      VALUES
+        (?, ?, ?, ?, ?)",
+      session_id,
+      user_name,
+      refresh_token_hash,
+      now,
+      expires_at,
+    )
+    .execute(pool)
+    .await,
+    "Inserting session",
+  )?;
+
+  Ok(())
+}
+
+// 為已通過驗證的使用者建立 session，回傳 access token 與 refresh token
+/*
+refresh token 只將雜湊值存入資料庫
+帳號密碼登入與 OIDC 登入共用
+*/
+pub async fn create_session(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  user_role: user::UserRole,
+) -> Result<token::TokenPair, Status> {
+  let session_id = Uuid::new_v4().simple().to_string();
+  let refresh_token = Uuid::new_v4().simple().to_string();
+  let expires_at = get_session_expiration(&user_role)?;
+
+  insert_session(
+    pool,
+    &session_id,
+    user_name,
+    &hash_token(&refresh_token),
+    expires_at,
+  )
+  .await?;
+
+  let access_token = create_userinfo_token(user_name, user_role, &session_id)?;
+
+  Ok(token::TokenPair {
+    access_token,
+    refresh_token,
+    expires_in: constant::ACCESS_TOKEN_MINUTES * 60,
+  })
+}
+
+// 以 refresh token 換發新的 refresh token，回傳 session id、使用者名稱與身分
+/*
+舊的 refresh token 換發後即失效
+若有人使用已被換發過的 refresh token，代表 token 可能已外洩，撤銷整個 session
+*/
+pub async fn rotate_refresh_token(
+  pool: &Pool<Sqlite>,
+  refresh_token_hash: &str,
+  new_refresh_token_hash: &str,
+) -> Result<(String, String, user::UserRole), Status> {
+  let now = get_now_timestamp();
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let session: Option<(String, String, user::UserRole)> = handle_sqlx(
+    query_as::<_, (String, String, user::UserRole)>(
+      "SELECT
+        Sessions.session_id,
+        Sessions.user_name,
+        Users.user_role
+      FROM
+        Sessions
+        INNER JOIN Users ON Users.user_name = Sessions.user_name
+      WHERE
+        Sessions.refresh_token_hash = ? AND
+        Sessions.revoked_at IS NULL AND
+        Sessions.expires_at > ? AND
+        Users.disabled = false",
+    )
+    .bind(refresh_token_hash)
+    .bind(now)
+    .fetch_optional(&mut *tx)
+    .await,
+    "Selecting session by refresh token",
+  )?;
+
+  let (session_id, user_name, user_role) = match session {
+    Some(session) => session,
+    None => {
+      let reused_rows = handle_sqlx(
+        query!(
+          "UPDATE Sessions
+          SET
+            revoked_at = ?
+          WHERE
+            previous_refresh_token_hash = ? AND
+            revoked_at IS NULL",
+          now,
+          refresh_token_hash,
+        )
+        .execute(&mut *tx)
+        .await,
+        "Revoking session of reused refresh token",
+      )?
+      .rows_affected();
+
+      if reused_rows != 0 {
+        log::warn!("A rotated refresh token was reused, the session has been revoked");
+      } else {
+        log::warn!("The refresh token is invalid, expired or revoked");
+      }
+
+      handle_sqlx(tx.commit().await, "Committing transaction")?;
+      return Err(Status::Unauthorized);
+    }
+  };
+
+  handle_sqlx(
+    query!(
+      "UPDATE Sessions
+      SET
+        previous_refresh_token_hash = refresh_token_hash,
+        refresh_token_hash = ?
+      WHERE
+        session_id = ?",
+      new_refresh_token_hash,
+      session_id,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Rotating refresh token",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok((session_id, user_name, user_role))
+}
+
+pub async fn is_session_active(
+  pool: &Pool<Sqlite>,
+  session_id: &str,
+  user_name: &str,
+) -> Result<bool, Status> {
+  let now = get_now_timestamp();
+
+  let is_active: bool = handle_sqlx(
+    query_scalar(
+      "SELECT EXISTS(
+        SELECT 1 FROM Sessions
+        WHERE
+          session_id = ? AND
+          user_name = ? AND
+          revoked_at IS NULL AND
+          expires_at > ?
+      )",
+    )
+    .bind(session_id)
+    .bind(user_name)
+    .bind(now)
+    .fetch_one(pool)
+    .await,
+    "Checking if the session is active",
+  )?;
+
+  Ok(is_active)
+}
+
+// 查詢 session 的登入時間，換發 refresh token 不會改變
+pub async fn get_session_created_at(pool: &Pool<Sqlite>, session_id: &str) -> Result<i64, Status> {
+  let created_at = handle_sqlx(
+    query_scalar("SELECT created_at FROM Sessions WHERE session_id = ?")
+      .bind(session_id)
+      .fetch_one(pool)
+      .await,
+    "Selecting session creation time",
+  )?;
+
+  Ok(created_at)
+}
+
+// 登出，撤銷目前的 session
+pub async fn revoke_session(pool: &Pool<Sqlite>, session_id: &str) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Sessions
+      SET
+        revoked_at = ?
+      WHERE
+        session_id = ? AND
+        revoked_at IS NULL",
+      now,
+      session_id,
+    )
+    .execute(pool)
+    .await,
+    "Revoking session",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No active session found for revocation");
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 撤銷使用者所有的 session，可保留目前使用中的 session
+pub async fn revoke_user_sessions(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  except_session_id: Option<&str>,
+) -> Result<u64, Status> {
+  let now = get_now_timestamp();
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Sessions
+      SET
+        revoked_at = ?
+      WHERE
+        user_name = ? AND
+        revoked_at IS NULL AND
+        (? IS NULL OR session_id != ?)",
+      now,
+      user_name,
+      except_session_id,
+      except_session_id,
+    )
+    .execute(pool)
+    .await,
+    "Revoking sessions of the user",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows)
+}
+
+pub async fn get_user_sessions(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Vec<token::SessionRecord>, Status> {
+  let sessions = handle_sqlx(
+    query_as::<_, (i64, i64, Option<i64>)>(
+      "SELECT created_at, expires_at, revoked_at FROM Sessions
+      WHERE
+        user_name = ?
+      ORDER BY
+        created_at",
+    )
+    .bind(user_name)
+    .fetch_all(pool)
+    .await,
+    "Selecting sessions of the user",
+  )?;
+
+  Ok(
+    sessions
+      .into_iter()
+      .map(
+        |(created_at, expires_at, revoked_at)| token::SessionRecord {
+          created_at,
+          expires_at,
+          revoked_at,
+        },
+      )
+      .collect(),
+  )
+}
@@ -0,0 +1,45 @@
+use super::common::*;
+use chrono::NaiveDate;
+
+// 查詢排程已產生不可預約時段到哪一天，尚未執行過則回傳 None
+pub async fn get_materialized_until(
+  pool: &Pool<Sqlite>,
+  task: &str,
+) -> Result<Option<NaiveDate>, Status> {
+  let materialized_until = handle_sqlx(
+    query_scalar::<_, NaiveDate>("SELECT materialized_until FROM TimerRuns WHERE task = ?")
+      .bind(task)
+      .fetch_optional(pool)
+      .await,
+    "Selecting materialized date of timer",
+  )?;
+
+  Ok(materialized_until)
+}
+
+// 記錄排程成功執行的時間與已產生到的日期
+pub async fn update_timer_run(
+  pool: &Pool<Sqlite>,
+  task: &str,
+  materialized_until: NaiveDate,
+) -> Result<(), Status> {
+  let last_run = get_now_timestamp();
+
+  let sql = "
+    INSERT OR REPLACE INTO TimerRuns
+      (task, last_run, materialized_until)
+    VALUES
+      (?1, ?2, ?3)";
+
+  handle_sqlx(
+    query(sql)
+      .bind(task)
+      .bind(last_run)
+      .bind(materialized_until)
+      .execute(pool)
+      .await,
+    "Updating timer run",
+  )?;
+
+  Ok(())
+}
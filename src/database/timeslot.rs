@@ -10,7 +10,7 @@ pub async fn is_overlapping_with_unavailable_timeslot(
       "SELECT EXISTS(
         SELECT 1 FROM UnavailableTimeSlots
         WHERE 
-          (MAX(datetime(?, 'unixepoch', '+8 hours'), start_time) < MIN(datetime(?, 'unixepoch', '+8 hours'), end_time))
+          (MAX(?, start_time) < MIN(?, end_time))
       )",
       start_time,
       end_time
@@ -34,8 +34,8 @@ pub async fn is_within_unavailable_timeslot(
       "SELECT EXISTS(
         SELECT 1 FROM UnavailableTimeSlots
         WHERE 
-          start_time <= datetime(?, 'unixepoch', '+8 hours') AND 
-          end_time > datetime(?, 'unixepoch', '+8 hours')
+          start_time <= ? AND 
+          end_time > ?
       )",
       time,
       time
@@ -54,17 +54,21 @@ pub async fn insert_unavailable_timeslot(
   pool: &Pool<Sqlite>,
   start_time: i64,
   end_time: i64,
+  reason: Option<&str>,
+  created_by: &str,
 ) -> Result<(), Status> {
   let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
 
   handle_sqlx(
     query!(
       "INSERT INTO UnavailableTimeSlots 
-        (start_time, end_time) 
+        (start_time, end_time, reason, created_by) 
       VALUES 
-        (datetime(?, 'unixepoch', '+8 hours'), datetime(?, 'unixepoch', '+8 hours'))",
+        (?, ?, ?, ?)",
       start_time,
-      end_time
+      end_time,
+      reason,
+      created_by
     )
     .execute(&mut *tx)
     .await,
@@ -75,3 +79,327 @@ pub async fn insert_unavailable_timeslot(
 
   Ok(())
 }
+
+const SELECT_UNAVAILABLE_TIMESLOT: &str = "
+    SELECT
+      id,
+      start_time,
+      end_time,
+      reason,
+      created_by,
+      source_uid
+    FROM
+      UnavailableTimeSlots";
+
+pub async fn get_unavailable_timeslots(
+  pool: &Pool<Sqlite>,
+  start_time: Option<i64>,
+  end_time: Option<i64>,
+) -> Result<Vec<timeslot::UnavailableTimeSlot>, Status> {
+  /*
+  獲取不可預約時段，可依時間範圍篩選
+   */
+  let sql = format!(
+    "{}
+    WHERE
+      (?1 IS NULL OR end_time > ?1) AND
+      (?2 IS NULL OR start_time < ?2)
+    ORDER BY
+      start_time",
+    SELECT_UNAVAILABLE_TIMESLOT
+  );
+
+  let timeslots = handle_sqlx(
+    query_as::<_, timeslot::UnavailableTimeSlot>(&sql)
+      .bind(start_time)
+      .bind(end_time)
+      .fetch_all(pool)
+      .await,
+    "Selecting unavailable time slots",
+  )?;
+
+  Ok(timeslots)
+}
+
+pub async fn get_unavailable_timeslot(
+  pool: &Pool<Sqlite>,
+  id: i64,
+) -> Result<timeslot::UnavailableTimeSlot, Status> {
+  let sql = format!(
+    "{}
+    WHERE
+      id = ?",
+    SELECT_UNAVAILABLE_TIMESLOT
+  );
+
+  let timeslot = handle_sqlx(
+    query_as::<_, timeslot::UnavailableTimeSlot>(&sql)
+      .bind(id)
+      .fetch_one(pool)
+      .await,
+    "Selecting unavailable time slot",
+  )?;
+
+  Ok(timeslot)
+}
+
+// 查詢即將到來的休館時段，不包含每日開放時間以外的固定時段(由排程產生且沒有原因)
+pub async fn get_upcoming_closures(
+  pool: &Pool<Sqlite>,
+  now: i64,
+) -> Result<Vec<timeslot::Closure>, Status> {
+  let sql = "
+    SELECT
+      start_time,
+      end_time,
+      reason
+    FROM
+      UnavailableTimeSlots
+    WHERE
+      end_time > ?1 AND
+      NOT (created_by = ?2 AND source_uid IS NULL AND reason IS NULL)
+    ORDER BY
+      start_time";
+
+  let closures = handle_sqlx(
+    query_as::<_, timeslot::Closure>(sql)
+      .bind(now)
+      .bind(constant::SYSTEM_USER)
+      .fetch_all(pool)
+      .await,
+    "Selecting upcoming closures",
+  )?;
+
+  Ok(closures)
+}
+
+pub async fn update_unavailable_timeslot(
+  pool: &Pool<Sqlite>,
+  id: i64,
+  start_time: i64,
+  end_time: i64,
+  reason: Option<&str>,
+) -> Result<(), Status> {
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let exists: bool = handle_sqlx(
+    query_scalar(
+      "SELECT EXISTS(
+        SELECT 1 FROM UnavailableTimeSlots
+        WHERE
+          start_time = ?1 AND
+          end_time = ?2 AND
+          id != ?3
+      )",
+    )
+    .bind(start_time)
+    .bind(end_time)
+    .bind(id)
+    .fetch_one(&mut *tx)
+    .await,
+    "Checking if the time slot already exists",
+  )?;
+
+  if exists {
+    log::warn!("Another unavailable time slot has the same time");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::Conflict);
+  }
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE UnavailableTimeSlots
+      SET
+        start_time = ?,
+        end_time = ?,
+        reason = ?
+      WHERE
+        id = ?",
+      start_time,
+      end_time,
+      reason,
+      id,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Updating unavailable time slot",
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，此次操作無作用到任何資料
+  if affected_rows == 0 {
+    log::warn!("No unavailable time slot found for updation");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::NotFound);
+  }
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+pub async fn delete_unavailable_timeslot(pool: &Pool<Sqlite>, id: i64) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!("DELETE FROM UnavailableTimeSlots WHERE id = ?", id)
+      .execute(pool)
+      .await,
+    "Deleting unavailable time slot",
+  )?
+  .rows_affected();
+
+  // affected_rows == 0，此次操作無作用到任何資料
+  if affected_rows == 0 {
+    log::warn!("No unavailable time slot found for deletion");
+
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 刪除排程依開放時間產生、且在指定時間範圍內開始的不可預約時段，管理員建立與匯入的時段不受影響
+pub async fn delete_generated_closures(
+  pool: &Pool<Sqlite>,
+  start_time: i64,
+  end_time: i64,
+) -> Result<(), Status> {
+  handle_sqlx(
+    query!(
+      "DELETE FROM UnavailableTimeSlots
+      WHERE
+        created_by = ? AND
+        source_uid IS NULL AND
+        start_time >= ? AND
+        start_time <= ?",
+      constant::SYSTEM_USER,
+      start_time,
+      end_time
+    )
+    .execute(pool)
+    .await,
+    "Deleting generated closures",
+  )?;
+
+  Ok(())
+}
+
+// 以 iCalendar 事件 UID 取代對應的不可預約時段，重複匯入時不會產生重複資料
+/*
+與管理員建立、排程產生或其他事件匯入的時段時間完全相同時不覆寫，回傳略過的時段數量
+*/
+pub async fn replace_calendar_event_timeslots(
+  pool: &Pool<Sqlite>,
+  source_uid: &str,
+  time_slots: &[(i64, i64)],
+  reason: Option<&str>,
+  created_by: &str,
+) -> Result<(timeslot::ImportOutcome, usize), Status> {
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let existing: Vec<(i64, i64, Option<String>)> = handle_sqlx(
+    query_as::<_, (i64, i64, Option<String>)>(
+      "SELECT
+        start_time,
+        end_time,
+        reason
+      FROM
+        UnavailableTimeSlots
+      WHERE
+        source_uid = ?
+      ORDER BY
+        start_time, end_time",
+    )
+    .bind(source_uid)
+    .fetch_all(&mut *tx)
+    .await,
+    "Selecting imported unavailable time slots",
+  )?;
+
+  let mut importable: Vec<(i64, i64)> = Vec::new();
+
+  for (start_time, end_time) in time_slots.iter() {
+    let is_conflicting = handle_sqlx(
+      query_scalar::<_, bool>(
+        "SELECT EXISTS(
+          SELECT 1 FROM UnavailableTimeSlots
+          WHERE
+            start_time = ?1 AND
+            end_time = ?2 AND
+            (source_uid IS NULL OR source_uid != ?3)
+        )",
+      )
+      .bind(start_time)
+      .bind(end_time)
+      .bind(source_uid)
+      .fetch_one(&mut *tx)
+      .await,
+      "Checking conflicting unavailable time slot",
+    )?;
+
+    if is_conflicting {
+      log::warn!(
+        "Skipping time slot of {} which conflicts with an existing one: {} ~ {}",
+        source_uid,
+        start_time,
+        end_time
+      );
+    } else {
+      importable.push((*start_time, *end_time));
+    }
+  }
+
+  let conflicts = time_slots.len() - importable.len();
+
+  let unchanged = existing.len() == importable.len()
+    && existing.iter().zip(importable.iter()).all(
+      |((start, end, old_reason), (new_start, new_end))| {
+        start == new_start && end == new_end && old_reason.as_deref() == reason
+      },
+    );
+
+  if unchanged {
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Ok((timeslot::ImportOutcome::Unchanged, conflicts));
+  }
+
+  handle_sqlx(
+    query!(
+      "DELETE FROM UnavailableTimeSlots WHERE source_uid = ?",
+      source_uid
+    )
+    .execute(&mut *tx)
+    .await,
+    "Deleting imported unavailable time slots",
+  )?;
+
+  for (start_time, end_time) in importable.iter() {
+    handle_sqlx(
+      query!(
+        "INSERT INTO UnavailableTimeSlots
+          (start_time, end_time, reason, created_by, source_uid)
+        VALUES
+          (?, ?, ?, ?, ?)",
+        start_time,
+        end_time,
+        reason,
+        created_by,
+        source_uid
+      )
+      .execute(&mut *tx)
+      .await,
+      "Inserting imported unavailable time slot",
+    )?;
+  }
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  if existing.is_empty() {
+    Ok((timeslot::ImportOutcome::Created, conflicts))
+  } else {
+    Ok((timeslot::ImportOutcome::Updated, conflicts))
+  }
+}
@@ -0,0 +1,334 @@
+use super::common::*;
+use sqlx::SqliteConnection;
+
+// 查詢兩步驟驗證的設定，回傳密鑰與是否已啟用
+pub async fn get_totp_secret(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<(Option<String>, bool), Status> {
+  let totp = handle_sqlx(
+    query_as::<_, (Option<String>, bool)>(
+      "SELECT totp_secret, totp_enabled FROM Users WHERE user_name = ?",
+    )
+    .bind(user_name)
+    .fetch_optional(pool)
+    .await,
+    "Selecting TOTP secret",
+  )?;
+
+  totp.ok_or_else(|| {
+    log::warn!("No user found with user name: {}", user_name);
+    Status::NotFound
+  })
+}
+
+pub async fn is_totp_enabled(pool: &Pool<Sqlite>, user_name: &str) -> Result<bool, Status> {
+  let (_, enabled) = get_totp_secret(pool, user_name).await?;
+
+  Ok(enabled)
+}
+
+// 儲存尚未啟用的密鑰，已啟用時需先停用才能重新設定
+pub async fn set_totp_secret(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  secret: &str,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        totp_secret = ?,
+        totp_last_step = NULL
+      WHERE
+        user_name = ? AND
+        totp_enabled = false",
+      secret,
+      user_name,
+    )
+    .execute(pool)
+    .await,
+    "Setting TOTP secret",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("TOTP is already enabled for user: {}", user_name);
+    return Err(Status::Conflict);
+  }
+
+  Ok(())
+}
+
+async fn replace_recovery_codes(
+  conn: &mut SqliteConnection,
+  user_name: &str,
+  code_hashes: &[String],
+) -> Result<(), Status> {
+  handle_sqlx(
+    query!(
+      "DELETE FROM TotpRecoveryCodes WHERE user_name = ?",
+      user_name
+    )
+    .execute(&mut *conn)
+    .await,
+    "Deleting TOTP recovery codes",
+  )?;
+
+  for code_hash in code_hashes {
+    handle_sqlx(
+      query!(
+        "INSERT INTO TotpRecoveryCodes
+          (user_name, code_hash)
+        VALUES
+          (?, ?)",
+        user_name,
+        code_hash,
+      )
+      .execute(&mut *conn)
+      .await,
+      "Inserting TOTP recovery code",
+    )?;
+  }
+
+  Ok(())
+}
+
+// 確認驗證碼後啟用兩步驟驗證，並產生新的備用碼
+pub async fn enable_totp(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  step: i64,
+  code_hashes: &[String],
+) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        totp_enabled = true,
+        totp_last_step = ?
+      WHERE
+        user_name = ? AND
+        totp_enabled = false AND
+        totp_secret IS NOT NULL",
+      step,
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Enabling TOTP",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!(
+      "TOTP is already enabled or not set up for user: {}",
+      user_name
+    );
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::Conflict);
+  }
+
+  replace_recovery_codes(&mut tx, user_name, code_hashes).await?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+pub async fn reset_recovery_codes(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  code_hashes: &[String],
+) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  replace_recovery_codes(&mut tx, user_name, code_hashes).await?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+// 停用兩步驟驗證，刪除密鑰、備用碼與尚未完成的登入
+pub async fn disable_totp(pool: &Pool<Sqlite>, user_name: &str) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        totp_secret = NULL,
+        totp_enabled = false,
+        totp_last_step = NULL
+      WHERE
+        user_name = ?",
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Disabling TOTP",
+  )?;
+
+  for table_name in ["TotpRecoveryCodes", "TotpChallenges"] {
+    let sql = format!("DELETE FROM {} WHERE user_name = ?", table_name);
+
+    handle_sqlx(
+      query(&sql).bind(user_name).execute(&mut *tx).await,
+      &format!("Deleting {} of the user", table_name),
+    )?;
+  }
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+// 記錄使用過的時間步數，同一時間步數(含更早的)的驗證碼不可再次使用
+pub async fn use_totp_step(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  step: i64,
+) -> Result<bool, Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        totp_last_step = ?
+      WHERE
+        user_name = ? AND
+        (totp_last_step IS NULL OR totp_last_step < ?)",
+      step,
+      user_name,
+      step,
+    )
+    .execute(pool)
+    .await,
+    "Using TOTP step",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows != 0)
+}
+
+// 使用備用碼，每組只能使用一次
+pub async fn use_recovery_code(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  code_hash: &str,
+) -> Result<bool, Status> {
+  let now = get_now_timestamp();
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE TotpRecoveryCodes
+      SET
+        used_at = ?
+      WHERE
+        user_name = ? AND
+        code_hash = ? AND
+        used_at IS NULL",
+      now,
+      user_name,
+      code_hash,
+    )
+    .execute(pool)
+    .await,
+    "Using TOTP recovery code",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows != 0)
+}
+
+// 密碼驗證通過後建立 challenge，並順便清除已過期的 challenge
+pub async fn insert_totp_challenge(
+  pool: &Pool<Sqlite>,
+  challenge_hash: &str,
+  user_name: &str,
+  expires_at: i64,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  handle_sqlx(
+    query!("DELETE FROM TotpChallenges WHERE expires_at <= ?", now)
+      .execute(pool)
+      .await,
+    "Deleting expired TOTP challenges",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "INSERT INTO TotpChallenges
+        (challenge_hash, user_name, expires_at)
+      VALUES
+        (?, ?, ?)",
+      challenge_hash,
+      user_name,
+      expires_at,
+    )
+    .execute(pool)
+    .await,
+    "Inserting TOTP challenge",
+  )?;
+
+  Ok(())
+}
+
+// 查詢 challenge 所屬的使用者與身分，過期或不存在時回傳 401
+pub async fn get_totp_challenge_user(
+  pool: &Pool<Sqlite>,
+  challenge_hash: &str,
+) -> Result<(String, user::UserRole), Status> {
+  let now = get_now_timestamp();
+
+  let user = handle_sqlx(
+    query_as::<_, (String, user::UserRole)>(
+      "SELECT
+        Users.user_name,
+        Users.user_role
+      FROM
+        TotpChallenges
+        INNER JOIN Users ON Users.user_name = TotpChallenges.user_name
+      WHERE
+        TotpChallenges.challenge_hash = ? AND
+        TotpChallenges.expires_at > ?",
+    )
+    .bind(challenge_hash)
+    .bind(now)
+    .fetch_optional(pool)
+    .await,
+    "Selecting TOTP challenge",
+  )?;
+
+  user.ok_or_else(|| {
+    log::warn!("The TOTP challenge is invalid or expired");
+    Status::Unauthorized
+  })
+}
+
+// 完成登入後刪除 challenge，每個 challenge 只能使用一次
+pub async fn delete_totp_challenge(
+  pool: &Pool<Sqlite>,
+  challenge_hash: &str,
+) -> Result<bool, Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "DELETE FROM TotpChallenges WHERE challenge_hash = ?",
+      challenge_hash
+    )
+    .execute(pool)
+    .await,
+    "Deleting TOTP challenge",
+  )?
+  .rows_affected();
+
+  Ok(affected_rows != 0)
+}
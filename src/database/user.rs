@@ -1,17 +1,22 @@
 use super::common::*;
+use sqlx::SqliteConnection;
 
-// 註冊
+// 註冊，verification_token 為驗證 token 的雜湊值
 pub async fn insert_new_user_info(
   pool: &Pool<Sqlite>,
   user_info: user::UserInfo,
 ) -> Result<(), Status> {
   log::info!("Inserting new user information");
 
+  let now = get_now_timestamp();
+  let expires_at = now + constant::VERIFICATION_TOKEN_HOURS * 60 * 60;
+
   let sql = "
     INSERT INTO Users 
-      (user_name, password_hash, email, user_role, verified, verification_token) 
+      (user_name, password_hash, email, user_role, verified, verification_token,
+       verification_token_expires_at, verification_sent_at, created_at) 
     VALUES 
-      (?1, ?2, ?3, ?4, ?5, ?6)";
+      (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)";
 
   handle_sqlx(
     query(sql)
@@ -21,6 +26,8 @@ pub async fn insert_new_user_info(
       .bind(user_info.user_role)
       .bind(user_info.verified)
       .bind(user_info.verification_token)
+      .bind(expires_at)
+      .bind(now)
       .execute(pool)
       .await,
     "Inserting new user information",
@@ -50,87 +57,369 @@ pub async fn get_user_info(pool: &Pool<Sqlite>, user_name: &str) -> Result<user:
   Ok(user_info)
 }
 
+// 驗證 email，若為變更 email 的驗證則以新的 email 取代原本的 email
+/*
+token 只能使用一次，驗證後即清除；過期的 token 需重新寄送驗證信
+*/
 pub async fn update_user_verified_by_token(
   pool: &Pool<Sqlite>,
-  verification_token: &str,
+  verification_token_hash: &str,
 ) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
   let sql = "
     UPDATE Users 
     SET 
-      verified = true 
+      verified = true,
+      email = COALESCE(pending_email, email),
+      pending_email = NULL,
+      verification_token = NULL,
+      verification_token_expires_at = NULL
     WHERE 
-      verification_token = ?";
+      verification_token = ? AND
+      verification_token_expires_at > ?";
 
   let result = handle_sqlx(
-    query(sql).bind(verification_token).execute(pool).await,
+    query(sql)
+      .bind(verification_token_hash)
+      .bind(now)
+      .execute(pool)
+      .await,
     "Updating user verified by verification_token",
   )?;
 
   let affected_rows = result.rows_affected();
 
   if affected_rows == 0 {
-    log::warn!("No Users found for updation");
+    log::warn!("The verification token is invalid, expired or already used");
     return Err(Status::NotFound);
   }
 
   Ok(())
 }
 
+// 重新產生驗證 token，回傳要寄送驗證信的 email
+/*
+對象為尚未驗證的帳號，或正在變更 email 的帳號(寄到新的 email)
+距離上次寄送未超過 VERIFICATION_RESEND_COOLDOWN_SECONDS 時不會更新，回傳 None
+*/
+pub async fn renew_verification_token(
+  pool: &Pool<Sqlite>,
+  email: &str,
+  verification_token_hash: &str,
+) -> Result<Option<String>, Status> {
+  let now = get_now_timestamp();
+  let expires_at = now + constant::VERIFICATION_TOKEN_HOURS * 60 * 60;
+  let resend_after = now - constant::VERIFICATION_RESEND_COOLDOWN_SECONDS;
+
+  let email = handle_sqlx(
+    query_scalar::<_, String>(
+      "UPDATE Users
+      SET
+        verification_token = ?,
+        verification_token_expires_at = ?,
+        verification_sent_at = ?
+      WHERE
+        ((verified = false AND email = ?) OR pending_email = ?) AND
+        (verification_sent_at IS NULL OR verification_sent_at <= ?) AND
+        deleted_at IS NULL
+      RETURNING
+        COALESCE(pending_email, email)",
+    )
+    .bind(verification_token_hash)
+    .bind(expires_at)
+    .bind(now)
+    .bind(email)
+    .bind(email)
+    .bind(resend_after)
+    .fetch_optional(pool)
+    .await,
+    "Renewing verification token",
+  )?;
+
+  Ok(email)
+}
+
+// 刪除註冊後超過期限仍未驗證 email 的帳號，釋出使用者名稱與 email
+/*
+只刪除未匿名化，且沒有預約、黑名單、座位問題回報與 API key 紀錄的帳號
+一併刪除其重設密碼 token、session、行事曆訂閱與兩步驟驗證的資料
+*/
+pub async fn delete_unverified_users(
+  pool: &Pool<Sqlite>,
+  created_before: i64,
+) -> Result<u64, Status> {
+  let candidates = "
+    SELECT user_name FROM Users
+    WHERE
+      verified = false AND
+      deleted_at IS NULL AND
+      created_at <= ?1 AND
+      user_name NOT IN (SELECT user_name FROM Reservations) AND
+      user_name NOT IN (SELECT user_name FROM BlackList) AND
+      user_name NOT IN (SELECT user_name FROM ApiKeys) AND
+      user_name NOT IN (SELECT user_name FROM SeatIssues) AND
+      user_name NOT IN (SELECT assignee FROM SeatIssues WHERE assignee IS NOT NULL)";
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  // 先刪除參照帳號的登入、驗證資料，再刪除帳號
+  for table_name in [
+    "PasswordResetTokens",
+    "Sessions",
+    "CalendarFeeds",
+    "TotpRecoveryCodes",
+    "TotpChallenges",
+  ] {
+    handle_sqlx(
+      query(&format!(
+        "DELETE FROM {} WHERE user_name IN ({})",
+        table_name, candidates
+      ))
+      .bind(created_before)
+      .execute(&mut *tx)
+      .await,
+      &format!("Deleting {} of unverified users", table_name),
+    )?;
+  }
+
+  let affected_rows = handle_sqlx(
+    query(&format!(
+      "DELETE FROM Users WHERE user_name IN ({})",
+      candidates
+    ))
+    .bind(created_before)
+    .execute(&mut *tx)
+    .await,
+    "Deleting unverified users",
+  )?
+  .rows_affected();
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(affected_rows)
+}
+
+// 新增停權紀錄，尚未結束的停權會被新的停權取代
+/*
+cancel_reservations 為 true 時，一併取消與停權期間重疊且尚未開始的預約，回傳取消的預約數
+進行中且與停權期間重疊的預約，改為在停權開始時(已開始則為現在)結束，同樣計入回傳的數量
+*/
 pub async fn insert_user_to_blacklist(
   pool: &Pool<Sqlite>,
   user_name: &str,
   start_time: i64,
   end_time: i64,
-) -> Result<(), Status> {
-  let result = handle_sqlx(
+  reason: Option<&str>,
+  created_by: &str,
+  cancel_reservations: bool,
+) -> Result<u64, Status> {
+  let now = get_now_timestamp();
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  handle_sqlx(
+    query!(
+      "UPDATE BlackList
+      SET
+        lifted_at = ?,
+        lifted_by = ?
+      WHERE
+        user_name = ? AND
+        lifted_at IS NULL AND
+        end_time > ?",
+      now,
+      created_by,
+      user_name,
+      now,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Lifting previous bans of the user",
+  )?;
+
+  handle_sqlx(
     query!(
       "INSERT INTO BlackList
-        (user_name, start_time, end_time)
-      VALUES 
-        (
-          ?, 
-          datetime(?, 'unixepoch', '+8 hours'), 
-          datetime(?, 'unixepoch', '+8 hours')
-        )",
+        (user_name, start_time, end_time, reason, created_by, created_at)
+      VALUES
+        (?, ?, ?, ?, ?, ?)",
       user_name,
       start_time,
-      end_time
+      end_time,
+      reason,
+      created_by,
+      now,
     )
-    .execute(pool)
+    .execute(&mut *tx)
     .await,
     "Inserting user to balck list",
   )?;
 
-  let affected_rows = result.rows_affected();
+  let cancelled_reservations = if cancel_reservations {
+    /*
+    提前結束後的時段若與先前取消的預約相同，會與主鍵衝突
+    比照重新預約的作法恢復該筆已取消的預約，並將進行中的預約標記為已取消，保留兩筆紀錄
+     */
+    handle_sqlx(
+      query(
+        "UPDATE Reservations AS cancelled
+        SET
+          (seat_id, checked_in_at) = (
+            SELECT seat_id, checked_in_at FROM Reservations
+            WHERE
+              user_name = ?1 AND
+              start_time = cancelled.start_time AND
+              end_time > MAX(?2, ?3) AND
+              cancelled_at IS NULL
+          ),
+          sequence = sequence + 1,
+          cancelled_at = NULL
+        WHERE
+          user_name = ?1 AND
+          end_time = MAX(?2, ?3) AND
+          cancelled_at IS NOT NULL AND
+          EXISTS(
+            SELECT 1 FROM Reservations
+            WHERE
+              user_name = ?1 AND
+              start_time = cancelled.start_time AND
+              start_time < MAX(?2, ?3) AND
+              start_time <= ?2 AND
+              end_time > MAX(?2, ?3) AND
+              ?4 > ?2 AND
+              cancelled_at IS NULL
+          )",
+      )
+      .bind(user_name)
+      .bind(now)
+      .bind(start_time)
+      .bind(end_time)
+      .execute(&mut *tx)
+      .await,
+      "Restoring cancelled reservations of the banned user",
+    )?;
 
-  if affected_rows == 0 {
-    log::warn!("No insert operation was executed");
-    return Err(Status::NotFound);
-  }
+    let replaced_reservations = handle_sqlx(
+      query(
+        "UPDATE Reservations AS ongoing
+        SET
+          sequence = sequence + 1,
+          cancelled_at = ?2
+        WHERE
+          user_name = ?1 AND
+          end_time > MAX(?2, ?3) AND
+          cancelled_at IS NULL AND
+          EXISTS(
+            SELECT 1 FROM Reservations
+            WHERE
+              user_name = ?1 AND
+              start_time = ongoing.start_time AND
+              end_time = MAX(?2, ?3) AND
+              cancelled_at IS NULL
+          )",
+      )
+      .bind(user_name)
+      .bind(now)
+      .bind(start_time)
+      .execute(&mut *tx)
+      .await,
+      "Replacing in-progress reservations of the banned user",
+    )?
+    .rows_affected();
 
-  Ok(())
+    let ended_reservations = handle_sqlx(
+      query(
+        "UPDATE Reservations
+        SET
+          sequence = sequence + 1,
+          end_time = MAX(?2, ?3)
+        WHERE
+          user_name = ?1 AND
+          start_time < MAX(?2, ?3) AND
+          start_time <= ?2 AND
+          end_time > MAX(?2, ?3) AND
+          ?4 > ?2 AND
+          cancelled_at IS NULL",
+      )
+      .bind(user_name)
+      .bind(now)
+      .bind(start_time)
+      .bind(end_time)
+      .execute(&mut *tx)
+      .await,
+      "Ending in-progress reservations of the banned user",
+    )?
+    .rows_affected();
+
+    let cancelled_reservations = handle_sqlx(
+      query!(
+        "UPDATE Reservations
+        SET
+          sequence = sequence + 1,
+          cancelled_at = ?
+        WHERE
+          user_name = ? AND
+          start_time >= ? AND
+          start_time < ? AND
+          end_time > ? AND
+          cancelled_at IS NULL",
+        now,
+        user_name,
+        now,
+        end_time,
+        start_time,
+      )
+      .execute(&mut *tx)
+      .await,
+      "Cancelling reservations of the banned user",
+    )?
+    .rows_affected();
+
+    replaced_reservations + ended_reservations + cancelled_reservations
+  } else {
+    0
+  };
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(cancelled_reservations)
 }
 
-pub async fn delete_user_from_blacklist(
+// 解除尚未結束的停權，紀錄仍保留在停權歷史中
+pub async fn lift_user_bans(
   pool: &Pool<Sqlite>,
   user_name: &str,
+  lifted_by: &str,
 ) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
   let result = handle_sqlx(
     query!(
-      "DELETE FROM BlackList
+      "UPDATE BlackList
+      SET
+        lifted_at = ?,
+        lifted_by = ?
       WHERE
-        user_name = ? ",
+        user_name = ? AND
+        lifted_at IS NULL AND
+        end_time > ?",
+      now,
+      lifted_by,
       user_name,
+      now,
     )
     .execute(pool)
     .await,
-    "Deleting user from balck list",
+    "Lifting bans of the user",
   )?;
 
   let affected_rows = result.rows_affected();
 
   if affected_rows == 0 {
-    log::warn!("No delete operation was executed from BlackList");
+    log::warn!("No active or upcoming ban was found in BlackList");
     return Err(Status::NotFound);
   }
 
@@ -138,27 +427,1112 @@ pub async fn delete_user_from_blacklist(
 }
 
 pub async fn is_user_in_blacklist(pool: &Pool<Sqlite>, user_name: &str) -> Result<bool, Status> {
-  let now = naive_datetime_to_timestamp(get_now()).unwrap();
+  let ban = get_active_ban(pool, user_name).await?;
 
-  let result = handle_sqlx(
-    query_scalar!(
+  Ok(ban.is_some())
+}
+
+// 查詢預約時段是否與停權期間重疊，包含尚未開始的停權
+pub async fn is_user_banned_during(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  start_time: i64,
+  end_time: i64,
+) -> Result<bool, Status> {
+  let banned: bool = handle_sqlx(
+    query_scalar(
       "SELECT EXISTS(
         SELECT 1 FROM BlackList
-        WHERE 
-          user_name = ? AND 
-          start_time <= datetime(?, 'unixepoch', '+8 hours') AND 
-          end_time > datetime(?, 'unixepoch', '+8 hours')
+        WHERE
+          user_name = ? AND
+          start_time < ? AND
+          end_time > ? AND
+          lifted_at IS NULL
       )",
-      user_name,
-      now,
-      now
     )
+    .bind(user_name)
+    .bind(end_time)
+    .bind(start_time)
     .fetch_one(pool)
     .await,
+    "Checking if the user is banned during the period",
+  )?;
+
+  Ok(banned)
+}
+
+// 查詢目前生效中的停權
+pub async fn get_active_ban(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Option<user::BanInfo>, Status> {
+  let now = get_now_timestamp();
+
+  let ban = handle_sqlx(
+    query_as::<_, (i64, i64, Option<String>)>(
+      "SELECT start_time, end_time, reason FROM BlackList
+      WHERE
+        user_name = ?1 AND
+        start_time <= ?2 AND
+        end_time > ?2 AND
+        lifted_at IS NULL
+      ORDER BY
+        end_time DESC",
+    )
+    .bind(user_name)
+    .bind(now)
+    .fetch_optional(pool)
+    .await,
     "Checking if the user is currently listed in the blacklist",
   )?;
 
-  let is_within_blacklist: bool = result.map_or(false, |count| count != 0);
+  Ok(ban.map(|(start_time, end_time, reason)| user::BanInfo {
+    start_time,
+    end_time,
+    reason,
+  }))
+}
+
+// 分頁的 OFFSET，page 過大導致溢位時回傳 400
+fn get_page_offset(page: i64, page_size: i64) -> Result<i64, Status> {
+  (page - 1).checked_mul(page_size).ok_or_else(|| {
+    log::warn!("The page: {} is out of range", page);
+    Status::BadRequest
+  })
+}
+
+// 管理員查詢停權紀錄，可依使用者與是否生效中篩選
+pub async fn get_bans(
+  pool: &Pool<Sqlite>,
+  user_name: Option<&str>,
+  active: Option<bool>,
+  page: i64,
+  page_size: i64,
+) -> Result<user::BanPage, Status> {
+  let now = get_now_timestamp();
+
+  let condition = "
+    (?1 IS NULL OR user_name = ?1) AND
+    (?2 IS NULL OR (lifted_at IS NULL AND start_time <= ?3 AND end_time > ?3) = ?2)";
+
+  let total: i64 = handle_sqlx(
+    query_scalar(&format!(
+      "SELECT COUNT(*) FROM BlackList WHERE {}",
+      condition
+    ))
+    .bind(user_name)
+    .bind(active)
+    .bind(now)
+    .fetch_one(pool)
+    .await,
+    "Counting bans",
+  )?;
+
+  let bans = handle_sqlx(
+    query_as::<_, user::BanRecord>(&format!(
+      "SELECT * FROM BlackList
+      WHERE {}
+      ORDER BY
+        ban_id DESC
+      LIMIT ?4 OFFSET ?5",
+      condition
+    ))
+    .bind(user_name)
+    .bind(active)
+    .bind(now)
+    .bind(page_size)
+    .bind(get_page_offset(page, page_size)?)
+    .fetch_all(pool)
+    .await,
+    "Selecting bans",
+  )?;
+
+  Ok(user::BanPage {
+    bans,
+    total,
+    page,
+    page_size,
+  })
+}
+
+pub async fn get_user_name_by_email(
+  pool: &Pool<Sqlite>,
+  email: &str,
+) -> Result<Option<String>, Status> {
+  let user_name = handle_sqlx(
+    query_scalar::<_, String>("SELECT user_name FROM Users WHERE email = ?")
+      .bind(email)
+      .fetch_optional(pool)
+      .await,
+    "Selecting user name by email",
+  )?;
+
+  Ok(user_name)
+}
+
+// 新增重設密碼 token，只儲存 token 的雜湊值，並順便清除已過期的 token
+pub async fn insert_password_reset_token(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  token_hash: &str,
+  expires_at: i64,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  handle_sqlx(
+    query!("DELETE FROM PasswordResetTokens WHERE expires_at <= ?", now)
+      .execute(pool)
+      .await,
+    "Deleting expired password reset tokens",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "INSERT INTO PasswordResetTokens
+        (token_hash, user_name, expires_at)
+      VALUES
+        (?, ?, ?)",
+      token_hash,
+      user_name,
+      expires_at,
+    )
+    .execute(pool)
+    .await,
+    "Inserting password reset token",
+  )?;
+
+  Ok(())
+}
+
+// 以重設密碼 token 更新密碼，成功後該使用者所有尚未使用的 token 皆失效
+pub async fn reset_password_by_token(
+  pool: &Pool<Sqlite>,
+  token_hash: &str,
+  password_hash: &str,
+) -> Result<String, Status> {
+  let now = get_now_timestamp();
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let user_name: Option<String> = handle_sqlx(
+    query_scalar::<_, String>(
+      "SELECT user_name FROM PasswordResetTokens
+      WHERE
+        token_hash = ? AND
+        expires_at > ?",
+    )
+    .bind(token_hash)
+    .bind(now)
+    .fetch_optional(&mut *tx)
+    .await,
+    "Selecting password reset token",
+  )?;
+
+  let user_name = match user_name {
+    Some(user_name) => user_name,
+    None => {
+      log::warn!("The password reset token is invalid or expired");
+
+      // rollback
+      handle_sqlx(tx.rollback().await, "Rolling back")?;
+      return Err(Status::BadRequest);
+    }
+  };
+
+  handle_sqlx(
+    query!(
+      "UPDATE Users SET password_hash = ? WHERE user_name = ?",
+      password_hash,
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Updating user password",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "DELETE FROM PasswordResetTokens WHERE user_name = ?",
+      user_name
+    )
+    .execute(&mut *tx)
+    .await,
+    "Deleting password reset tokens of the user",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(user_name)
+}
+
+pub async fn get_user_profile(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<user::UserProfile, Status> {
+  let now = get_now_timestamp();
 
-  Ok(is_within_blacklist)
+  // 只有目前仍在停權期間的黑名單紀錄才會被 JOIN
+  let (user_name, email, pending_email, user_role, verified, totp_enabled, ban_end_time) =
+    handle_sqlx(
+      query_as::<
+        _,
+        (
+          String,
+          String,
+          Option<String>,
+          user::UserRole,
+          bool,
+          bool,
+          Option<i64>,
+        ),
+      >(
+        "SELECT
+        Users.user_name,
+        Users.email,
+        Users.pending_email,
+        Users.user_role,
+        Users.verified,
+        Users.totp_enabled,
+        BlackList.end_time
+      FROM
+        Users
+        LEFT JOIN BlackList ON
+          BlackList.user_name = Users.user_name AND
+          BlackList.start_time <= ?1 AND
+          BlackList.end_time > ?1 AND
+          BlackList.lifted_at IS NULL
+      WHERE
+        Users.user_name = ?2",
+      )
+      .bind(now)
+      .bind(user_name)
+      .fetch_one(pool)
+      .await,
+      "Selecting user profile",
+    )?;
+
+  Ok(user::UserProfile {
+    user_name,
+    email,
+    pending_email,
+    user_role,
+    verified,
+    totp_enabled,
+    banned: ban_end_time.is_some(),
+    ban_end_time,
+  })
+}
+
+// 管理員查詢使用者列表，search 比對使用者名稱或 email 的部分字串
+pub async fn get_users(
+  pool: &Pool<Sqlite>,
+  search: Option<&str>,
+  page: i64,
+  page_size: i64,
+) -> Result<user::UserPage, Status> {
+  let now = get_now_timestamp();
+
+  // 跳脫 LIKE 的萬用字元，只做部分字串比對
+  let pattern = search.map(|search| {
+    format!(
+      "%{}%",
+      search
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+    )
+  });
+
+  let total: i64 = handle_sqlx(
+    query_scalar(
+      "SELECT COUNT(*) FROM Users
+      WHERE
+        ?1 IS NULL OR
+        user_name LIKE ?1 ESCAPE '\\' OR
+        email LIKE ?1 ESCAPE '\\'",
+    )
+    .bind(&pattern)
+    .fetch_one(pool)
+    .await,
+    "Counting users",
+  )?;
+
+  let users = handle_sqlx(
+    query_as::<_, user::UserSummary>(
+      "SELECT
+        Users.user_name,
+        Users.email,
+        Users.user_role,
+        Users.verified,
+        Users.totp_enabled,
+        Users.disabled,
+        Users.created_at,
+        EXISTS(
+          SELECT 1 FROM BlackList
+          WHERE
+            BlackList.user_name = Users.user_name AND
+            BlackList.start_time <= ?2 AND
+            BlackList.end_time > ?2 AND
+            BlackList.lifted_at IS NULL
+        ) AS banned
+      FROM
+        Users
+      WHERE
+        ?1 IS NULL OR
+        Users.user_name LIKE ?1 ESCAPE '\\' OR
+        Users.email LIKE ?1 ESCAPE '\\'
+      ORDER BY
+        Users.user_name
+      LIMIT ?3 OFFSET ?4",
+    )
+    .bind(&pattern)
+    .bind(now)
+    .bind(page_size)
+    .bind(get_page_offset(page, page_size)?)
+    .fetch_all(pool)
+    .await,
+    "Selecting users",
+  )?;
+
+  Ok(user::UserPage {
+    users,
+    total,
+    page,
+    page_size,
+  })
+}
+
+// 查詢帳號是否被停用，以及註冊時間
+pub async fn get_user_account_status(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<(bool, Option<i64>), Status> {
+  let status = handle_sqlx(
+    query_as::<_, (bool, Option<i64>)>(
+      "SELECT disabled, created_at FROM Users WHERE user_name = ?",
+    )
+    .bind(user_name)
+    .fetch_optional(pool)
+    .await,
+    "Selecting user account status",
+  )?;
+
+  status.ok_or_else(|| {
+    log::warn!("No user found with user name: {}", user_name);
+    Status::NotFound
+  })
+}
+
+pub async fn is_user_disabled(pool: &Pool<Sqlite>, user_name: &str) -> Result<bool, Status> {
+  let (disabled, _) = get_user_account_status(pool, user_name).await?;
+
+  Ok(disabled)
+}
+
+pub async fn update_user_role(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  user_role: user::UserRole,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query("UPDATE Users SET user_role = ? WHERE user_name = ?")
+      .bind(user_role)
+      .bind(user_name)
+      .execute(pool)
+      .await,
+    "Updating user role",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 管理員直接將帳號設為已驗證，尚未使用的驗證 token 隨即失效
+pub async fn force_verify_user(pool: &Pool<Sqlite>, user_name: &str) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        verified = true,
+        verification_token = NULL,
+        verification_token_expires_at = NULL
+      WHERE
+        user_name = ? AND
+        deleted_at IS NULL",
+      user_name,
+    )
+    .execute(pool)
+    .await,
+    "Force verifying user",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 管理員重寄驗證信時重新產生驗證 token，不受重寄間隔限制
+/*
+回傳要寄送驗證信的 email，已驗證且沒有變更中的 email 時回傳 None
+*/
+pub async fn renew_verification_token_by_user_name(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  verification_token_hash: &str,
+) -> Result<Option<String>, Status> {
+  let now = get_now_timestamp();
+  let expires_at = now + constant::VERIFICATION_TOKEN_HOURS * 60 * 60;
+
+  let email = handle_sqlx(
+    query_scalar::<_, String>(
+      "UPDATE Users
+      SET
+        verification_token = ?,
+        verification_token_expires_at = ?,
+        verification_sent_at = ?
+      WHERE
+        user_name = ? AND
+        (verified = false OR pending_email IS NOT NULL) AND
+        deleted_at IS NULL
+      RETURNING
+        COALESCE(pending_email, email)",
+    )
+    .bind(verification_token_hash)
+    .bind(expires_at)
+    .bind(now)
+    .bind(user_name)
+    .fetch_optional(pool)
+    .await,
+    "Renewing verification token",
+  )?;
+
+  Ok(email)
+}
+
+// 停用或重新啟用帳號
+pub async fn update_user_disabled(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  disabled: bool,
+) -> Result<(), Status> {
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users SET disabled = ? WHERE user_name = ?",
+      disabled,
+      user_name,
+    )
+    .execute(pool)
+    .await,
+    "Updating user disabled",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 登入時將舊的密碼雜湊值換成新的，密碼在此期間被變更時不更新
+pub async fn rehash_user_password(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  old_password_hash: &str,
+  password_hash: &str,
+) -> Result<(), Status> {
+  handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        password_hash = ?
+      WHERE
+        user_name = ? AND
+        password_hash = ?",
+      password_hash,
+      user_name,
+      old_password_hash,
+    )
+    .execute(pool)
+    .await,
+    "Rehashing user password",
+  )?;
+
+  Ok(())
+}
+
+// 更新密碼，並讓尚未使用的重設密碼 token 失效
+pub async fn update_user_password(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  password_hash: &str,
+) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users SET password_hash = ? WHERE user_name = ?",
+      password_hash,
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Updating user password",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::NotFound);
+  }
+
+  handle_sqlx(
+    query!(
+      "DELETE FROM PasswordResetTokens WHERE user_name = ?",
+      user_name
+    )
+    .execute(&mut *tx)
+    .await,
+    "Deleting password reset tokens of the user",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+// 暫存待驗證的新 email，驗證完成後才會取代原本的 email
+pub async fn update_user_pending_email(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  pending_email: &str,
+  verification_token_hash: &str,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+  let expires_at = now + constant::VERIFICATION_TOKEN_HOURS * 60 * 60;
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        pending_email = ?,
+        verification_token = ?,
+        verification_token_expires_at = ?,
+        verification_sent_at = ?
+      WHERE
+        user_name = ?",
+      pending_email,
+      verification_token_hash,
+      expires_at,
+      now,
+      user_name,
+    )
+    .execute(pool)
+    .await,
+    "Updating user pending email",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+    return Err(Status::NotFound);
+  }
+
+  Ok(())
+}
+
+// 變更使用者名稱，並一併更新所有參照該名稱的資料
+pub async fn update_user_name(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  new_user_name: &str,
+) -> Result<(), Status> {
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  let exists: bool = handle_sqlx(
+    query_scalar("SELECT EXISTS(SELECT 1 FROM Users WHERE user_name = ?)")
+      .bind(new_user_name)
+      .fetch_one(&mut *tx)
+      .await,
+    "Checking if the user name is taken",
+  )?;
+
+  if exists {
+    log::warn!("The user name: {} is already taken", new_user_name);
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::Conflict);
+  }
+
+  let affected_rows = rename_user(&mut tx, user_name, new_user_name).await?;
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for updation");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::NotFound);
+  }
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+// 在 transaction 中變更使用者名稱，並一併更新所有參照該名稱的資料，回傳更新的使用者數量
+async fn rename_user(
+  conn: &mut SqliteConnection,
+  user_name: &str,
+  new_user_name: &str,
+) -> Result<u64, Status> {
+  // 外鍵檢查延後到 commit 時，才能先更新 Users 再更新參照的資料表
+  handle_sqlx(
+    query("PRAGMA defer_foreign_keys = ON")
+      .execute(&mut *conn)
+      .await,
+    "Deferring foreign key checks",
+  )?;
+
+  let affected_rows = handle_sqlx(
+    query!(
+      "UPDATE Users SET user_name = ? WHERE user_name = ?",
+      new_user_name,
+      user_name,
+    )
+    .execute(&mut *conn)
+    .await,
+    "Updating user name",
+  )?
+  .rows_affected();
+
+  if affected_rows == 0 {
+    return Ok(0);
+  }
+
+  let references = [
+    ("Reservations", "user_name"),
+    ("BlackList", "user_name"),
+    ("BlackList", "created_by"),
+    ("BlackList", "lifted_by"),
+    ("SeatIssues", "user_name"),
+    ("SeatIssues", "assignee"),
+    ("CalendarFeeds", "user_name"),
+    ("PasswordResetTokens", "user_name"),
+    ("Sessions", "user_name"),
+    ("TotpRecoveryCodes", "user_name"),
+    ("TotpChallenges", "user_name"),
+    ("ApiKeys", "user_name"),
+    ("ApiKeys", "created_by"),
+    ("UnavailableTimeSlots", "created_by"),
+  ];
+
+  for (table_name, column_name) in references {
+    let sql = format!(
+      "UPDATE {} SET {} = ?1 WHERE {} = ?2",
+      table_name, column_name, column_name
+    );
+
+    handle_sqlx(
+      query(&sql)
+        .bind(new_user_name)
+        .bind(user_name)
+        .execute(&mut *conn)
+        .await,
+      &format!("Updating user name in {}.{}", table_name, column_name),
+    )?;
+  }
+
+  // 登入失敗紀錄以使用者名稱為 subject，先移除新名稱殘留的紀錄以免主鍵衝突
+  handle_sqlx(
+    query("DELETE FROM LoginThrottles WHERE kind = ? AND subject = ?")
+      .bind(user::LoginThrottleKind::Account)
+      .bind(new_user_name)
+      .execute(&mut *conn)
+      .await,
+    "Deleting login throttle of the new user name",
+  )?;
+
+  handle_sqlx(
+    query("UPDATE LoginThrottles SET subject = ? WHERE kind = ? AND subject = ?")
+      .bind(new_user_name)
+      .bind(user::LoginThrottleKind::Account)
+      .bind(user_name)
+      .execute(&mut *conn)
+      .await,
+    "Updating user name in LoginThrottles",
+  )?;
+
+  Ok(affected_rows)
+}
+
+pub async fn get_user_bans(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Vec<user::BanRecord>, Status> {
+  let bans = handle_sqlx(
+    query_as::<_, user::BanRecord>(
+      "SELECT * FROM BlackList
+      WHERE
+        user_name = ?
+      ORDER BY
+        start_time",
+    )
+    .bind(user_name)
+    .fetch_all(pool)
+    .await,
+    "Selecting bans of the user",
+  )?;
+
+  Ok(bans)
+}
+
+// 查詢使用者以管理員身分執行或解除的停權紀錄
+pub async fn get_bans_issued_by_user(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+) -> Result<Vec<user::BanRecord>, Status> {
+  let bans = handle_sqlx(
+    query_as::<_, user::BanRecord>(
+      "SELECT * FROM BlackList
+      WHERE
+        created_by = ?1 OR
+        lifted_by = ?1
+      ORDER BY
+        start_time",
+    )
+    .bind(user_name)
+    .fetch_all(pool)
+    .await,
+    "Selecting bans issued by the user",
+  )?;
+
+  Ok(bans)
+}
+
+// 刪除帳號
+/*
+為了保留預約統計，Users 與參照的資料不會被刪除，而是改為匿名名稱
+清除 email、密碼等個人資料，取消尚未開始的預約，並撤銷所有登入的 session 與 API key
+*/
+pub async fn anonymize_user(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  anonymous_name: &str,
+) -> Result<(), Status> {
+  let now = get_now_timestamp();
+
+  // 使用transaction
+  let mut tx = handle_sqlx(pool.begin().await, "Starting new transaction")?;
+
+  handle_sqlx(
+    query!(
+      "UPDATE Reservations
+      SET
+        sequence = sequence + 1,
+        cancelled_at = ?
+      WHERE
+        user_name = ? AND
+        start_time > ? AND
+        cancelled_at IS NULL",
+      now,
+      user_name,
+      now,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Cancelling upcoming reservations of the user",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "UPDATE Sessions
+      SET
+        revoked_at = ?
+      WHERE
+        user_name = ? AND
+        revoked_at IS NULL",
+      now,
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Revoking sessions of the user",
+  )?;
+
+  handle_sqlx(
+    query!(
+      "UPDATE ApiKeys
+      SET
+        revoked_at = ?
+      WHERE
+        user_name = ? AND
+        revoked_at IS NULL",
+      now,
+      user_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Revoking API keys of the user",
+  )?;
+
+  for table_name in [
+    "CalendarFeeds",
+    "PasswordResetTokens",
+    "TotpRecoveryCodes",
+    "TotpChallenges",
+  ] {
+    let sql = format!("DELETE FROM {} WHERE user_name = ?", table_name);
+
+    handle_sqlx(
+      query(&sql).bind(user_name).execute(&mut *tx).await,
+      &format!("Deleting {} of the user", table_name),
+    )?;
+  }
+
+  handle_sqlx(
+    query("DELETE FROM LoginThrottles WHERE kind = ? AND subject = ?")
+      .bind(user::LoginThrottleKind::Account)
+      .bind(user_name)
+      .execute(&mut *tx)
+      .await,
+    "Deleting login throttle of the user",
+  )?;
+
+  let affected_rows = rename_user(&mut tx, user_name, anonymous_name).await?;
+
+  if affected_rows == 0 {
+    log::warn!("No Users found for anonymization");
+
+    // rollback
+    handle_sqlx(tx.rollback().await, "Rolling back")?;
+    return Err(Status::NotFound);
+  }
+
+  // email 必須唯一，以匿名名稱產生不會寄出的位址；密碼設為無法通過驗證的值
+  // 保留 verified，以 deleted_at 標記為已刪除，避免被當成未驗證的帳號重寄驗證信或清除
+  let anonymous_email = format!("{}@deleted.invalid", anonymous_name);
+
+  handle_sqlx(
+    query!(
+      "UPDATE Users
+      SET
+        email = ?,
+        password_hash = '',
+        deleted_at = ?,
+        verification_token = NULL,
+        verification_token_expires_at = NULL,
+        pending_email = NULL,
+        oidc_subject = NULL,
+        totp_secret = NULL,
+        totp_enabled = false,
+        totp_last_step = NULL
+      WHERE
+        user_name = ?",
+      anonymous_email,
+      now,
+      anonymous_name,
+    )
+    .execute(&mut *tx)
+    .await,
+    "Anonymizing user",
+  )?;
+
+  handle_sqlx(tx.commit().await, "Committing transaction")?;
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::database::{self, calendar_feed, login_throttle, session, totp};
+
+  async fn insert_unverified_user(pool: &Pool<Sqlite>, user_name: &str) {
+    insert_new_user_info(
+      pool,
+      user::UserInfo {
+        user_name: user_name.to_string(),
+        password_hash: "password-hash".to_string(),
+        email: format!("{}@example.com", user_name),
+        user_role: user::UserRole::RegularUser,
+        verified: false,
+        verification_token: None,
+      },
+    )
+    .await
+    .unwrap();
+
+    // 建立登入後會參照帳號的資料
+    session::create_session(pool, user_name, user::UserRole::RegularUser)
+      .await
+      .unwrap();
+    calendar_feed::upsert_calendar_feed_token(pool, user_name, &format!("{}-feed", user_name))
+      .await
+      .unwrap();
+    totp::reset_recovery_codes(pool, user_name, &[format!("{}-code", user_name)])
+      .await
+      .unwrap();
+    totp::insert_totp_challenge(
+      pool,
+      &format!("{}-challenge", user_name),
+      user_name,
+      i64::MAX,
+    )
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn purges_unverified_users_but_keeps_anonymized_accounts() {
+    let pool = database::connect_test_pool().await;
+
+    insert_unverified_user(&pool, "alice").await;
+    insert_unverified_user(&pool, "bob").await;
+    anonymize_user(&pool, "alice", "deleted-alice")
+      .await
+      .unwrap();
+
+    let created_before = get_now_timestamp() + 1;
+    let deleted = delete_unverified_users(&pool, created_before)
+      .await
+      .unwrap();
+    assert_eq!(deleted, 1);
+
+    assert!(get_user_info(&pool, "deleted-alice").await.is_ok());
+    assert!(get_user_info(&pool, "bob").await.is_err());
+
+    for table_name in [
+      "Sessions",
+      "CalendarFeeds",
+      "TotpRecoveryCodes",
+      "TotpChallenges",
+    ] {
+      let (count,): (i64,) = query_as(&format!(
+        "SELECT COUNT(*) FROM {} WHERE user_name = 'bob'",
+        table_name
+      ))
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+      assert_eq!(count, 0, "{} of the purged user remain", table_name);
+    }
+
+    // 再次清除時不會因匿名化帳號留下的 session 而失敗
+    let deleted = delete_unverified_users(&pool, created_before)
+      .await
+      .unwrap();
+    assert_eq!(deleted, 0);
+  }
+
+  #[tokio::test]
+  async fn ban_keeps_earlier_cancelled_reservation_with_the_same_period() {
+    let pool = database::connect_test_pool().await;
+
+    insert_unverified_user(&pool, "carol").await;
+    query("INSERT OR IGNORE INTO Seats (seat_id, available, other_info) VALUES (1, 1, NULL)")
+      .execute(&pool)
+      .await
+      .unwrap();
+
+    // 進行中的預約，以及先前取消、結束時間恰為停權開始時間的預約
+    let now = get_now_timestamp();
+    let start_time = now - 60 * 60;
+    let ban_start_time = now + 60 * 60;
+    query(
+      "INSERT INTO Reservations
+        (user_name, seat_id, start_time, end_time, uid, cancelled_at)
+      VALUES
+        ('carol', 1, ?1, ?2, 'cancelled-uid', ?1),
+        ('carol', 1, ?1, ?3, 'ongoing-uid', NULL)",
+    )
+    .bind(start_time)
+    .bind(ban_start_time)
+    .bind(now + 3 * 60 * 60)
+    .execute(&pool)
+    .await
+    .unwrap();
+
+    let affected = insert_user_to_blacklist(
+      &pool,
+      "carol",
+      ban_start_time,
+      now + 24 * 60 * 60,
+      None,
+      "admin",
+      true,
+    )
+    .await
+    .unwrap();
+    assert_eq!(affected, 1);
+
+    let rows: Vec<(String, i64, bool)> = query_as(
+      "SELECT uid, end_time, cancelled_at IS NOT NULL FROM Reservations
+      WHERE user_name = 'carol'
+      ORDER BY uid",
+    )
+    .fetch_all(&pool)
+    .await
+    .unwrap();
+    assert_eq!(
+      rows,
+      vec![
+        ("cancelled-uid".to_string(), ban_start_time, false),
+        ("ongoing-uid".to_string(), now + 3 * 60 * 60, true),
+      ]
+    );
+  }
+
+  #[tokio::test]
+  async fn renaming_and_anonymizing_update_login_throttles() {
+    let pool = database::connect_test_pool().await;
+
+    insert_unverified_user(&pool, "dave").await;
+    insert_unverified_user(&pool, "erin").await;
+    for user_name in ["dave", "erin"] {
+      login_throttle::record_login_failure(
+        &pool,
+        user::LoginThrottleKind::Account,
+        user_name,
+        0,
+        None,
+      )
+      .await
+      .unwrap();
+    }
+
+    update_user_name(&pool, "dave", "david").await.unwrap();
+    let subjects: Vec<(String,)> =
+      query_as("SELECT subject FROM LoginThrottles WHERE kind = 'Account' ORDER BY subject")
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+    assert_eq!(
+      subjects,
+      vec![("david".to_string(),), ("erin".to_string(),)]
+    );
+
+    anonymize_user(&pool, "erin", "deleted-erin").await.unwrap();
+    let (count,): (i64,) = query_as("SELECT COUNT(*) FROM LoginThrottles WHERE kind = 'Account'")
+      .fetch_one(&pool)
+      .await
+      .unwrap();
+    assert_eq!(count, 1);
+  }
 }
@@ -0,0 +1,206 @@
+use crate::utils::*;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{
+  decode, decode_header, encode,
+  jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, Jwk, JwkSet, KeyAlgorithm,
+    OctetKeyPairParameters, OctetKeyPairType, PublicKeyUse, RSAKeyParameters, RSAKeyType,
+  },
+  Algorithm, DecodingKey, EncodingKey, Header, Validation,
+};
+use ring::signature::{Ed25519KeyPair, KeyPair, RsaKeyPair, RsaPublicKeyComponents};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{env, fs, sync::OnceLock};
+
+// 簽發與驗證 JWT 的金鑰
+pub struct SigningKey {
+  // 寫入 JWT header 的 kid，舊版以 SECRET_KEY 簽發的 token 沒有 kid
+  pub kid: Option<String>,
+  pub algorithm: Algorithm,
+  encoding_key: EncodingKey,
+  decoding_key: DecodingKey,
+  // 非對稱式金鑰的公鑰，公開於 JWKS 供其他服務驗證
+  jwk: Option<Jwk>,
+}
+
+// JWT 金鑰組，第一把金鑰用於簽發新的 token，其餘只用於驗證
+/*
+JWT_KEYS 以逗號分隔多把金鑰，格式為 kid:演算法:私鑰檔案路徑，演算法可為 RS256、EdDSA 或 HS256
+RS256 與 EdDSA 的檔案為 PEM 格式的私鑰，HS256 的檔案內容即為密鑰
+輪替金鑰時將新金鑰放在第一個，舊金鑰保留到以其簽發的 access token 都過期後再移除
+有設定 SECRET_KEY 時一併接受沒有 kid 的舊 token，未設定 JWT_KEYS 時以 SECRET_KEY 簽發
+*/
+pub struct KeySet {
+  keys: Vec<SigningKey>,
+}
+
+impl KeySet {
+  pub fn signing_key(&self) -> &SigningKey {
+    &self.keys[0]
+  }
+
+  fn find(&self, kid: Option<&str>) -> Option<&SigningKey> {
+    self.keys.iter().find(|key| key.kid.as_deref() == kid)
+  }
+}
+
+fn load_key(kid: &str, algorithm: &str, path: &str) -> SigningKey {
+  let fail = |message: String| -> ! {
+    log::error!("Failed to load JWT key: {}, {}", kid, message);
+    panic!("Failed to load JWT key: {}, {}", kid, message);
+  };
+
+  let content = fs::read(path).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+
+  let common = |key_algorithm| CommonParameters {
+    public_key_use: Some(PublicKeyUse::Signature),
+    key_algorithm: Some(key_algorithm),
+    key_id: Some(kid.to_string()),
+    ..Default::default()
+  };
+
+  match algorithm {
+    "HS256" => {
+      let secret = String::from_utf8_lossy(&content).trim().to_string();
+
+      SigningKey {
+        kid: Some(kid.to_string()),
+        algorithm: Algorithm::HS256,
+        encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+        decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+        jwk: None,
+      }
+    }
+    "RS256" => {
+      let pem = pem::parse(&content).unwrap_or_else(|e| fail(e.to_string()));
+
+      // PKCS#8 (BEGIN PRIVATE KEY) 或 PKCS#1 (BEGIN RSA PRIVATE KEY)
+      let key_pair = match pem.tag() {
+        "PRIVATE KEY" => RsaKeyPair::from_pkcs8(pem.contents()),
+        "RSA PRIVATE KEY" => RsaKeyPair::from_der(pem.contents()),
+        tag => fail(format!("unsupported PEM tag: {}", tag)),
+      }
+      .unwrap_or_else(|e| fail(e.to_string()));
+
+      let components: RsaPublicKeyComponents<Vec<u8>> = key_pair.public().into();
+      let n = URL_SAFE_NO_PAD.encode(&components.n);
+      let e = URL_SAFE_NO_PAD.encode(&components.e);
+
+      SigningKey {
+        kid: Some(kid.to_string()),
+        algorithm: Algorithm::RS256,
+        encoding_key: EncodingKey::from_rsa_pem(&content).unwrap_or_else(|e| fail(e.to_string())),
+        decoding_key: DecodingKey::from_rsa_components(&n, &e)
+          .unwrap_or_else(|e| fail(e.to_string())),
+        jwk: Some(Jwk {
+          common: common(KeyAlgorithm::RS256),
+          algorithm: AlgorithmParameters::RSA(RSAKeyParameters {
+            key_type: RSAKeyType::RSA,
+            n,
+            e,
+          }),
+        }),
+      }
+    }
+    "EdDSA" => {
+      let pem = pem::parse(&content).unwrap_or_else(|e| fail(e.to_string()));
+
+      let key_pair = Ed25519KeyPair::from_pkcs8_maybe_unchecked(pem.contents())
+        .unwrap_or_else(|e| fail(e.to_string()));
+      let x = URL_SAFE_NO_PAD.encode(key_pair.public_key());
+
+      SigningKey {
+        kid: Some(kid.to_string()),
+        algorithm: Algorithm::EdDSA,
+        encoding_key: EncodingKey::from_ed_pem(&content).unwrap_or_else(|e| fail(e.to_string())),
+        decoding_key: DecodingKey::from_ed_components(&x).unwrap_or_else(|e| fail(e.to_string())),
+        jwk: Some(Jwk {
+          common: common(KeyAlgorithm::EdDSA),
+          algorithm: AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+            key_type: OctetKeyPairType::OctetKeyPair,
+            curve: EllipticCurve::Ed25519,
+            x,
+          }),
+        }),
+      }
+    }
+    algorithm => fail(format!("unsupported algorithm: {}", algorithm)),
+  }
+}
+
+pub fn get_key_set() -> &'static KeySet {
+  static KEY_SET: OnceLock<KeySet> = OnceLock::new();
+
+  KEY_SET.get_or_init(|| {
+    let mut keys: Vec<SigningKey> = env::var("JWT_KEYS")
+      .unwrap_or_default()
+      .split(',')
+      .map(str::trim)
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| {
+        let parts: Vec<&str> = entry.splitn(3, ':').collect();
+
+        match parts[..] {
+          [kid, algorithm, path] => load_key(kid, algorithm, path),
+          _ => {
+            log::error!("Invalid JWT_KEYS entry: {}", entry);
+            panic!("Invalid JWT_KEYS entry: {}", entry);
+          }
+        }
+      })
+      .collect();
+
+    if let Ok(secret) = env::var("SECRET_KEY") {
+      keys.push(SigningKey {
+        kid: None,
+        algorithm: Algorithm::HS256,
+        encoding_key: EncodingKey::from_secret(secret.as_bytes()),
+        decoding_key: DecodingKey::from_secret(secret.as_bytes()),
+        jwk: None,
+      });
+    }
+
+    if keys.is_empty() {
+      panic!("Either JWT_KEYS or SECRET_KEY must be set");
+    }
+
+    KeySet { keys }
+  })
+}
+
+pub fn encode_token<T: Serialize>(claims: &T) -> Result<String, Status> {
+  let key = get_key_set().signing_key();
+
+  let mut header = Header::new(key.algorithm);
+  header.kid = key.kid.clone();
+
+  handle(encode(&header, claims, &key.encoding_key), "Encoding JWT")
+}
+
+// 依 header 的 kid 找出金鑰驗證，演算法需與金鑰相符
+pub fn decode_token<T: DeserializeOwned>(token: &str) -> Result<T, Status> {
+  let header = handle(decode_header(token), "Decoding JWT header")?;
+
+  let key = get_key_set().find(header.kid.as_deref()).ok_or_else(|| {
+    log::warn!("No JWT key found for kid: {:?}", header.kid);
+    Status::Unauthorized
+  })?;
+
+  let token = handle(
+    decode::<T>(token, &key.decoding_key, &Validation::new(key.algorithm)),
+    "Decoding JWT",
+  )?;
+
+  Ok(token.claims)
+}
+
+// 公開所有非對稱式金鑰的公鑰，包含輪替中只用於驗證的舊金鑰
+pub fn get_jwks() -> JwkSet {
+  JwkSet {
+    keys: get_key_set()
+      .keys
+      .iter()
+      .filter_map(|key| key.jwk.clone())
+      .collect(),
+  }
+}
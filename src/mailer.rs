@@ -0,0 +1,246 @@
+use crate::{model::constant, utils::*};
+use lettre::{
+  message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport,
+};
+use std::{
+  env, fs,
+  path::PathBuf,
+  sync::{Arc, Mutex, OnceLock},
+};
+use uuid::Uuid;
+
+// 系統寄出的純文字郵件
+#[derive(Debug, Clone)]
+pub struct Email {
+  pub to: String,
+  pub subject: String,
+  pub body: String,
+}
+
+// 寄送郵件的方式，由 MAIL_TRANSPORT 選擇
+pub trait Mailer: Send + Sync {
+  fn send(&self, email: &Email) -> Result<(), Status>;
+}
+
+// 寄件者，可由 EMAIL_ADDRESS 設定
+fn get_sender() -> String {
+  env::var("EMAIL_ADDRESS").unwrap_or_else(|_| constant::DEFAULT_EMAIL_ADDRESS.to_string())
+}
+
+fn build_message(email: &Email) -> Result<Message, Status> {
+  let from = handle(get_sender().parse::<Mailbox>(), "Parsing email address")?;
+  let to = handle(email.to.parse::<Mailbox>(), "Parsing user email")?;
+
+  handle(
+    Message::builder()
+      .to(to)
+      .from(from)
+      .subject(email.subject.as_str())
+      .body(email.body.clone()),
+    "Building email",
+  )
+}
+
+// 透過 SMTP 伺服器寄送
+/*
+SMTP_HOST 未設定時沿用舊的設定 smtp.{EMAIL_DOMAIN}.com
+SMTP_TLS 可為 tls (預設，port 465)、starttls (port 587) 或 none (port 25，只用於本機的轉送伺服器)
+SMTP_PORT 可覆寫預設的 port，有設定 EMAIL_PASSWORD 時以 EMAIL_ADDRESS 登入
+*/
+pub struct SmtpMailer {
+  transport: SmtpTransport,
+}
+
+impl SmtpMailer {
+  pub fn from_env() -> Result<SmtpMailer, String> {
+    let host = match env::var("SMTP_HOST") {
+      Ok(host) => host,
+      Err(_) => {
+        let email_domain =
+          env::var("EMAIL_DOMAIN").map_err(|_| "SMTP_HOST or EMAIL_DOMAIN must be set")?;
+        format!("smtp.{}.com", email_domain)
+      }
+    };
+
+    let tls = env::var("SMTP_TLS").unwrap_or_else(|_| "tls".to_string());
+
+    let mut builder = match tls.as_str() {
+      "tls" => SmtpTransport::relay(&host),
+      "starttls" => SmtpTransport::starttls_relay(&host),
+      "none" => Ok(SmtpTransport::builder_dangerous(&host)),
+      _ => return Err(format!("Unsupported SMTP_TLS: {}", tls)),
+    }
+    .map_err(|e| format!("Invalid SMTP host: {}, {}", host, e))?;
+
+    if let Ok(port) = env::var("SMTP_PORT") {
+      let port = port
+        .parse()
+        .map_err(|_| format!("Invalid SMTP_PORT: {}", port))?;
+      builder = builder.port(port);
+    }
+
+    if let Ok(password) = env::var("EMAIL_PASSWORD") {
+      builder = builder.credentials(Credentials::new(get_sender(), password));
+    }
+
+    log::info!("Sending emails via SMTP server: {} ({})", host, tls);
+
+    Ok(SmtpMailer {
+      transport: builder.build(),
+    })
+  }
+}
+
+impl Mailer for SmtpMailer {
+  fn send(&self, email: &Email) -> Result<(), Status> {
+    let message = build_message(email)?;
+
+    handle(self.transport.send(&message), "Sending email")?;
+
+    Ok(())
+  }
+}
+
+// 將郵件寫入本機資料夾 (.eml)，用於開發與離線測試
+pub struct FileMailer {
+  dir: PathBuf,
+}
+
+impl FileMailer {
+  pub fn new(dir: PathBuf) -> Result<FileMailer, String> {
+    fs::create_dir_all(&dir)
+      .map_err(|e| format!("Failed to create outbox: {}, {}", dir.display(), e))?;
+
+    log::info!("Writing emails to outbox: {}", dir.display());
+
+    Ok(FileMailer { dir })
+  }
+}
+
+impl Mailer for FileMailer {
+  fn send(&self, email: &Email) -> Result<(), Status> {
+    let message = build_message(email)?;
+
+    let file_name = format!(
+      "{}-{}.eml",
+      chrono::Utc::now().format("%Y%m%d%H%M%S"),
+      Uuid::new_v4().simple()
+    );
+
+    handle(
+      fs::write(self.dir.join(file_name), message.formatted()),
+      "Writing email to outbox",
+    )?;
+
+    Ok(())
+  }
+}
+
+// 只保存在記憶體中，供測試檢查寄出的郵件
+#[derive(Default)]
+pub struct MemoryMailer {
+  emails: Mutex<Vec<Email>>,
+}
+
+impl MemoryMailer {
+  #[cfg(test)]
+  pub fn emails(&self) -> Vec<Email> {
+    self.emails.lock().unwrap().clone()
+  }
+}
+
+impl Mailer for MemoryMailer {
+  fn send(&self, email: &Email) -> Result<(), Status> {
+    log::info!("Keeping email to {} in memory: {}", email.to, email.subject);
+
+    self.emails.lock().unwrap().push(email.clone());
+
+    Ok(())
+  }
+}
+
+static MAILER: OnceLock<Arc<dyn Mailer>> = OnceLock::new();
+
+// 依 MAIL_TRANSPORT (smtp、file、memory) 建立寄送郵件的方式
+/*
+未設定時使用 smtp，缺少 SMTP 的設定會在啟動時失敗，避免郵件在未察覺的情況下只寫入本機
+file 需明確設定，寫入 MAIL_OUTBOX_DIR (預設為 {ROOT}/outbox)
+*/
+fn create_mailer() -> Arc<dyn Mailer> {
+  let transport = env::var("MAIL_TRANSPORT").unwrap_or_else(|_| "smtp".to_string());
+
+  let mailer: Result<Arc<dyn Mailer>, String> = match transport.as_str() {
+    "smtp" => SmtpMailer::from_env().map(|mailer| Arc::new(mailer) as Arc<dyn Mailer>),
+    "file" => {
+      let dir = env::var("MAIL_OUTBOX_DIR").unwrap_or_else(|_| format!("{}/outbox", get_root()));
+
+      FileMailer::new(PathBuf::from(dir)).map(|mailer| Arc::new(mailer) as Arc<dyn Mailer>)
+    }
+    "memory" => Ok(Arc::new(MemoryMailer::default())),
+    _ => Err(format!("Unsupported MAIL_TRANSPORT: {}", transport)),
+  };
+
+  mailer.unwrap_or_else(|e| {
+    log::error!("Failed to create mailer: {}", e);
+    panic!("Failed to create mailer: {}", e);
+  })
+}
+
+pub fn get_mailer() -> &'static Arc<dyn Mailer> {
+  MAILER.get_or_init(create_mailer)
+}
+
+// 以指定的方式取代依設定建立的方式，需在寄出第一封郵件前呼叫，例如測試時使用 MemoryMailer
+#[cfg(test)]
+pub fn set_mailer(mailer: Arc<dyn Mailer>) -> Result<(), Arc<dyn Mailer>> {
+  MAILER.set(mailer)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    api::{email_verify, register},
+    database,
+  };
+  use rocket::{http::ContentType, local::asynchronous::Client, routes};
+
+  #[tokio::test]
+  async fn registration_sends_verification_link() {
+    let pool = database::connect_test_pool().await;
+
+    let mailer = Arc::new(MemoryMailer::default());
+    assert!(set_mailer(mailer.clone()).is_ok());
+
+    let rocket = rocket::build()
+      .mount("/", routes![register, email_verify])
+      .manage(pool.clone());
+    let client = Client::tracked(rocket).await.unwrap();
+
+    let response = client
+      .post("/api/register")
+      .header(ContentType::JSON)
+      .body(r#"{"user_name":"frank","password":"password123","email":"frank@example.com"}"#)
+      .dispatch()
+      .await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let emails = mailer.emails();
+    assert_eq!(emails.len(), 1);
+    assert_eq!(emails[0].to, "frank@example.com");
+
+    // 從郵件內容取出驗證連結，以 BASE_URL 之後的路徑送出驗證
+    let url = emails[0]
+      .body
+      .split_whitespace()
+      .find(|word| word.starts_with(&get_base_url()))
+      .unwrap();
+    let path = url.trim_start_matches(&get_base_url()).to_string();
+
+    let response = client.get(path).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let user_info = database::user::get_user_info(&pool, "frank").await.unwrap();
+    assert!(user_info.verified);
+  }
+}
@@ -1,8 +1,16 @@
+// rocket 的路由 macro 會替每個路由匯出 uri! 用的 macro，沒有使用 uri! 時會被視為未使用的 import
+#[allow(unused_imports)]
 mod api;
+mod calendar;
+mod cli;
 mod database;
+mod jwt;
 mod logger;
+mod mailer;
 mod model;
+mod oidc;
 mod timer;
+mod two_factor;
 mod utils;
 
 use std::env;
@@ -31,7 +39,7 @@ impl Fairing for CORS {
     response.set_header(Header::new("Access-Control-Allow-Origin", "*"));
     response.set_header(Header::new(
       "Access-Control-Allow-Methods",
-      "POST, GET, PATCH, OPTIONS",
+      "POST, GET, PATCH, DELETE, OPTIONS",
     ));
     response.set_header(Header::new("Access-Control-Allow-Headers", "*"));
     response.set_header(Header::new("Access-Control-Allow-Credentials", "true"));
@@ -55,6 +63,11 @@ fn unauthorized(_: &Request) -> &'static str {
 fn handle_not_found(_: &Request) -> &'static str {
   "The resource was not found"
 }
+#[catch(429)]
+fn handle_too_many_requests(_: &Request) -> &'static str {
+  // 登入失敗次數過多，需稍後再試
+  "Too many failed attempts, please try again later"
+}
 #[catch(500)]
 fn handle_internal_server_error(_: &Request) -> &'static str {
   "Something went wrong"
@@ -69,6 +82,32 @@ fn handle_service_unavailable(_: &Request) -> &'static str {
 async fn main() {
   dotenv().ok();
   logger::init_logger(log::LevelFilter::Info);
+  log::info!("Using time zone: {}", utils::get_time_zone());
+
+  // 啟動時載入 JWT 金鑰，設定錯誤時直接結束
+  let signing_key = jwt::get_key_set().signing_key();
+  log::info!(
+    "Signing JWT with key: {:?} ({:?})",
+    signing_key.kid,
+    signing_key.algorithm
+  );
+
+  // 啟動時建立寄送郵件的方式，設定錯誤時直接結束
+  mailer::get_mailer();
+
+  // 啟動時讀取 OIDC 的設定，設定錯誤時直接結束
+  let oidc_config = oidc::get_oidc_config().expect("Failed to load OIDC config");
+  match &oidc_config {
+    Some(config) => log::info!("OIDC login is enabled with issuer: {}", config.issuer),
+    None => log::info!("OIDC login is disabled"),
+  }
+
+  let allowed_email_domains = utils::get_allowed_email_domains();
+  if allowed_email_domains.is_empty() {
+    log::info!("Registration is open to all email domains");
+  } else {
+    log::info!("Allowed email domains: {:?}", allowed_email_domains);
+  }
 
   let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
   // let pool = SqlitePool::connect_lazy(&database_url).expect("Failed to create pool.");
@@ -88,6 +127,13 @@ async fn main() {
   // database::init::clear_table(&pool).await;
   database::init::init_db(&pool_clone).await;
 
+  // 有帶參數時以命令列工具執行，不啟動伺服器
+  let args: Vec<String> = env::args().skip(1).collect();
+  if !args.is_empty() {
+    cli::run(&pool, &args).await;
+    return;
+  }
+
   tokio::spawn(async move {
     timer::start(&pool_clone).await;
   });
@@ -96,6 +142,7 @@ async fn main() {
     handle_unprocessable_entity,
     handle_forbidden,
     handle_not_found,
+    handle_too_many_requests,
     handle_internal_server_error,
     handle_service_unavailable,
     unauthorized
@@ -103,6 +150,25 @@ async fn main() {
   let routes = routes![
     register,
     login,
+    login_totp,
+    refresh_token,
+    logout,
+    logout_all,
+    oidc_login,
+    oidc_callback,
+    show_jwks,
+    forgot_password,
+    reset_password,
+    show_user_profile,
+    change_password,
+    change_email,
+    change_user_name,
+    export_user_data,
+    delete_account,
+    setup_totp,
+    enable_totp,
+    regenerate_totp_recovery_codes,
+    disable_totp,
     show_current_seats_status,
     reserve_seat,
     show_seats_status_in_specific_timeslots,
@@ -116,12 +182,44 @@ async fn main() {
     set_seat_availability,
     add_user_to_blacklist,
     remove_user_from_blacklist,
+    show_bans,
+    unlock_account,
+    create_api_key,
+    show_api_keys,
+    revoke_api_key,
+    show_users,
+    show_user_detail,
+    change_user_role,
+    force_verify_user,
+    resend_user_verification_email,
+    disable_user,
+    enable_user,
+    send_user_password_reset,
+    report_seat_issue,
+    check_in,
+    show_seat_issues,
+    show_seat_issue_photo,
+    assign_seat_issue,
+    resolve_seat_issue,
+    show_opening_hours,
+    set_opening_hours,
+    set_opening_hours_exception,
+    delete_opening_hours_exception,
+    import_unavailable_timeslots,
+    show_unavailable_timeslots,
+    update_unavailable_timeslot,
+    delete_unavailable_timeslot,
+    show_upcoming_closures,
+    create_calendar_feed,
+    revoke_calendar_feed,
+    show_calendar_feed,
   ];
   let server = rocket::build()
     .register("/", catchers)
     .mount("/", routes)
     .attach(CORS)
     .manage(pool)
+    .manage(oidc_config)
     .launch();
 
   tokio::select! {
@@ -1,8 +1,15 @@
+pub mod api_key;
 mod common;
 pub mod constant;
+// rocket 的 FromForm derive 會產生已移除的 private_in_public lint
+#[allow(renamed_and_removed_lints)]
+pub mod issue;
+pub mod opening_hours;
+pub mod permission;
 pub mod reservation;
 pub mod seat;
 pub mod timeslot;
 pub mod token;
+pub mod totp;
 pub mod user;
 pub mod validate_utils;
@@ -0,0 +1,93 @@
+use super::{common::*, permission::Permission};
+
+// 建立 API key，key 以 user_name 帳號的身分存取，只能使用 scopes 中的權限
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct CreateApiKeyRequest {
+  #[validate(length(min = 1, max = 50))]
+  pub name: String,
+  #[validate(length(min = 1, max = 20))]
+  pub user_name: String,
+  #[validate(length(min = 1))]
+  pub scopes: Vec<Permission>,
+}
+
+// API key 只在建立時回傳一次，資料庫只儲存雜湊值
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKeyCreated {
+  pub key_id: String,
+  pub api_key: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ApiKey {
+  pub key_id: String,
+  pub name: String,
+  pub user_name: String,
+  pub scopes: Vec<Permission>,
+  pub created_by: String,
+  pub created_at: i64,
+  pub last_used_at: Option<i64>,
+  pub revoked_at: Option<i64>,
+}
+
+pub fn parse_scopes(scopes: &str) -> Result<Vec<Permission>, Error> {
+  scopes
+    .split(',')
+    .filter(|scope| !scope.is_empty())
+    .map(|scope| {
+      scope
+        .parse()
+        .map_err(|e: std::io::Error| Error::ColumnDecode {
+          index: "scopes".to_string(),
+          source: e.into(),
+        })
+    })
+    .collect()
+}
+
+pub fn format_scopes(scopes: &[Permission]) -> String {
+  scopes
+    .iter()
+    .map(ToString::to_string)
+    .collect::<Vec<_>>()
+    .join(",")
+}
+
+impl FromRow<'_, SqliteRow> for ApiKey {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    let scopes: String = row.try_get("scopes")?;
+
+    Ok(ApiKey {
+      key_id: row.try_get("key_id")?,
+      name: row.try_get("name")?,
+      user_name: row.try_get("user_name")?,
+      scopes: parse_scopes(&scopes)?,
+      created_by: row.try_get("created_by")?,
+      created_at: row.try_get("created_at")?,
+      last_used_at: row.try_get("last_used_at")?,
+      revoked_at: row.try_get("revoked_at")?,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_scopes_round_trips_with_format_scopes() {
+    let scopes = vec![Permission::Reserve, Permission::ManageUsers];
+
+    assert_eq!(parse_scopes(&format_scopes(&scopes)).unwrap(), scopes);
+    assert_eq!(parse_scopes("").unwrap(), vec![]);
+    assert_eq!(
+      parse_scopes("ManageSeats,,ManageSchedule").unwrap(),
+      vec![Permission::ManageSeats, Permission::ManageSchedule]
+    );
+  }
+
+  #[test]
+  fn parse_scopes_rejects_unknown_scope() {
+    assert!(parse_scopes("Reserve,Unknown").is_err());
+  }
+}
@@ -7,3 +7,70 @@ pub use sqlx::{
 };
 pub use std::{io::ErrorKind, str::FromStr, string::ToString};
 pub use validator::{Validate, ValidationError};
+
+// 以文字形式存入 SQLite 的 enum，實作 Decode / Encode / Type / Display / FromStr
+macro_rules! impl_text_enum {
+  ($name:ident, [$($variant:ident),+ $(,)?]) => {
+    impl<'r> sqlx::decode::Decode<'r, sqlx::Sqlite> for $name {
+      fn decode(
+        value: sqlx::sqlite::SqliteValueRef<'r>,
+      ) -> Result<Self, sqlx::error::BoxDynError> {
+        let value = <&str as sqlx::decode::Decode<sqlx::Sqlite>>::decode(value)?;
+
+        Ok(<$name as std::str::FromStr>::from_str(value)?)
+      }
+    }
+
+    impl<'q> sqlx::Encode<'q, sqlx::Sqlite> for $name {
+      fn encode_by_ref(
+        &self,
+        buf: &mut Vec<sqlx::sqlite::SqliteArgumentValue<'q>>,
+      ) -> sqlx::encode::IsNull {
+        buf.push(sqlx::sqlite::SqliteArgumentValue::Text(
+          self.to_string().into(),
+        ));
+
+        sqlx::encode::IsNull::No
+      }
+    }
+
+    impl sqlx::Type<sqlx::Sqlite> for $name {
+      fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+        <&str as sqlx::Type<sqlx::Sqlite>>::type_info()
+      }
+    }
+
+    impl std::fmt::Display for $name {
+      fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+          $($name::$variant => write!(f, stringify!($variant)),)+
+        }
+      }
+    }
+
+    impl std::str::FromStr for $name {
+      type Err = std::io::Error;
+
+      fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+          $(stringify!($variant) => Ok($name::$variant),)+
+          _ => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            concat!("Provided string does not match any ", stringify!($name), " variant"),
+          )),
+        }
+      }
+    }
+  };
+}
+
+pub(crate) use impl_text_enum;
+
+// 用於 PATCH 請求，區分未提供的欄位(None)與設為 null 的欄位(Some(None))
+pub fn deserialize_nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+  D: serde::Deserializer<'de>,
+  T: Deserialize<'de>,
+{
+  Option::<T>::deserialize(deserializer).map(Some)
+}
@@ -1 +1,66 @@
 pub static NUMBER_OF_SEATS: u16 = 217;
+pub static MAX_ISSUE_PHOTO_SIZE: u64 = 10 * 1024 * 1024;
+pub static MAX_CALENDAR_SIZE: u64 = 5 * 1024 * 1024;
+// 由系統(開放時間、排程)建立的資料使用的建立者名稱
+pub static SYSTEM_USER: &str = "system";
+// 每週休館日產生的不可預約時段沒有指定原因時使用的原因
+pub static CLOSED_DAY_REASON: &str = "Closed";
+// 預設提前產生幾天後的不可預約時段
+pub static DEFAULT_CLOSURE_HORIZON_DAYS: i64 = 3;
+// 行事曆訂閱包含幾天前的預約，讓已取消的預約能同步到行事曆
+pub static CALENDAR_FEED_PAST_DAYS: i64 = 30;
+pub static DEFAULT_VENUE_NAME: &str = "Study Room";
+// 舊版資料庫以文字儲存時間時固定使用的時差(小時)
+pub static LEGACY_UTC_OFFSET_HOURS: i64 = 8;
+// 未設定 TIME_ZONE 時使用的時區
+pub static DEFAULT_TIME_ZONE: &str = "Asia/Taipei";
+// 重設密碼 token 的有效時間(分鐘)
+pub static PASSWORD_RESET_TOKEN_MINUTES: i64 = 30;
+// 沒有密碼的帳號，登入後幾分鐘內可以變更帳號資料
+pub static REAUTH_MINUTES: i64 = 10;
+// 預約開始前幾分鐘可以報到
+pub static CHECK_IN_EARLY_MINUTES: i64 = 15;
+// access token 的有效時間(分鐘)，過期後以 refresh token 換發
+pub static ACCESS_TOKEN_MINUTES: i64 = 15;
+// 登入 session (refresh token) 的有效時間，管理員與館員較短
+pub static ADMIN_SESSION_HOURS: i64 = 24;
+pub static USER_SESSION_HOURS: i64 = 14 * 24;
+// 登入失敗超過此次數後開始延遲，之後每次失敗延遲時間加倍
+pub static LOGIN_BACKOFF_FREE_ATTEMPTS: i64 = 3;
+// 同一 IP 可能為校園網路的多位使用者共用，允許較多次失敗
+pub static LOGIN_IP_BACKOFF_FREE_ATTEMPTS: i64 = 20;
+pub static LOGIN_BACKOFF_MAX_SECONDS: i64 = 15 * 60;
+// 帳號連續登入失敗達此次數後鎖定，並通知帳號擁有者
+pub static LOGIN_LOCKOUT_FAILURES: i64 = 10;
+pub static LOGIN_LOCKOUT_MINUTES: i64 = 15;
+// 超過此時間沒有再失敗，失敗次數重新計算
+pub static LOGIN_FAILURE_WINDOW_MINUTES: i64 = 60;
+// OIDC 登入流程中 state 的有效時間(分鐘)
+pub static OIDC_STATE_MINUTES: i64 = 10;
+// 兩步驟驗證(TOTP)的時間步長(秒)與驗證碼位數，與常見的驗證器 App 相同
+pub static TOTP_STEP_SECONDS: i64 = 30;
+pub static TOTP_DIGITS: u32 = 6;
+// 允許前後幾個時間步長的時間誤差
+pub static TOTP_ALLOWED_SKEW_STEPS: i64 = 1;
+// 驗證器 App 中顯示的服務名稱
+pub static TOTP_ISSUER: &str = "StudySeatReserve";
+pub static TOTP_RECOVERY_CODE_COUNT: usize = 10;
+// 通過密碼驗證後，輸入兩步驟驗證碼的期限(分鐘)
+pub static TOTP_CHALLENGE_MINUTES: i64 = 5;
+// 自助機、門禁系統等以 API key 存取時使用的標頭
+pub static API_KEY_HEADER: &str = "X-API-Key";
+// email 驗證 token 的有效時間(小時)
+pub static VERIFICATION_TOKEN_HOURS: i64 = 24;
+// 同一 email 重寄驗證信的間隔(秒)
+pub static VERIFICATION_RESEND_COOLDOWN_SECONDS: i64 = 60;
+// 預設註冊後幾天仍未驗證 email 的帳號會被刪除
+pub static DEFAULT_UNVERIFIED_ACCOUNT_DAYS: i64 = 7;
+// 管理員查詢使用者列表時，每頁的預設與最大筆數
+pub static DEFAULT_USER_PAGE_SIZE: i64 = 20;
+pub static MAX_USER_PAGE_SIZE: i64 = 100;
+// Argon2id 的預設參數，記憶體用量(KiB)、迭代次數與平行度，依 OWASP 的建議值
+pub static DEFAULT_ARGON2_MEMORY_KIB: u32 = 19 * 1024;
+pub static DEFAULT_ARGON2_ITERATIONS: u32 = 2;
+pub static DEFAULT_ARGON2_PARALLELISM: u32 = 1;
+// 未設定 EMAIL_ADDRESS 時的寄件者
+pub static DEFAULT_EMAIL_ADDRESS: &str = "noreply@localhost";
@@ -0,0 +1,123 @@
+use super::{common::*, validate_utils::*};
+use crate::utils::get_now_timestamp;
+use rocket::{fs::TempFile, FromForm, FromFormField};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SeatIssue {
+  pub issue_id: i64,
+  pub seat_id: u16,
+  pub user_name: String,
+  pub category: IssueCategory,
+  pub description: String,
+  pub has_photo: bool,
+  pub status: IssueStatus,
+  pub assignee: Option<String>,
+  pub resolution: Option<IssueResolution>,
+  pub created_at: i64,
+  pub resolved_at: Option<i64>,
+}
+
+#[derive(Debug, FromForm, Validate)]
+pub struct ReportIssueForm<'r> {
+  #[validate(custom = "validate_seat_id")]
+  pub seat_id: u16,
+  pub category: IssueCategory,
+  #[validate(length(min = 1, max = 500))]
+  pub description: String,
+  pub photo: Option<TempFile<'r>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReportIssueResponse {
+  pub issue_id: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct AssignIssueRequest {
+  pub issue_id: i64,
+  #[validate(length(min = 1, max = 20))]
+  pub assignee: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(
+  function = "validate_resolve_issue_request",
+  skip_on_field_errors = false
+))]
+pub struct ResolveIssueRequest {
+  pub issue_id: i64,
+  pub resolution: IssueResolution,
+  // 座位停用到何時，resolution 為 OutOfService 時必填
+  pub maintenance_end_time: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, FromFormField)]
+pub enum IssueCategory {
+  Lamp,
+  Socket,
+  Furniture,
+  Network,
+  Cleanliness,
+  Other,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, FromFormField)]
+pub enum IssueStatus {
+  Open,
+  Assigned,
+  Resolved,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum IssueResolution {
+  Fixed,
+  OutOfService,
+  Dismissed,
+}
+
+impl FromRow<'_, SqliteRow> for SeatIssue {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    let seat_id_i64: i64 = row.try_get("seat_id")?;
+    let seat_id: u16 = seat_id_i64.try_into().map_err(|_| Error::RowNotFound)?;
+    let photo_path: Option<String> = row.try_get("photo_path")?;
+
+    Ok(SeatIssue {
+      issue_id: row.try_get("issue_id")?,
+      seat_id,
+      user_name: row.try_get("user_name")?,
+      category: row.try_get("category")?,
+      description: row.try_get("description")?,
+      has_photo: photo_path.is_some(),
+      status: row.try_get("status")?,
+      assignee: row.try_get("assignee")?,
+      resolution: row.try_get("resolution")?,
+      created_at: row.try_get("created_at")?,
+      resolved_at: row.try_get("resolved_at")?,
+    })
+  }
+}
+
+impl_text_enum!(
+  IssueCategory,
+  [Lamp, Socket, Furniture, Network, Cleanliness, Other]
+);
+impl_text_enum!(IssueStatus, [Open, Assigned, Resolved]);
+impl_text_enum!(IssueResolution, [Fixed, OutOfService, Dismissed]);
+
+fn validate_resolve_issue_request(request: &ResolveIssueRequest) -> Result<(), ValidationError> {
+  if request.resolution != IssueResolution::OutOfService {
+    return Ok(());
+  }
+
+  let now = get_now_timestamp();
+
+  match request.maintenance_end_time {
+    Some(end_time) if end_time > now => Ok(()),
+    Some(_) => Err(ValidationError::new(
+      "Invalid resolution: Maintenance end time must be in the future",
+    )),
+    None => Err(ValidationError::new(
+      "Invalid resolution: Maintenance end time is required for OutOfService",
+    )),
+  }
+}
@@ -0,0 +1,95 @@
+use super::common::*;
+use chrono::{NaiveDate, NaiveTime, Weekday};
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(function = "validate_opening_hours_rule", skip_on_field_errors = false))]
+pub struct OpeningHoursRule {
+  pub weekday: Weekday,
+  pub closed: bool,
+  pub open_time: Option<NaiveTime>,
+  pub close_time: Option<NaiveTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+#[validate(schema(
+  function = "validate_opening_hours_exception",
+  skip_on_field_errors = false
+))]
+pub struct OpeningHoursException {
+  pub date: NaiveDate,
+  pub closed: bool,
+  pub open_time: Option<NaiveTime>,
+  pub close_time: Option<NaiveTime>,
+  #[validate(length(max = 100))]
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DeleteOpeningHoursExceptionRequest {
+  pub date: NaiveDate,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct OpeningHoursCalendar {
+  pub weekly: Vec<OpeningHoursRule>,
+  pub exceptions: Vec<OpeningHoursException>,
+}
+
+impl FromRow<'_, SqliteRow> for OpeningHoursRule {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    let weekday_u8: u8 = row.try_get("weekday")?;
+    let weekday = Weekday::try_from(weekday_u8).map_err(|source| Error::ColumnDecode {
+      index: "weekday".to_string(),
+      source: Box::new(source),
+    })?;
+
+    Ok(OpeningHoursRule {
+      weekday,
+      closed: row.try_get("closed")?,
+      open_time: row.try_get("open_time")?,
+      close_time: row.try_get("close_time")?,
+    })
+  }
+}
+
+impl FromRow<'_, SqliteRow> for OpeningHoursException {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    Ok(OpeningHoursException {
+      date: row.try_get("date")?,
+      closed: row.try_get("closed")?,
+      open_time: row.try_get("open_time")?,
+      close_time: row.try_get("close_time")?,
+      reason: row.try_get("reason")?,
+    })
+  }
+}
+
+fn validate_opening_hours_rule(rule: &OpeningHoursRule) -> Result<(), ValidationError> {
+  validate_opening_hours(rule.closed, rule.open_time, rule.close_time)
+}
+
+fn validate_opening_hours_exception(
+  exception: &OpeningHoursException,
+) -> Result<(), ValidationError> {
+  validate_opening_hours(exception.closed, exception.open_time, exception.close_time)
+}
+
+fn validate_opening_hours(
+  closed: bool,
+  open_time: Option<NaiveTime>,
+  close_time: Option<NaiveTime>,
+) -> Result<(), ValidationError> {
+  if closed {
+    return Ok(());
+  }
+
+  match (open_time, close_time) {
+    (Some(open_time), Some(close_time)) if open_time < close_time => Ok(()),
+    (Some(_), Some(_)) => Err(ValidationError::new(
+      "Invalid opening hours: Open time is greater than close time",
+    )),
+    _ => Err(ValidationError::new(
+      "Invalid opening hours: Open time and close time are required unless closed",
+    )),
+  }
+}
@@ -0,0 +1,106 @@
+use super::{common::*, user::UserRole};
+
+// 可授權給身分的操作
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum Permission {
+  // 預約、修改與取消自己的座位預約
+  Reserve,
+  // 設定座位是否可使用、處理座位問題回報
+  ManageSeats,
+  // 設定不可預約時段與開放時間
+  ManageSchedule,
+  // 管理使用者、黑名單與帳號鎖定
+  ManageUsers,
+  // 回報座位問題
+  ReportIssue,
+  // 替使用者在現場報到
+  CheckIn,
+}
+
+// 身分與權限對照表
+impl UserRole {
+  pub fn permissions(&self) -> &'static [Permission] {
+    match self {
+      UserRole::RegularUser => &[Permission::Reserve, Permission::ReportIssue],
+      UserRole::Staff => &[
+        Permission::Reserve,
+        Permission::ReportIssue,
+        Permission::CheckIn,
+        Permission::ManageSeats,
+        Permission::ManageSchedule,
+      ],
+      UserRole::Kiosk => &[Permission::ReportIssue, Permission::CheckIn],
+      UserRole::Admin => &[
+        Permission::Reserve,
+        Permission::ReportIssue,
+        Permission::CheckIn,
+        Permission::ManageSeats,
+        Permission::ManageSchedule,
+        Permission::ManageUsers,
+      ],
+    }
+  }
+
+  pub fn has_permission(&self, permission: Permission) -> bool {
+    self.permissions().contains(&permission)
+  }
+}
+
+// 給 token::RequirePermission 使用的權限標記，例如 RequirePermission<ManageSeats>
+pub trait PermissionMarker {
+  const PERMISSION: Permission;
+}
+
+macro_rules! permission_markers {
+  ($($name:ident),+ $(,)?) => {
+    $(
+      pub struct $name;
+
+      impl PermissionMarker for $name {
+        const PERMISSION: Permission = Permission::$name;
+      }
+    )+
+  };
+}
+
+permission_markers!(
+  Reserve,
+  ManageSeats,
+  ManageSchedule,
+  ManageUsers,
+  ReportIssue,
+  CheckIn
+);
+
+// API key 的權限範圍以逗號分隔的文字儲存
+impl_text_enum!(
+  Permission,
+  [
+    Reserve,
+    ManageSeats,
+    ManageSchedule,
+    ManageUsers,
+    ReportIssue,
+    CheckIn
+  ]
+);
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn kiosk_can_only_report_issues_and_check_in() {
+    assert_eq!(
+      UserRole::Kiosk.permissions(),
+      &[Permission::ReportIssue, Permission::CheckIn]
+    );
+    assert!(!UserRole::Kiosk.has_permission(Permission::Reserve));
+  }
+
+  #[test]
+  fn staff_cannot_manage_users() {
+    assert!(UserRole::Staff.has_permission(Permission::ManageSeats));
+    assert!(!UserRole::Staff.has_permission(Permission::ManageUsers));
+  }
+}
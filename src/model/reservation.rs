@@ -7,8 +7,27 @@ pub struct Reservation {
   pub end_time: i64,
 }
 
+// 行事曆訂閱中的一筆預約，cancelled 為 true 時以已取消的事件輸出
+#[derive(Debug, Deserialize, Serialize)]
+pub struct ReservationEvent {
+  pub uid: String,
+  pub seat_id: u16,
+  pub start_time: i64,
+  pub end_time: i64,
+  pub sequence: i64,
+  pub cancelled: bool,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct CalendarFeed {
+  pub url: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Validate)]
-#[validate(schema(function = "validate_reservation_request", skip_on_field_errors = false))]
+#[validate(schema(
+  function = "validate_reservation_request",
+  skip_on_field_errors = false
+))]
 pub struct InsertReservationRequest {
   #[validate(custom = "validate_seat_id")]
   pub seat_id: u16,
@@ -17,7 +36,10 @@ pub struct InsertReservationRequest {
 }
 
 #[derive(Debug, Deserialize, Serialize, Validate)]
-#[validate(schema(function = "validate_update_reservation_request", skip_on_field_errors = false))]
+#[validate(schema(
+  function = "validate_update_reservation_request",
+  skip_on_field_errors = false
+))]
 pub struct UpdateReservationRequest {
   pub start_time: i64,
   pub end_time: i64,
@@ -25,8 +47,18 @@ pub struct UpdateReservationRequest {
   pub new_end_time: i64,
 }
 
+// 現場的查詢裝置或門禁系統替使用者報到
+#[derive(Debug, Deserialize, Serialize, Validate)]
+pub struct CheckInRequest {
+  #[validate(length(min = 1, max = 20))]
+  pub user_name: String,
+}
+
 #[derive(Debug, Deserialize, Serialize, Validate)]
-#[validate(schema(function = "validate_delete_reservation_request", skip_on_field_errors = false))]
+#[validate(schema(
+  function = "validate_delete_reservation_request",
+  skip_on_field_errors = false
+))]
 pub struct DeleteReservationRequest {
   pub start_time: i64,
   pub end_time: i64,
@@ -44,30 +76,33 @@ impl FromRow<'_, SqliteRow> for Reservation {
     let seat_id_i64: i64 = row.try_get("seat_id")?;
     let seat_id: u16 = seat_id_i64.try_into().map_err(|_| Error::RowNotFound)?;
 
-    let start_time_str: String = row.try_get("start_time")?;
-    let end_time_str: String = row.try_get("end_time")?;
-
-    let start_time: i64 = start_time_str
-      .parse()
-      .map_err(|source| Error::ColumnDecode {
-        index: "start_time".to_string(),
-        source: Box::new(source),
-      })?;
+    Ok(Reservation {
+      seat_id,
+      start_time: row.try_get("start_time")?,
+      end_time: row.try_get("end_time")?,
+    })
+  }
+}
 
-    let end_time: i64 = end_time_str.parse().map_err(|source| Error::ColumnDecode {
-      index: "end_time".to_string(),
-      source: Box::new(source),
-    })?;
+impl FromRow<'_, SqliteRow> for ReservationEvent {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    let seat_id_i64: i64 = row.try_get("seat_id")?;
+    let seat_id: u16 = seat_id_i64.try_into().map_err(|_| Error::RowNotFound)?;
 
-    Ok(Reservation {
+    Ok(ReservationEvent {
+      uid: row.try_get("uid")?,
       seat_id,
-      start_time,
-      end_time,
+      start_time: row.try_get("start_time")?,
+      end_time: row.try_get("end_time")?,
+      sequence: row.try_get("sequence")?,
+      cancelled: row.try_get("cancelled")?,
     })
   }
 }
 
-fn validate_update_reservation_request(request: &UpdateReservationRequest) -> Result<(), ValidationError> {
+fn validate_update_reservation_request(
+  request: &UpdateReservationRequest,
+) -> Result<(), ValidationError> {
   let start_time = request.start_time;
   let end_time = request.end_time;
   let new_start_time = request.new_start_time;
@@ -78,7 +113,9 @@ fn validate_update_reservation_request(request: &UpdateReservationRequest) -> Re
   on_the_same_day(start_time, new_start_time)
 }
 
-fn validate_delete_reservation_request(request: &DeleteReservationRequest) -> Result<(), ValidationError> {
+fn validate_delete_reservation_request(
+  request: &DeleteReservationRequest,
+) -> Result<(), ValidationError> {
   let start_time = request.start_time;
   let end_time = request.end_time;
 
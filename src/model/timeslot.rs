@@ -5,6 +5,75 @@ use super::{common::*, validate_utils::*};
 pub struct TimeSlot {
   pub start_time: i64,
   pub end_time: i64,
+  #[validate(length(min = 1, max = 100))]
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UnavailableTimeSlot {
+  pub id: i64,
+  pub start_time: i64,
+  pub end_time: i64,
+  pub reason: Option<String>,
+  pub created_by: Option<String>,
+  pub source_uid: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UpdateTimeSlotRequest {
+  pub start_time: Option<i64>,
+  pub end_time: Option<i64>,
+  // 設為 null 時清除原因
+  #[serde(default, deserialize_with = "deserialize_nullable")]
+  #[validate(length(min = 1, max = 100))]
+  pub reason: Option<Option<String>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct Closure {
+  pub start_time: i64,
+  pub end_time: i64,
+  pub reason: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ImportCalendarResult {
+  pub events: usize,
+  pub created: usize,
+  pub updated: usize,
+  pub unchanged: usize,
+  // 與既有時段時間相同而略過的時段數量
+  pub conflicts: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ImportOutcome {
+  Created,
+  Updated,
+  Unchanged,
+}
+
+impl FromRow<'_, SqliteRow> for UnavailableTimeSlot {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    Ok(UnavailableTimeSlot {
+      id: row.try_get("id")?,
+      start_time: row.try_get("start_time")?,
+      end_time: row.try_get("end_time")?,
+      reason: row.try_get("reason")?,
+      created_by: row.try_get("created_by")?,
+      source_uid: row.try_get("source_uid")?,
+    })
+  }
+}
+
+impl FromRow<'_, SqliteRow> for Closure {
+  fn from_row(row: &SqliteRow) -> Result<Self, Error> {
+    Ok(Closure {
+      start_time: row.try_get("start_time")?,
+      end_time: row.try_get("end_time")?,
+      reason: row.try_get("reason")?,
+    })
+  }
 }
 
 fn validate_timeslot(timeslot: &TimeSlot) -> Result<(), ValidationError> {
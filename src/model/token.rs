@@ -1,12 +1,18 @@
-use super::{common::*, user};
-use crate::utils::handle;
+use super::{common::*, constant, permission::PermissionMarker, totp, user};
+use crate::{
+  database, jwt,
+  utils::{get_trusted_proxies, hash_token, is_admin_totp_required},
+};
 
-use jsonwebtoken::{decode, Algorithm, DecodingKey, Validation};
 use rocket::{
   http::Status,
+  outcome::try_outcome,
   request::{FromRequest, Outcome, Request},
+  serde::json::Json,
+  Responder, State,
 };
-use std::env;
+use sqlx::Pool;
+use std::{convert::Infallible, marker::PhantomData, net::IpAddr};
 
 pub trait Claim: Sized {
   fn verify_jwt(token: &str) -> Result<Self, Status>;
@@ -16,31 +22,88 @@ pub trait Claim: Sized {
 pub struct UserInfoClaim {
   pub user: String,
   pub role: user::UserRole,
+  // 登入的 session id，session 被撤銷後 token 即失效
+  pub sid: String,
   pub exp: usize,
 }
 
+// 需要特定權限的請求，未登入時回傳 401，已登入但權限不足時回傳 403
+/*
+除了 Authorization 的 JWT，也可在 X-API-Key 標頭帶入管理員建立的 API key
+以 API key 存取時，user_name 為 key 所屬的帳號，權限以 key 的 scopes 為準
+*/
+pub struct RequirePermission<P: PermissionMarker> {
+  pub user_name: String,
+  _permission: PhantomData<P>,
+}
+
+// 用戶端的 IP，用於登入失敗的節流
+/*
+預設為連線的來源位址，只有連線來自 TRUSTED_PROXIES 中的反向代理時才採用 X-Real-IP 標頭
+*/
+pub struct ClientIp(pub Option<IpAddr>);
+
+// 登入或換發後回傳的 access token 與 refresh token
 #[derive(Debug, Serialize, Deserialize)]
-pub struct ResendVerificationClaim {
-  pub email: String,
-  pub verification_token: String,
-  pub expiration: i64,
-  pub exp: usize,
+pub struct TokenPair {
+  pub access_token: String,
+  pub refresh_token: String,
+  pub expires_in: i64,
+}
+
+// 登入的回應，啟用兩步驟驗證的帳號需再以驗證碼完成登入
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResponse {
+  Tokens(TokenPair),
+  TotpRequired(totp::TotpChallenge),
+}
+
+// 登入失敗的回應，停權中的帳號回傳 403 與停權的結束時間、原因
+#[derive(Debug, Responder)]
+pub enum LoginError {
+  #[response(status = 403)]
+  Banned(Json<user::BanInfo>),
+  Status(Status),
+}
+
+impl From<Status> for LoginError {
+  fn from(status: Status) -> Self {
+    LoginError::Status(status)
+  }
+}
+
+// 登入紀錄，不包含 session id 與 refresh token
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SessionRecord {
+  pub created_at: i64,
+  pub expires_at: i64,
+  pub revoked_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct RefreshRequest {
+  #[validate(length(min = 1, max = 64))]
+  pub refresh_token: String,
 }
 
 impl Claim for UserInfoClaim {
   fn verify_jwt(token: &str) -> Result<UserInfoClaim, Status> {
-    let key = env::var("SECRET_KEY").expect("Failed to get secret key");
-
-    let token = handle(
-      decode::<UserInfoClaim>(
-        token,
-        &DecodingKey::from_secret(key.as_ref()),
-        &Validation::new(Algorithm::HS256),
-      ),
-      "Decoding JWT",
-    )?;
-
-    Ok(token.claims)
+    jwt::decode_token(token)
+  }
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for ClientIp {
+  type Error = Infallible;
+
+  async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+    let ip = match request.remote().map(|remote| remote.ip()) {
+      Some(remote) if get_trusted_proxies().contains(&remote) => request.real_ip().or(Some(remote)),
+      remote => remote,
+    };
+
+    Outcome::Success(ClientIp(ip))
   }
 }
 
@@ -50,53 +113,165 @@ impl<'r> FromRequest<'r> for UserInfoClaim {
 
   async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
     let headers = request.headers().get_one("Authorization");
-    match headers {
+    let claims = match headers {
       Some(header) => {
         let token = header.replace("Bearer ", "");
 
         match Self::verify_jwt(&token) {
-          Ok(claims) => Outcome::Success(claims),
-          Err(_) => Outcome::Failure((Status::Unauthorized, ())),
+          Ok(claims) => claims,
+          Err(_) => return Outcome::Failure((Status::Unauthorized, ())),
         }
       }
-      None => Outcome::Failure((Status::BadRequest, ())),
-    }
-  }
-}
+      None => return Outcome::Failure((Status::BadRequest, ())),
+    };
 
-impl Claim for ResendVerificationClaim {
-  fn verify_jwt(token: &str) -> Result<ResendVerificationClaim, Status> {
-    let key = env::var("SECRET_KEY").expect("Failed to get secret key");
+    let pool = match request.guard::<&State<Pool<Sqlite>>>().await {
+      Outcome::Success(pool) => pool.inner(),
+      _ => return Outcome::Failure((Status::InternalServerError, ())),
+    };
 
-    let token = handle(
-      decode::<ResendVerificationClaim>(
-        token,
-        &DecodingKey::from_secret(key.as_ref()),
-        &Validation::new(Algorithm::HS256),
-      ),
-      "Decoding JWT",
-    )?;
+    // 已登出或被撤銷的 session 不可再使用
+    match database::session::is_session_active(pool, &claims.sid, &claims.user).await {
+      Ok(true) => {}
+      Ok(false) => {
+        log::warn!("The session of user: {} has been revoked", claims.user);
+        return Outcome::Failure((Status::Unauthorized, ()));
+      }
+      Err(status) => return Outcome::Failure((status, ())),
+    }
 
-    Ok(token.claims)
+    // 停權中的使用者即使持有未過期的 token 也不可使用
+    match database::user::is_user_in_blacklist(pool, &claims.user).await {
+      Ok(false) => Outcome::Success(claims),
+      Ok(true) => {
+        log::warn!("User: {} is currently in the blacklist", claims.user);
+        Outcome::Failure((Status::Forbidden, ()))
+      }
+      Err(status) => Outcome::Failure((status, ())),
+    }
   }
 }
 
 #[rocket::async_trait]
-impl<'r> FromRequest<'r> for ResendVerificationClaim {
+impl<'r, P: PermissionMarker> FromRequest<'r> for RequirePermission<P> {
   type Error = ();
 
   async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
-    let headers = request.headers().get_one("Authorization");
-    match headers {
-      Some(header) => {
-        let token = header.replace("Bearer ", "");
+    if let Some(api_key) = request.headers().get_one(constant::API_KEY_HEADER) {
+      return Self::from_api_key(request, api_key).await;
+    }
 
-        match Self::verify_jwt(&token) {
-          Ok(claims) => Outcome::Success(claims),
-          Err(_) => Outcome::Failure((Status::Unauthorized, ())),
-        }
+    let claims = try_outcome!(UserInfoClaim::from_request(request).await);
+
+    if !claims.role.has_permission(P::PERMISSION) {
+      log::warn!(
+        "User: {} with role: {} lacks permission: {:?} for {}",
+        claims.user,
+        claims.role.to_string(),
+        P::PERMISSION,
+        request.uri()
+      );
+      return Outcome::Failure((Status::Forbidden, ()));
+    }
+
+    if let Err(status) = require_admin_totp(request, &claims.user, &claims.role).await {
+      return Outcome::Failure((status, ()));
+    }
+
+    Outcome::Success(RequirePermission {
+      user_name: claims.user,
+      _permission: PhantomData,
+    })
+  }
+}
+
+impl<P: PermissionMarker> RequirePermission<P> {
+  async fn from_api_key(request: &Request<'_>, api_key: &str) -> Outcome<Self, ()> {
+    let pool = match request.guard::<&State<Pool<Sqlite>>>().await {
+      Outcome::Success(pool) => pool.inner(),
+      _ => return Outcome::Failure((Status::InternalServerError, ())),
+    };
+
+    let api_key = match database::api_key::use_api_key(pool, &hash_token(api_key)).await {
+      Ok(Some(api_key)) => api_key,
+      Ok(None) => {
+        log::warn!("The API key is invalid or revoked");
+        return Outcome::Failure((Status::Unauthorized, ()));
       }
-      None => Outcome::Failure((Status::BadRequest, ())),
+      Err(status) => return Outcome::Failure((status, ())),
+    };
+
+    // key 的權限為 scopes 與帳號目前身分權限的交集，帳號降級後 key 的權限也隨之減少
+    let user_role = match database::user::get_user_info(pool, &api_key.user_name).await {
+      Ok(user_info) => user_info.user_role,
+      Err(status) => return Outcome::Failure((status, ())),
+    };
+
+    if !api_key.scopes.contains(&P::PERMISSION) || !user_role.has_permission(P::PERMISSION) {
+      log::warn!(
+        "API key: {} with role: {} lacks scope: {:?} for {}",
+        api_key.key_id,
+        user_role.to_string(),
+        P::PERMISSION,
+        request.uri()
+      );
+      return Outcome::Failure((Status::Forbidden, ()));
+    }
+
+    if let Err(status) = require_admin_totp(request, &api_key.user_name, &user_role).await {
+      return Outcome::Failure((status, ()));
+    }
+
+    // 停權中的帳號，其 API key 也不可使用
+    match database::user::is_user_in_blacklist(pool, &api_key.user_name).await {
+      Ok(false) => {}
+      Ok(true) => {
+        log::warn!("User: {} is currently in the blacklist", api_key.user_name);
+        return Outcome::Failure((Status::Forbidden, ()));
+      }
+      Err(status) => return Outcome::Failure((status, ())),
     }
+
+    log::info!(
+      "API key: {} accessing {} as user: {}",
+      api_key.key_id,
+      request.uri(),
+      api_key.user_name
+    );
+
+    Outcome::Success(RequirePermission {
+      user_name: api_key.user_name,
+      _permission: PhantomData,
+    })
   }
 }
+
+// 要求管理員啟用兩步驟驗證時，未啟用的管理員只能使用一般功能與設定兩步驟驗證
+/*
+以 API key 存取時依 key 所屬帳號檢查，避免以管理員的 key 略過此限制
+*/
+async fn require_admin_totp(
+  request: &Request<'_>,
+  user_name: &str,
+  user_role: &user::UserRole,
+) -> Result<(), Status> {
+  if *user_role != user::UserRole::Admin || !is_admin_totp_required() {
+    return Ok(());
+  }
+
+  let pool = match request.guard::<&State<Pool<Sqlite>>>().await {
+    Outcome::Success(pool) => pool.inner(),
+    _ => return Err(Status::InternalServerError),
+  };
+
+  if !database::totp::is_totp_enabled(pool, user_name).await? {
+    log::warn!(
+      "Admin: {} must enable TOTP before accessing {}",
+      user_name,
+      request.uri()
+    );
+    return Err(Status::Forbidden);
+  }
+
+  Ok(())
+}
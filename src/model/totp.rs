@@ -0,0 +1,56 @@
+use super::common::*;
+
+// 開始設定兩步驟驗證，需再次輸入密碼
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TotpSetupRequest {
+  #[serde(default)]
+  #[validate(length(max = 128))]
+  pub password: String,
+}
+
+// 驗證器 App 使用的密鑰，provisioning_uri (otpauth://) 可轉為 QR code 供掃描
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpSetup {
+  pub secret: String,
+  pub provisioning_uri: String,
+}
+
+// 以驗證器 App 產生的驗證碼確認設定
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TotpCodeRequest {
+  #[validate(length(min = 6, max = 6))]
+  pub code: String,
+}
+
+// code 可為驗證碼或備用碼
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DisableTotpRequest {
+  #[serde(default)]
+  #[validate(length(max = 128))]
+  pub password: String,
+  #[validate(length(min = 6, max = 20))]
+  pub code: String,
+}
+
+// 備用碼只在產生時回傳一次
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpRecoveryCodes {
+  pub recovery_codes: Vec<String>,
+}
+
+// 登入的第二步驟，code 可為驗證碼或備用碼
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct TotpLoginRequest {
+  #[validate(length(min = 1, max = 64))]
+  pub challenge: String,
+  #[validate(length(min = 6, max = 20))]
+  pub code: String,
+}
+
+// 帳號啟用兩步驟驗證時，密碼驗證通過後回傳的 challenge
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TotpChallenge {
+  pub totp_required: bool,
+  pub challenge: String,
+  pub expires_in: i64,
+}
@@ -1,4 +1,4 @@
-use super::{common::*, validate_utils::*};
+use super::{api_key, common::*, issue, reservation, token, validate_utils::*};
 use regex::Regex;
 use sqlx::{encode::IsNull, sqlite::SqliteArgumentValue, Encode};
 
@@ -6,10 +6,9 @@ use sqlx::{encode::IsNull, sqlite::SqliteArgumentValue, Encode};
 pub struct RegisterRequest {
   #[validate(length(min = 1, max = 20), custom = "validate_username")]
   pub user_name: String,
-  #[validate(length(min = 8, max = 20))]
+  #[validate(length(min = 8, max = 128))]
   pub password: String,
-  // #[validate(email, contains = "@mail.ntou.edu.tw")]
-  #[validate(email)]
+  #[validate(email, custom = "validate_email_domain")]
   pub email: String,
 }
 
@@ -20,17 +19,164 @@ pub struct UserInfo {
   pub email: String,
   pub user_role: UserRole,
   pub verified: bool,
-  pub verification_token: String,
+  // 以 OIDC 建立或已刪除的帳號沒有驗證 token
+  pub verification_token: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
 pub struct LoginRequest {
   #[validate(length(min = 1, max = 20), custom = "validate_username")]
   pub user_name: String,
-  #[validate(length(min = 8, max = 20))]
+  #[validate(length(min = 8, max = 128))]
   pub password: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResendVerificationRequest {
+  #[validate(email)]
+  pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ForgotPasswordRequest {
+  #[validate(email)]
+  pub email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ResetPasswordRequest {
+  #[validate(length(min = 1, max = 64))]
+  pub token: String,
+  #[validate(length(min = 8, max = 128))]
+  pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangePasswordRequest {
+  #[validate(length(min = 8, max = 128))]
+  pub current_password: String,
+  #[validate(length(min = 8, max = 128))]
+  pub new_password: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangeEmailRequest {
+  #[serde(default)]
+  #[validate(length(max = 128))]
+  pub password: String,
+  #[validate(email, custom = "validate_email_domain")]
+  pub new_email: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct ChangeUserNameRequest {
+  #[serde(default)]
+  #[validate(length(max = 128))]
+  pub password: String,
+  #[validate(length(min = 1, max = 20), custom = "validate_username")]
+  pub new_user_name: String,
+}
+
+// 使用者查詢自己的帳號資訊
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserProfile {
+  pub user_name: String,
+  pub email: String,
+  pub pending_email: Option<String>,
+  pub user_role: UserRole,
+  pub verified: bool,
+  pub totp_enabled: bool,
+  pub banned: bool,
+  pub ban_end_time: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct DeleteAccountRequest {
+  #[serde(default)]
+  #[validate(length(max = 128))]
+  pub password: String,
+}
+
+// 停權紀錄，lifted_at 為管理員解除或被新的停權取代的時間
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BanRecord {
+  pub ban_id: i64,
+  pub user_name: String,
+  pub start_time: i64,
+  pub end_time: i64,
+  pub reason: Option<String>,
+  pub created_by: Option<String>,
+  pub created_at: Option<i64>,
+  pub lifted_at: Option<i64>,
+  pub lifted_by: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BanPage {
+  pub bans: Vec<BanRecord>,
+  pub total: i64,
+  pub page: i64,
+  pub page_size: i64,
+}
+
+// 停權中的帳號登入時回傳的停權資訊
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BanInfo {
+  pub start_time: i64,
+  pub end_time: i64,
+  pub reason: Option<String>,
+}
+
+// 使用者個人資料匯出
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserDataExport {
+  pub exported_at: i64,
+  pub profile: UserProfile,
+  pub reservations: Vec<reservation::ReservationEvent>,
+  pub bans: Vec<BanRecord>,
+  pub seat_issues: Vec<issue::SeatIssue>,
+  // 稽核紀錄：登入的 session、擁有或建立的 API key，以及以管理員身分執行或解除的停權
+  pub sessions: Vec<token::SessionRecord>,
+  pub api_keys: Vec<api_key::ApiKey>,
+  pub issued_bans: Vec<BanRecord>,
+}
+
+// 管理員查詢的使用者列表
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserSummary {
+  pub user_name: String,
+  pub email: String,
+  pub user_role: UserRole,
+  pub verified: bool,
+  pub totp_enabled: bool,
+  pub disabled: bool,
+  pub banned: bool,
+  pub created_at: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserPage {
+  pub users: Vec<UserSummary>,
+  pub total: i64,
+  pub page: i64,
+  pub page_size: i64,
+}
+
+// 管理員查詢的使用者詳細資料，包含預約與停權紀錄
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserDetail {
+  pub profile: UserProfile,
+  pub disabled: bool,
+  pub created_at: Option<i64>,
+  pub reservations: Vec<reservation::ReservationEvent>,
+  pub bans: Vec<BanRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ChangeUserRoleRequest {
+  pub user_role: UserRole,
+}
+
 #[derive(Debug, Serialize, Deserialize, Validate)]
 #[validate(schema(function = "validate_ban_request", skip_on_field_errors = false))]
 pub struct BanRequest {
@@ -38,6 +184,17 @@ pub struct BanRequest {
   pub user_name: String,
   pub start_time: i64,
   pub end_time: i64,
+  // 停權原因，使用者登入時可以看到
+  #[validate(length(max = 200))]
+  pub reason: Option<String>,
+  // 是否取消停權期間內尚未開始的預約，釋出座位給其他使用者；進行中的預約則提前結束
+  #[serde(default)]
+  pub cancel_reservations: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BanResult {
+  pub cancelled_reservations: u64,
 }
 
 #[derive(Debug, Serialize, Deserialize, Validate)]
@@ -46,12 +203,69 @@ pub struct UnBanRequest {
   pub user_name: String,
 }
 
+#[derive(Debug, Serialize, Deserialize, Validate)]
+pub struct UnlockAccountRequest {
+  #[validate(length(min = 1, max = 20), custom = "validate_username")]
+  pub user_name: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
 pub enum UserRole {
   RegularUser,
+  // 館員，可管理座位與開放時間，但不可管理使用者
+  Staff,
+  // 設置於現場的查詢裝置與門禁系統，通常以管理員建立的 API key 存取，只能回報問題與替使用者報到
+  Kiosk,
   Admin,
 }
 
+// 登入失敗次數的計算對象
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+pub enum LoginThrottleKind {
+  Account,
+  Ip,
+}
+
+// 記錄一次登入失敗後的結果
+#[derive(Debug)]
+pub struct LoginFailure {
+  pub failed_count: i64,
+  pub blocked_until: Option<i64>,
+  // 此次失敗是否讓帳號被鎖定
+  pub locked: bool,
+}
+
+impl FromRow<'_, SqliteRow> for UserSummary {
+  fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+    Ok(UserSummary {
+      user_name: row.try_get("user_name")?,
+      email: row.try_get("email")?,
+      user_role: row.try_get("user_role")?,
+      verified: row.try_get("verified")?,
+      totp_enabled: row.try_get("totp_enabled")?,
+      disabled: row.try_get("disabled")?,
+      banned: row.try_get("banned")?,
+      created_at: row.try_get("created_at")?,
+    })
+  }
+}
+
+impl FromRow<'_, SqliteRow> for BanRecord {
+  fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
+    Ok(BanRecord {
+      ban_id: row.try_get("ban_id")?,
+      user_name: row.try_get("user_name")?,
+      start_time: row.try_get("start_time")?,
+      end_time: row.try_get("end_time")?,
+      reason: row.try_get("reason")?,
+      created_by: row.try_get("created_by")?,
+      created_at: row.try_get("created_at")?,
+      lifted_at: row.try_get("lifted_at")?,
+      lifted_by: row.try_get("lifted_by")?,
+    })
+  }
+}
+
 impl FromRow<'_, SqliteRow> for UserInfo {
   fn from_row(row: &SqliteRow) -> Result<Self, sqlx::Error> {
     Ok(UserInfo {
@@ -71,6 +285,8 @@ impl<'r> Decode<'r, Sqlite> for UserRole {
 
     match value {
       "RegularUser" => Ok(UserRole::RegularUser),
+      "Staff" => Ok(UserRole::Staff),
+      "Kiosk" => Ok(UserRole::Kiosk),
       "Admin" => Ok(UserRole::Admin),
       _ => Err("Invalid UserRole".into()),
     }
@@ -98,6 +314,8 @@ impl ToString for UserRole {
   fn to_string(&self) -> String {
     match *self {
       UserRole::RegularUser => "RegularUser".to_owned(),
+      UserRole::Staff => "Staff".to_owned(),
+      UserRole::Kiosk => "Kiosk".to_owned(),
       UserRole::Admin => "Admin".to_owned(),
     }
   }
@@ -109,6 +327,8 @@ impl FromStr for UserRole {
   fn from_str(s: &str) -> Result<Self, Self::Err> {
     match s {
       "RegularUser" => Ok(UserRole::RegularUser),
+      "Staff" => Ok(UserRole::Staff),
+      "Kiosk" => Ok(UserRole::Kiosk),
       "Admin" => Ok(UserRole::Admin),
       _ => Err(std::io::Error::new(
         ErrorKind::InvalidInput,
@@ -142,3 +362,5 @@ fn validate_ban_request(request: &BanRequest) -> Result<(), ValidationError> {
 
   Ok(())
 }
+
+impl_text_enum!(LoginThrottleKind, [Account, Ip]);
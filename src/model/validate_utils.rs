@@ -1,12 +1,10 @@
 use super::{common::*, constant::*};
-use crate::utils::{get_now, naive_datetime_to_timestamp, timestamp_to_naive_datetime};
+use crate::utils::{get_now_timestamp, is_email_domain_allowed, timestamp_to_naive_datetime};
 
 pub fn validate_datetime(start_time: i64, end_time: i64) -> Result<(), ValidationError> {
   on_the_same_day(start_time, end_time)?;
 
-  let now = get_now(); // 本地時間
-  let current_timestamp: i64 =
-    naive_datetime_to_timestamp(now).expect("Failed to convert naive datetime to timestamp");
+  let current_timestamp = get_now_timestamp();
 
   if start_time < current_timestamp {
     return Err(ValidationError::new(
@@ -43,3 +41,13 @@ pub fn validate_seat_id(seat_id: u16) -> Result<(), ValidationError> {
 
   Ok(())
 }
+
+pub fn validate_email_domain(email: &str) -> Result<(), ValidationError> {
+  if !is_email_domain_allowed(email) {
+    return Err(ValidationError::new(
+      "Email domain is not allowed for registration",
+    ));
+  }
+
+  Ok(())
+}
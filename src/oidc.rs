@@ -0,0 +1,702 @@
+use crate::{database, model::*, utils::*};
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use jsonwebtoken::{decode, decode_header, jwk::JwkSet, Algorithm, DecodingKey, Validation};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::{Pool, Sqlite};
+use std::env;
+use uuid::Uuid;
+
+// 自動建立帳號時，使用者名稱重複的重試次數
+const MAX_USER_NAME_ATTEMPTS: usize = 5;
+
+// OIDC 身分提供者的設定，未設定 OIDC_ISSUER 時停用 OIDC 登入
+/*
+OIDC_ISSUER 可設為本機的模擬身分提供者，方便測試
+OIDC_REDIRECT_URI 未設定時使用 {BASE_URL}/api/oidc/callback
+啟動時讀取一次，交由 rocket 管理，路由不再讀取環境變數
+*/
+pub struct OidcConfig {
+  pub issuer: String,
+  pub client_id: String,
+  pub client_secret: String,
+  pub redirect_uri: String,
+}
+
+pub fn get_oidc_config() -> Result<Option<OidcConfig>, Status> {
+  let issuer = match env::var("OIDC_ISSUER") {
+    Ok(issuer) if !issuer.is_empty() => issuer,
+    _ => return Ok(None),
+  };
+
+  let client_id = env::var("OIDC_CLIENT_ID").map_err(|_| {
+    log::error!("OIDC_CLIENT_ID must be set when OIDC_ISSUER is set");
+    Status::InternalServerError
+  })?;
+
+  Ok(Some(OidcConfig {
+    issuer: issuer.trim_end_matches('/').to_string(),
+    client_id,
+    client_secret: env::var("OIDC_CLIENT_SECRET").unwrap_or_default(),
+    redirect_uri: env::var("OIDC_REDIRECT_URI")
+      .unwrap_or_else(|_| format!("{}/api/oidc/callback", get_base_url())),
+  }))
+}
+
+// 身分提供者無法連線或回應無法解析時回傳 502
+fn handle_upstream<T, E>(result: Result<T, E>, prefix: &str) -> Result<T, Status>
+where
+  E: std::error::Error,
+{
+  result.map_err(|err| {
+    log::error!("{} failed with error: {:?}", prefix, err);
+    Status::BadGateway
+  })
+}
+
+#[derive(Deserialize)]
+struct ProviderMetadata {
+  issuer: String,
+  authorization_endpoint: String,
+  token_endpoint: String,
+  jwks_uri: String,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+  id_token: String,
+}
+
+// id_token 中使用到的 claims，iss、aud、exp 由 jsonwebtoken 驗證
+#[derive(Debug, Deserialize)]
+pub struct IdTokenClaims {
+  pub sub: String,
+  pub email: Option<String>,
+  pub email_verified: Option<bool>,
+  pub preferred_username: Option<String>,
+  pub nonce: Option<String>,
+}
+
+async fn discover(client: &Client, config: &OidcConfig) -> Result<ProviderMetadata, Status> {
+  let url = format!("{}/.well-known/openid-configuration", config.issuer);
+
+  let response = handle_upstream(
+    handle_upstream(
+      client.get(&url).send().await,
+      "Fetching OIDC discovery document",
+    )?
+    .error_for_status(),
+    "Fetching OIDC discovery document",
+  )?;
+
+  let metadata: ProviderMetadata =
+    handle_upstream(response.json().await, "Parsing OIDC discovery document")?;
+
+  if metadata.issuer.trim_end_matches('/') != config.issuer {
+    log::error!(
+      "OIDC issuer mismatch, expected: {}, got: {}",
+      config.issuer,
+      metadata.issuer
+    );
+    return Err(Status::BadGateway);
+  }
+
+  Ok(metadata)
+}
+
+// PKCE 的 code challenge (S256)
+pub fn create_code_challenge(code_verifier: &str) -> String {
+  URL_SAFE_NO_PAD.encode(Sha256::digest(code_verifier.as_bytes()))
+}
+
+pub async fn build_authorization_url(
+  config: &OidcConfig,
+  state: &str,
+  nonce: &str,
+  code_challenge: &str,
+) -> Result<String, Status> {
+  let metadata = discover(&Client::new(), config).await?;
+
+  let url = handle(
+    Url::parse_with_params(
+      &metadata.authorization_endpoint,
+      &[
+        ("response_type", "code"),
+        ("client_id", config.client_id.as_str()),
+        ("redirect_uri", config.redirect_uri.as_str()),
+        ("scope", "openid email profile"),
+        ("state", state),
+        ("nonce", nonce),
+        ("code_challenge", code_challenge),
+        ("code_challenge_method", "S256"),
+      ],
+    ),
+    "Building OIDC authorization url",
+  )?;
+
+  Ok(url.to_string())
+}
+
+// 以授權碼換取 id_token，並以身分提供者公開的 JWKS 驗證簽章與 nonce
+pub async fn exchange_code(
+  config: &OidcConfig,
+  code: &str,
+  code_verifier: &str,
+  nonce: &str,
+) -> Result<IdTokenClaims, Status> {
+  let client = Client::new();
+  let metadata = discover(&client, config).await?;
+
+  let response = handle_upstream(
+    client
+      .post(&metadata.token_endpoint)
+      .form(&[
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", config.redirect_uri.as_str()),
+        ("client_id", config.client_id.as_str()),
+        ("client_secret", config.client_secret.as_str()),
+        ("code_verifier", code_verifier),
+      ])
+      .send()
+      .await,
+    "Requesting OIDC token",
+  )?;
+
+  // 授權碼無效、已使用過或 PKCE 的 code_verifier 不符時，身分提供者回傳 4xx
+  if response.status().is_client_error() {
+    log::warn!(
+      "The OIDC provider rejected the authorization code: {}",
+      response.status()
+    );
+    return Err(Status::Unauthorized);
+  }
+
+  let token: TokenResponse = handle_upstream(
+    handle_upstream(response.error_for_status(), "Requesting OIDC token")?
+      .json()
+      .await,
+    "Parsing OIDC token response",
+  )?;
+
+  let jwks: JwkSet = handle_upstream(
+    handle_upstream(
+      handle_upstream(
+        client.get(&metadata.jwks_uri).send().await,
+        "Fetching OIDC JWKS",
+      )?
+      .error_for_status(),
+      "Fetching OIDC JWKS",
+    )?
+    .json()
+    .await,
+    "Parsing OIDC JWKS",
+  )?;
+
+  let header = handle(decode_header(&token.id_token), "Decoding id_token header")?;
+
+  // 只接受非對稱式簽章，避免以 client secret 偽造的 HMAC 簽章
+  if matches!(
+    header.alg,
+    Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+  ) {
+    log::warn!("Unsupported id_token algorithm: {:?}", header.alg);
+    return Err(Status::Unauthorized);
+  }
+
+  let jwk = match &header.kid {
+    Some(kid) => jwks.find(kid),
+    None => jwks.keys.first(),
+  }
+  .ok_or_else(|| {
+    log::warn!("No matching JWK found for id_token kid: {:?}", header.kid);
+    Status::Unauthorized
+  })?;
+
+  let decoding_key = handle(DecodingKey::from_jwk(jwk), "Loading JWK")?;
+
+  let mut validation = Validation::new(header.alg);
+  validation.set_audience(&[&config.client_id]);
+  validation.set_issuer(&[&config.issuer, &metadata.issuer]);
+
+  let claims = decode::<IdTokenClaims>(&token.id_token, &decoding_key, &validation)
+    .map_err(|e| {
+      log::warn!("Invalid id_token: {:?}", e);
+      Status::Unauthorized
+    })?
+    .claims;
+
+  if claims.nonce.as_deref() != Some(nonce) {
+    log::warn!("The id_token nonce does not match");
+    return Err(Status::Unauthorized);
+  }
+
+  Ok(claims)
+}
+
+// 由身分提供者的資料產生使用者名稱，只保留英數字
+fn derive_user_name(claims: &IdTokenClaims) -> String {
+  let base = claims
+    .preferred_username
+    .as_deref()
+    .or_else(|| {
+      claims
+        .email
+        .as_deref()
+        .and_then(|email| email.split('@').next())
+    })
+    .unwrap_or_default();
+
+  let user_name: String = base
+    .chars()
+    .filter(char::is_ascii_alphanumeric)
+    .take(14)
+    .collect();
+
+  if user_name.is_empty() {
+    "user".to_string()
+  } else {
+    user_name
+  }
+}
+
+// 找出 OIDC 帳號對應的使用者
+/*
+1. 已連結 subject 的帳號
+2. email 相同的既有帳號，連結 subject (身分提供者需驗證過 email)
+3. 自動建立已驗證的帳號，身分依 email 網域決定
+*/
+pub async fn find_or_create_user(
+  pool: &Pool<Sqlite>,
+  claims: &IdTokenClaims,
+) -> Result<user::UserInfo, Status> {
+  if let Some(user_info) = database::oidc::get_user_info_by_oidc_subject(pool, &claims.sub).await? {
+    return Ok(user_info);
+  }
+
+  let email = claims.email.as_deref().ok_or_else(|| {
+    log::warn!("The id_token does not contain an email");
+    Status::BadRequest
+  })?;
+
+  // 未提供 email_verified 時視為未驗證，避免以未驗證的 email 連結既有帳號
+  if claims.email_verified != Some(true) {
+    log::warn!("The email of OIDC subject: {} is not verified", claims.sub);
+    return Err(Status::Forbidden);
+  }
+
+  if !is_email_domain_allowed(email) {
+    log::warn!(
+      "The email domain of OIDC subject: {} is not allowed",
+      claims.sub
+    );
+    return Err(Status::Forbidden);
+  }
+
+  if let Some(user_info) = database::oidc::link_oidc_subject(pool, email, &claims.sub).await? {
+    log::info!(
+      "Linked OIDC subject: {} to user: {}",
+      claims.sub,
+      user_info.user_name
+    );
+    return Ok(user_info);
+  }
+
+  if database::user::get_user_name_by_email(pool, email)
+    .await?
+    .is_some()
+  {
+    log::warn!("The email is already linked to another OIDC subject");
+    return Err(Status::Conflict);
+  }
+
+  let base_user_name = derive_user_name(claims);
+  let user_role = get_email_domain_role(email);
+
+  for attempt in 0..MAX_USER_NAME_ATTEMPTS {
+    let user_name = if attempt == 0 {
+      base_user_name.clone()
+    } else {
+      format!(
+        "{}{}",
+        base_user_name,
+        &Uuid::new_v4().simple().to_string()[..6]
+      )
+    };
+
+    let inserted =
+      database::oidc::insert_oidc_user(pool, &user_name, email, user_role.clone(), &claims.sub)
+        .await?;
+
+    if inserted {
+      log::info!(
+        "Created user: {} for OIDC subject: {}",
+        user_name,
+        claims.sub
+      );
+      return database::user::get_user_info(pool, &user_name).await;
+    }
+  }
+
+  log::error!(
+    "Failed to find an available user name for OIDC subject: {}",
+    claims.sub
+  );
+  Err(Status::Conflict)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::{oidc_callback, oidc_login};
+  use jsonwebtoken::{encode, EncodingKey, Header};
+  use ring::{
+    rand::SystemRandom,
+    signature::{Ed25519KeyPair, KeyPair},
+  };
+  use rocket::{http::Status, local::asynchronous::Client as LocalClient, routes};
+  use serde_json::{json, Value};
+  use std::{
+    collections::HashMap,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+  };
+
+  const CLIENT_ID: &str = "study-seat-reserve";
+  const KEY_ID: &str = "mock-idp-key";
+
+  // 模擬身分提供者發出的授權碼，換取 id_token 時檢查 PKCE
+  struct IssuedCode {
+    code_challenge: String,
+    claims: Value,
+  }
+
+  // 本機的模擬身分提供者，提供 discovery、token 與 JWKS 端點
+  struct MockIdp {
+    issuer: String,
+    codes: Arc<Mutex<HashMap<String, IssuedCode>>>,
+  }
+
+  impl MockIdp {
+    fn start() -> MockIdp {
+      let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+      let issuer = format!("http://{}", listener.local_addr().unwrap());
+
+      let pkcs8 = Ed25519KeyPair::generate_pkcs8(&SystemRandom::new()).unwrap();
+      let key_pair = Ed25519KeyPair::from_pkcs8(pkcs8.as_ref()).unwrap();
+      let jwks = json!({
+        "keys": [{
+          "kty": "OKP",
+          "crv": "Ed25519",
+          "alg": "EdDSA",
+          "use": "sig",
+          "kid": KEY_ID,
+          "x": URL_SAFE_NO_PAD.encode(key_pair.public_key()),
+        }]
+      });
+
+      let codes: Arc<Mutex<HashMap<String, IssuedCode>>> = Arc::default();
+      let server_issuer = issuer.clone();
+      let server_codes = codes.clone();
+      let server_key = EncodingKey::from_ed_der(pkcs8.as_ref());
+
+      thread::spawn(move || {
+        for stream in listener.incoming() {
+          let mut stream = stream.unwrap();
+          let (path, body) = read_request(&mut stream);
+
+          let (status, response) = match path.as_str() {
+            "/.well-known/openid-configuration" => (
+              "200 OK",
+              json!({
+                "issuer": server_issuer,
+                "authorization_endpoint": format!("{}/authorize", server_issuer),
+                "token_endpoint": format!("{}/token", server_issuer),
+                "jwks_uri": format!("{}/jwks", server_issuer),
+              }),
+            ),
+            "/jwks" => ("200 OK", jwks.clone()),
+            "/token" => {
+              let form: HashMap<String, String> = Url::parse(&format!("http://form/?{}", body))
+                .unwrap()
+                .query_pairs()
+                .into_owned()
+                .collect();
+
+              // 授權碼只能使用一次，且 code_verifier 需與授權時的 code_challenge 相符
+              let issued = server_codes.lock().unwrap().remove(&form["code"]);
+
+              match issued {
+                Some(issued)
+                  if create_code_challenge(&form["code_verifier"]) == issued.code_challenge
+                    && form["client_id"] == CLIENT_ID =>
+                {
+                  let mut header = Header::new(Algorithm::EdDSA);
+                  header.kid = Some(KEY_ID.to_string());
+
+                  let id_token = encode(&header, &issued.claims, &server_key).unwrap();
+
+                  (
+                    "200 OK",
+                    json!({ "id_token": id_token, "token_type": "Bearer" }),
+                  )
+                }
+                _ => ("400 Bad Request", json!({ "error": "invalid_grant" })),
+              }
+            }
+            _ => ("404 Not Found", json!({})),
+          };
+
+          let response = response.to_string();
+          let _ = write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            response.len(),
+            response
+          );
+        }
+      });
+
+      MockIdp { issuer, codes }
+    }
+
+    fn id_token_claims(&self, nonce: &str, sub: &str, email: &str, email_verified: bool) -> Value {
+      json!({
+        "iss": self.issuer,
+        "aud": CLIENT_ID,
+        "exp": chrono::Utc::now().timestamp() + 300,
+        "sub": sub,
+        "email": email,
+        "email_verified": email_verified,
+        "preferred_username": email.split('@').next(),
+        "nonce": nonce,
+      })
+    }
+
+    fn issue_code(&self, code: &str, code_challenge: &str, claims: Value) {
+      self.codes.lock().unwrap().insert(
+        code.to_string(),
+        IssuedCode {
+          code_challenge: code_challenge.to_string(),
+          claims,
+        },
+      );
+    }
+  }
+
+  fn read_request(stream: &mut TcpStream) -> (String, String) {
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).unwrap();
+    let path = request_line
+      .split_whitespace()
+      .nth(1)
+      .unwrap_or_default()
+      .to_string();
+
+    let mut content_length = 0;
+    loop {
+      let mut line = String::new();
+      reader.read_line(&mut line).unwrap();
+
+      if line.trim().is_empty() {
+        break;
+      }
+
+      if let Some((name, value)) = line.split_once(':') {
+        if name.eq_ignore_ascii_case("content-length") {
+          content_length = value.trim().parse().unwrap();
+        }
+      }
+    }
+
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body).unwrap();
+
+    (path, String::from_utf8(body).unwrap())
+  }
+
+  // 導向身分提供者，回傳授權網址中的參數
+  async fn start_login(client: &LocalClient) -> HashMap<String, String> {
+    let response = client.get("/api/oidc/login").dispatch().await;
+    assert_eq!(response.status(), Status::SeeOther);
+
+    let location = response.headers().get_one("Location").unwrap();
+    Url::parse(location)
+      .unwrap()
+      .query_pairs()
+      .into_owned()
+      .collect()
+  }
+
+  fn verified_claims(sub: &str, email: &str, email_verified: Option<bool>) -> IdTokenClaims {
+    IdTokenClaims {
+      sub: sub.to_string(),
+      email: Some(email.to_string()),
+      email_verified,
+      preferred_username: None,
+      nonce: None,
+    }
+  }
+
+  async fn insert_password_user(pool: &Pool<Sqlite>, user_name: &str, email: &str, verified: bool) {
+    database::user::insert_new_user_info(
+      pool,
+      user::UserInfo {
+        user_name: user_name.to_string(),
+        password_hash: "password-hash".to_string(),
+        email: email.to_string(),
+        user_role: user::UserRole::RegularUser,
+        verified,
+        verification_token: None,
+      },
+    )
+    .await
+    .unwrap();
+  }
+
+  #[tokio::test]
+  async fn login_with_mock_idp_checks_state_nonce_and_pkce() {
+    let pool = database::connect_test_pool().await;
+    let idp = MockIdp::start();
+
+    let config = OidcConfig {
+      issuer: idp.issuer.clone(),
+      client_id: CLIENT_ID.to_string(),
+      client_secret: String::new(),
+      redirect_uri: "http://localhost/api/oidc/callback".to_string(),
+    };
+
+    let rocket = rocket::build()
+      .mount("/", routes![oidc_login, oidc_callback])
+      .manage(pool.clone())
+      .manage(Some(config));
+    let client = LocalClient::tracked(rocket).await.unwrap();
+
+    let params = start_login(&client).await;
+    assert_eq!(params["client_id"], CLIENT_ID);
+    assert_eq!(params["code_challenge_method"], "S256");
+
+    idp.issue_code(
+      "code-1",
+      &params["code_challenge"],
+      idp.id_token_claims(&params["nonce"], "subject-1", "alice@example.com", true),
+    );
+
+    // 不存在的 state
+    let response = client
+      .get("/api/oidc/callback?code=code-1&state=unknown")
+      .dispatch()
+      .await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    let callback = format!("/api/oidc/callback?code=code-1&state={}", params["state"]);
+    let response = client.get(callback.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::Ok);
+
+    let body: Value = response.into_json().await.unwrap();
+    assert!(body["access_token"].is_string());
+
+    let user_info = database::oidc::get_user_info_by_oidc_subject(&pool, "subject-1")
+      .await
+      .unwrap()
+      .unwrap();
+    assert_eq!(user_info.user_name, "alice");
+    assert!(user_info.verified);
+    assert!(user_info.password_hash.is_empty());
+
+    // state 只能使用一次
+    let response = client.get(callback.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::BadRequest);
+
+    // id_token 的 nonce 與此次登入不符
+    let params = start_login(&client).await;
+    idp.issue_code(
+      "code-2",
+      &params["code_challenge"],
+      idp.id_token_claims("another-nonce", "subject-1", "alice@example.com", true),
+    );
+    let callback = format!("/api/oidc/callback?code=code-2&state={}", params["state"]);
+    let response = client.get(callback.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+
+    // 授權碼綁定的 code_challenge 與此次登入的 code_verifier 不符
+    let params = start_login(&client).await;
+    idp.issue_code(
+      "code-3",
+      &create_code_challenge("another-code-verifier"),
+      idp.id_token_claims(&params["nonce"], "subject-1", "alice@example.com", true),
+    );
+    let callback = format!("/api/oidc/callback?code=code-3&state={}", params["state"]);
+    let response = client.get(callback.as_str()).dispatch().await;
+    assert_eq!(response.status(), Status::Unauthorized);
+  }
+
+  #[tokio::test]
+  async fn provisions_new_account_for_verified_email() {
+    let pool = database::connect_test_pool().await;
+
+    let claims = IdTokenClaims {
+      preferred_username: Some("bob.smith".to_string()),
+      ..verified_claims("subject-bob", "bob@example.com", Some(true))
+    };
+
+    let user_info = find_or_create_user(&pool, &claims).await.unwrap();
+    assert_eq!(user_info.user_name, "bobsmith");
+    assert!(user_info.verified);
+
+    // 再次登入時以 subject 找到同一個帳號
+    let user_info = find_or_create_user(&pool, &claims).await.unwrap();
+    assert_eq!(user_info.user_name, "bobsmith");
+  }
+
+  #[tokio::test]
+  async fn links_verified_account_and_keeps_its_password() {
+    let pool = database::connect_test_pool().await;
+    insert_password_user(&pool, "carol", "carol@example.com", true).await;
+
+    let claims = verified_claims("subject-carol", "carol@example.com", Some(true));
+    let user_info = find_or_create_user(&pool, &claims).await.unwrap();
+
+    assert_eq!(user_info.user_name, "carol");
+    assert_eq!(user_info.password_hash, "password-hash");
+  }
+
+  #[tokio::test]
+  async fn clears_password_of_unverified_account_before_linking() {
+    let pool = database::connect_test_pool().await;
+    insert_password_user(&pool, "mallory", "dave@example.com", false).await;
+
+    let claims = verified_claims("subject-dave", "dave@example.com", Some(true));
+    let user_info = find_or_create_user(&pool, &claims).await.unwrap();
+
+    assert_eq!(user_info.user_name, "mallory");
+    assert!(user_info.verified);
+    assert!(user_info.password_hash.is_empty());
+  }
+
+  #[tokio::test]
+  async fn rejects_email_not_verified_by_provider() {
+    let pool = database::connect_test_pool().await;
+    insert_password_user(&pool, "erin", "erin@example.com", true).await;
+
+    for email_verified in [None, Some(false)] {
+      let claims = verified_claims("subject-erin", "erin@example.com", email_verified);
+
+      assert_eq!(
+        find_or_create_user(&pool, &claims).await.unwrap_err(),
+        Status::Forbidden
+      );
+    }
+
+    let linked = database::oidc::get_user_info_by_oidc_subject(&pool, "subject-erin")
+      .await
+      .unwrap();
+    assert!(linked.is_none());
+  }
+}
@@ -1,5 +1,5 @@
 use crate::{database, utils::*};
-use chrono::{Datelike, Duration, NaiveDate};
+use chrono::{Duration, NaiveDate};
 use sqlx::{Pool, Sqlite};
 use std::fs;
 use tokio::time::sleep;
@@ -8,18 +8,23 @@ pub async fn start(pool: &Pool<Sqlite>) {
   log::info!("Starting timmer!");
   delete_logfile();
   set_unavailable_timeslots(pool).await;
+  delete_unverified_users(pool).await;
+  delete_expired_login_throttles(pool).await;
   loop {
-    let today = get_today().and_hms_opt(0, 0, 0).unwrap();
-    let tomorrow_midnight = today + Duration::days(1);
-    let now = get_now();
+    // 以 timestamp 計算，避免日光節約時間切換當天的誤差
+    let tomorrow_midnight =
+      naive_date_to_timestamp(get_today() + Duration::days(1), 0, 0, 0).unwrap();
+    let now = get_now_timestamp();
 
-    let duration = tomorrow_midnight - now;
-    let std_duration = duration.to_std().unwrap();
+    let duration = Duration::seconds(tomorrow_midnight - now);
+    let std_duration = duration.to_std().unwrap_or_default();
     // let std_duration = std::time::Duration::from_secs(3);
 
     sleep(std_duration).await;
     delete_logfile();
     set_unavailable_timeslots(pool).await;
+    delete_unverified_users(pool).await;
+    delete_expired_login_throttles(pool).await;
   }
 }
 
@@ -65,63 +70,34 @@ fn delete_logfile() {
 async fn set_unavailable_timeslots(pool: &Pool<Sqlite>) {
   log::info!("Setting unavailable timeslots");
 
-  let today = get_today();
-
-  let future_date = today + chrono::Duration::days(3);
-  let weekday = future_date.weekday();
-  let is_holiday = weekday == chrono::Weekday::Sat || weekday == chrono::Weekday::Sun;
-
-  let mut time_slots: Vec<(i64, i64)> = Vec::new();
-
-  if is_holiday {
-    let start_time: i64 = naive_date_to_timestamp(future_date, 0, 0, 0).expect("Invalid timestamp");
-    let end_time: i64 = naive_date_to_timestamp(future_date, 9, 0, 0).expect("Invalid timestamp");
-
-    time_slots.push((start_time, end_time));
-
-    let start_time: i64 =
-      naive_date_to_timestamp(future_date, 17, 0, 0).expect("Invalid timestamp");
-    let end_time: i64 =
-      naive_date_to_timestamp(future_date, 23, 59, 59).expect("Invalid timestamp");
-
-    time_slots.push((start_time, end_time));
-  } else {
-    let start_time: i64 = naive_date_to_timestamp(future_date, 0, 0, 0).expect("Invalid timestamp");
-    let end_time: i64 = naive_date_to_timestamp(future_date, 8, 0, 0).expect("Invalid timestamp");
+  database::opening_hours::materialize_closures(pool, get_today(), get_closure_horizon_days())
+    .await
+    .unwrap_or_else(|e| {
+      log::error!("Failed to materialize closures: {}", e);
+      panic!("Failed to materialize closures: {}", e);
+    });
+}
 
-    time_slots.push((start_time, end_time));
+// 刪除註冊後超過期限仍未驗證 email 的帳號
+async fn delete_unverified_users(pool: &Pool<Sqlite>) {
+  log::info!("Deleting unverified users");
 
-    let start_time: i64 =
-      naive_date_to_timestamp(future_date, 22, 0, 0).expect("Invalid timestamp");
-    let end_time: i64 =
-      naive_date_to_timestamp(future_date, 23, 59, 59).expect("Invalid timestamp");
+  let created_before =
+    get_now_timestamp() - Duration::days(get_unverified_account_days()).num_seconds();
 
-    time_slots.push((start_time, end_time));
+  match database::user::delete_unverified_users(pool, created_before).await {
+    Ok(deleted) => log::info!("Deleted {} unverified users", deleted),
+    Err(e) => log::error!("Failed to delete unverified users: {}", e),
   }
+}
 
-  for (start_time, end_time) in time_slots.into_iter() {
-    let is_overlapping =
-      database::timeslot::is_overlapping_with_unavailable_timeslot(&pool, start_time, end_time)
-        .await
-        .unwrap_or_else(|e| {
-          log::error!(
-            "Failed to check overlapping with unavailable timeslot: {}",
-            e
-          );
-          panic!(
-            "Failed to check overlapping with unavailable timeslot: {}",
-            e
-          );
-        });
-
-    if !is_overlapping {
-      database::timeslot::insert_unavailable_timeslot(pool, start_time, end_time)
-        .await
-        .unwrap_or_else(|e| {
-          log::error!("Failed to insert unavailable timeslots: {}", e);
-          panic!("Failed to insert unavailable timeslots: {}", e);
-        });
-    }
+// 刪除過期的登入失敗紀錄
+async fn delete_expired_login_throttles(pool: &Pool<Sqlite>) {
+  log::info!("Deleting expired login throttles");
+
+  match database::login_throttle::delete_expired_login_throttles(pool).await {
+    Ok(deleted) => log::info!("Deleted {} expired login throttles", deleted),
+    Err(e) => log::error!("Failed to delete expired login throttles: {}", e),
   }
 }
 
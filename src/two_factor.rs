@@ -0,0 +1,211 @@
+use crate::{database, model::*, utils::*};
+use base32::Alphabet;
+use hmac::{Hmac, Mac};
+use rand::{rngs::OsRng, RngCore};
+use reqwest::Url;
+use sha1::Sha1;
+use sqlx::{Pool, Sqlite};
+use uuid::Uuid;
+
+const SECRET_ALPHABET: Alphabet = Alphabet::RFC4648 { padding: false };
+
+// 產生 160 bits 的隨機密鑰，以 base32 編碼
+pub fn generate_secret() -> String {
+  let mut secret = [0u8; 20];
+  OsRng.fill_bytes(&mut secret);
+
+  base32::encode(SECRET_ALPHABET, &secret)
+}
+
+// 驗證器 App 使用的 otpauth:// URI
+pub fn get_provisioning_uri(user_name: &str, secret: &str) -> Result<String, Status> {
+  let uri = handle(
+    Url::parse_with_params(
+      &format!("otpauth://totp/{}:{}", constant::TOTP_ISSUER, user_name),
+      &[
+        ("secret", secret),
+        ("issuer", constant::TOTP_ISSUER),
+        ("algorithm", "SHA1"),
+        ("digits", &constant::TOTP_DIGITS.to_string()),
+        ("period", &constant::TOTP_STEP_SECONDS.to_string()),
+      ],
+    ),
+    "Building TOTP provisioning uri",
+  )?;
+
+  Ok(uri.to_string())
+}
+
+// RFC 4226 HOTP
+fn hotp(secret: &[u8], counter: u64) -> u32 {
+  let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC can take key of any size");
+  mac.update(&counter.to_be_bytes());
+  let hash = mac.finalize().into_bytes();
+
+  let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+  let binary = u32::from_be_bytes([
+    hash[offset] & 0x7f,
+    hash[offset + 1],
+    hash[offset + 2],
+    hash[offset + 3],
+  ]);
+
+  binary % 10_u32.pow(constant::TOTP_DIGITS)
+}
+
+// 驗證 TOTP 驗證碼，回傳符合的時間步數
+pub fn verify_code(secret: &str, code: &str, now: i64) -> Option<i64> {
+  if code.len() != constant::TOTP_DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+    return None;
+  }
+
+  let secret = base32::decode(SECRET_ALPHABET, secret)?;
+  let code: u32 = code.parse().ok()?;
+  let current_step = now / constant::TOTP_STEP_SECONDS;
+
+  (current_step - constant::TOTP_ALLOWED_SKEW_STEPS
+    ..=current_step + constant::TOTP_ALLOWED_SKEW_STEPS)
+    .find(|step| hotp(&secret, *step as u64) == code)
+}
+
+// 產生備用碼，格式為 xxxxx-xxxxx
+pub fn generate_recovery_codes() -> Vec<String> {
+  (0..constant::TOTP_RECOVERY_CODE_COUNT)
+    .map(|_| {
+      let code = Uuid::new_v4().simple().to_string();
+      format!("{}-{}", &code[..5], &code[5..10])
+    })
+    .collect()
+}
+
+// 備用碼不分大小寫，忽略分隔符號
+pub fn hash_recovery_code(code: &str) -> String {
+  let normalized: String = code
+    .chars()
+    .filter(char::is_ascii_alphanumeric)
+    .map(|c| c.to_ascii_lowercase())
+    .collect();
+
+  hash_token(&normalized)
+}
+
+// 驗證兩步驟驗證碼或備用碼，使用過的驗證碼與備用碼不可再次使用
+pub async fn verify_second_factor(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  code: &str,
+) -> Result<bool, Status> {
+  let (secret, enabled) = database::totp::get_totp_secret(pool, user_name).await?;
+
+  let secret = match (secret, enabled) {
+    (Some(secret), true) => secret,
+    _ => {
+      log::warn!("TOTP is not enabled for user: {}", user_name);
+      return Err(Status::BadRequest);
+    }
+  };
+
+  let now = get_now_timestamp();
+
+  match verify_code(&secret, code, now) {
+    Some(step) => database::totp::use_totp_step(pool, user_name, step).await,
+    None => database::totp::use_recovery_code(pool, user_name, &hash_recovery_code(code)).await,
+  }
+}
+
+// 密碼或 OIDC 驗證通過後完成登入
+/*
+未啟用兩步驟驗證時直接建立 session
+已啟用時回傳 challenge，需再以驗證碼呼叫 /api/login/totp 才會發放 token
+*/
+pub async fn start_login(
+  pool: &Pool<Sqlite>,
+  user_name: &str,
+  user_role: user::UserRole,
+) -> Result<token::LoginResponse, Status> {
+  if database::user::is_user_disabled(pool, user_name).await? {
+    log::warn!("The account of user: {} is disabled", user_name);
+    return Err(Status::Forbidden);
+  }
+
+  if !database::totp::is_totp_enabled(pool, user_name).await? {
+    let token_pair = database::session::create_session(pool, user_name, user_role).await?;
+
+    return Ok(token::LoginResponse::Tokens(token_pair));
+  }
+
+  let challenge = Uuid::new_v4().simple().to_string();
+  let expires_in = constant::TOTP_CHALLENGE_MINUTES * 60;
+  let expires_at = get_now_timestamp() + expires_in;
+
+  database::totp::insert_totp_challenge(pool, &hash_token(&challenge), user_name, expires_at)
+    .await?;
+
+  log::info!("User: {} is required to enter a TOTP code", user_name);
+
+  Ok(token::LoginResponse::TotpRequired(totp::TotpChallenge {
+    totp_required: true,
+    challenge,
+    expires_in,
+  }))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // RFC 6238 附錄 B 的 SHA1 測試向量，密鑰為 ASCII 的 "12345678901234567890"，取後 6 位數
+  const RFC_SECRET: &[u8] = b"12345678901234567890";
+  const RFC_VECTORS: [(i64, &str); 6] = [
+    (59, "287082"),
+    (1111111109, "081804"),
+    (1111111111, "050471"),
+    (1234567890, "005924"),
+    (2000000000, "279037"),
+    (20000000000, "353130"),
+  ];
+
+  #[test]
+  fn hotp_matches_rfc_6238_vectors() {
+    for (time, code) in RFC_VECTORS {
+      let step = (time / constant::TOTP_STEP_SECONDS) as u64;
+
+      assert_eq!(
+        format!("{:06}", hotp(RFC_SECRET, step)),
+        code,
+        "time: {}",
+        time
+      );
+    }
+  }
+
+  #[test]
+  fn verify_code_accepts_rfc_6238_vectors_within_skew() {
+    let secret = base32::encode(SECRET_ALPHABET, RFC_SECRET);
+
+    for (time, code) in RFC_VECTORS {
+      let step = time / constant::TOTP_STEP_SECONDS;
+
+      assert_eq!(verify_code(&secret, code, time), Some(step));
+
+      // 前後各容許 TOTP_ALLOWED_SKEW_STEPS 個時間步
+      let skew = constant::TOTP_STEP_SECONDS * constant::TOTP_ALLOWED_SKEW_STEPS;
+      assert_eq!(verify_code(&secret, code, time + skew), Some(step));
+      assert_eq!(verify_code(&secret, code, time - skew), Some(step));
+      assert_eq!(
+        verify_code(&secret, code, time + skew + constant::TOTP_STEP_SECONDS),
+        None
+      );
+    }
+  }
+
+  #[test]
+  fn verify_code_rejects_malformed_codes() {
+    let secret = base32::encode(SECRET_ALPHABET, RFC_SECRET);
+
+    assert_eq!(verify_code(&secret, "28708", 59), None);
+    assert_eq!(verify_code(&secret, "2870820", 59), None);
+    assert_eq!(verify_code(&secret, "28708a", 59), None);
+    assert_eq!(verify_code("not base32!", "287082", 59), None);
+  }
+}
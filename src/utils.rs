@@ -1,14 +1,23 @@
-use crate::model::*;
-use chrono::{Duration, FixedOffset, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
-use jsonwebtoken::{encode, Algorithm, EncodingKey, Header};
-use lettre::{
-  message::Mailbox, transport::smtp::authentication::Credentials, Message, SmtpTransport, Transport,
+use crate::{jwt, mailer, model::*};
+use argon2::{
+  password_hash::{rand_core::OsRng, SaltString},
+  Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier,
 };
+use bcrypt::verify;
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use rocket::{fs::TempFile, http::ContentType};
+use sha2::{Digest, Sha256};
 use sqlx::Error as SqlxError;
 use std::{
-  env,
+  collections::HashMap,
+  env, fs,
   io::{Error as IoError, ErrorKind},
+  net::IpAddr,
+  path::Path,
+  sync::OnceLock,
 };
+use uuid::Uuid;
 use validator::ValidationErrorsKind;
 
 pub use rocket::http::Status;
@@ -73,21 +82,35 @@ pub fn handle_validator(result: Result<(), validator::ValidationErrors>) -> Resu
   })
 }
 
+// 服務所在的時區，由 TIME_ZONE 設定 IANA 時區名稱(預設 Asia/Taipei)，所有本地時間的換算都經過這裡
+pub fn get_time_zone() -> Tz {
+  static TIME_ZONE: OnceLock<Tz> = OnceLock::new();
+
+  *TIME_ZONE.get_or_init(|| {
+    let name = env::var("TIME_ZONE").unwrap_or_else(|_| constant::DEFAULT_TIME_ZONE.to_string());
+
+    name.parse().unwrap_or_else(|e| {
+      log::error!("Invalid time zone '{}': {}", name, e);
+      panic!("Invalid time zone '{}': {}", name, e);
+    })
+  })
+}
+
 pub fn get_today() -> NaiveDate {
-  let taipei_offset = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-  Local::now().with_timezone(&taipei_offset).date_naive()
+  get_now().date()
 }
 
 pub fn get_now() -> NaiveDateTime {
-  let taipei_offset = FixedOffset::east_opt(8 * 3600).expect("Invalid offset");
-  Local::now().with_timezone(&taipei_offset).naive_local()
+  Utc::now().with_timezone(&get_time_zone()).naive_local()
+}
+
+// 目前時間的 timestamp，不經過本地時間轉換，避免日光節約時間重複的一小時內早了一小時
+pub fn get_now_timestamp() -> i64 {
+  Utc::now().timestamp()
 }
 
 pub fn time_to_string(timestamp: i64) -> Result<String, Status> {
-  let naive_datetime = NaiveDateTime::from_timestamp_opt(timestamp, 0).ok_or_else(|| {
-    log::error!("Invalid timestamp");
-    Status::InternalServerError
-  })?;
+  let naive_datetime = timestamp_to_naive_datetime(timestamp)?;
 
   Ok(naive_datetime.format("%Y-%m-%d %H:%M:%S").to_string())
 }
@@ -103,31 +126,30 @@ pub fn naive_date_to_timestamp(
     Status::InternalServerError
   })?;
 
-  // 本地日期時間 GMT++8
-  let datetime_local = NaiveDateTime::new(date, time);
-  // GNT 0 日期時間
-  let datetime = datetime_local - chrono::Duration::hours(8);
-
-  let timestamp = datetime.timestamp();
-
-  Ok(timestamp)
+  naive_datetime_to_timestamp(NaiveDateTime::new(date, time))
 }
 
-/// -> get now timestamp
+// 使用者輸入的本地日期時間轉為 timestamp，目前時間請使用 get_now_timestamp
 pub fn naive_datetime_to_timestamp(datetime: NaiveDateTime) -> Result<i64, Status> {
-  let datetime = datetime - chrono::Duration::hours(8);
-  let timestamp = datetime.timestamp();
+  // 本地日期時間，日光節約時間造成重複的時間取較早者，不存在的時間往後推一小時
+  let time_zone = get_time_zone();
+  let datetime_local = time_zone
+    .from_local_datetime(&datetime)
+    .earliest()
+    .or_else(|| {
+      time_zone
+        .from_local_datetime(&(datetime + Duration::hours(1)))
+        .earliest()
+    })
+    .ok_or_else(|| {
+      log::error!("Invalid local datetime: {}", datetime);
+      Status::InternalServerError
+    })?;
 
-  Ok(timestamp)
+  Ok(datetime_local.timestamp())
 }
 
 pub fn timestamp_to_naive_datetime(timestamp: i64) -> Result<NaiveDateTime, Status> {
-  // GMT+8
-  let offset = FixedOffset::east_opt(8 * 3600).ok_or_else(|| {
-    log::error!("Invalid offset");
-    Status::InternalServerError
-  })?;
-
   // GMT 0 日期時間
   let datetime = Utc.timestamp_opt(timestamp, 0).single().ok_or_else(|| {
     log::error!("Invalid timestamp");
@@ -135,11 +157,24 @@ pub fn timestamp_to_naive_datetime(timestamp: i64) -> Result<NaiveDateTime, Stat
   })?;
 
   // 本地日期時間
-  let datetime_local = datetime.with_timezone(&offset).naive_local();
+  let datetime_local = datetime.with_timezone(&get_time_zone()).naive_local();
 
   Ok(datetime_local)
 }
 
+// 舊版資料庫中以文字儲存的時間，遷移資料表時轉換
+/*
+舊版固定以 UTC+8 寫入，與目前設定的 TIME_ZONE 無關，因此以固定的 UTC+8 轉換
+*/
+pub fn db_time_to_timestamp(db_time: &str) -> Result<i64, Status> {
+  let datetime = handle(
+    NaiveDateTime::parse_from_str(db_time, "%Y-%m-%d %H:%M:%S"),
+    &format!("Parsing datetime '{}'", db_time),
+  )?;
+
+  Ok(datetime.timestamp() - constant::LEGACY_UTC_OFFSET_HOURS * 60 * 60)
+}
+
 pub fn validate_seat_id(seat_id: u16) -> Result<(), Status> {
   validate_utils::validate_seat_id(seat_id).map_err(|e| {
     let message = e.code.as_ref();
@@ -173,107 +208,424 @@ pub fn get_base_url() -> String {
   env::var("BASE_URL").expect("Failed to get base url")
 }
 
-pub fn send_verification_email(user_email: &str, verification_token: &str) -> Result<(), Status> {
-  let email_address_str = env::var("EMAIL_ADDRESS").expect("Failed to get email address");
-  let email_password = env::var("EMAIL_PASSWORD").expect("Failed to get email password");
-  let email_domain = env::var("EMAIL_DOMAIN").expect("Failed to get email domain");
+// 可由 CLOSURE_HORIZON_DAYS 設定提前產生幾天後的不可預約時段
+pub fn get_closure_horizon_days() -> i64 {
+  env::var("CLOSURE_HORIZON_DAYS")
+    .ok()
+    .and_then(|days| days.parse().ok())
+    .filter(|days| *days >= 0)
+    .unwrap_or(constant::DEFAULT_CLOSURE_HORIZON_DAYS)
+}
 
-  let email_address = handle(
-    email_address_str.parse::<Mailbox>(),
-    "Parsing email address",
+// 註冊後超過幾天仍未驗證 email 的帳號會被刪除，可由 UNVERIFIED_ACCOUNT_DAYS 設定
+pub fn get_unverified_account_days() -> i64 {
+  env::var("UNVERIFIED_ACCOUNT_DAYS")
+    .ok()
+    .and_then(|days| days.parse().ok())
+    .filter(|days| *days > 0)
+    .unwrap_or(constant::DEFAULT_UNVERIFIED_ACCOUNT_DAYS)
+}
+
+// 行事曆事件中顯示的場館名稱，可由 VENUE_NAME 設定
+pub fn get_venue_name() -> String {
+  env::var("VENUE_NAME").unwrap_or_else(|_| constant::DEFAULT_VENUE_NAME.to_string())
+}
+
+// 由 REQUIRE_ADMIN_TOTP=true 要求管理員啟用兩步驟驗證後才能使用管理功能
+pub fn is_admin_totp_required() -> bool {
+  env::var("REQUIRE_ADMIN_TOTP").is_ok_and(|required| required == "true")
+}
+
+// 反向代理的位址，由 TRUSTED_PROXIES 設定，以逗號分隔
+/*
+只有連線來自這些位址時才採用 X-Real-IP 標頭作為用戶端 IP，否則標頭可由用戶端任意偽造
+*/
+pub fn get_trusted_proxies() -> &'static Vec<IpAddr> {
+  static TRUSTED_PROXIES: OnceLock<Vec<IpAddr>> = OnceLock::new();
+
+  TRUSTED_PROXIES.get_or_init(|| {
+    env::var("TRUSTED_PROXIES")
+      .unwrap_or_default()
+      .split(',')
+      .map(str::trim)
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| {
+        entry.parse().unwrap_or_else(|e| {
+          log::error!("Invalid trusted proxy '{}': {}", entry, e);
+          panic!("Invalid trusted proxy '{}': {}", entry, e);
+        })
+      })
+      .collect()
+  })
+}
+
+// 允許註冊的 email 網域，由 ALLOWED_EMAIL_DOMAINS 設定，以逗號分隔，未設定時不限制網域
+/*
+網域後可加上 =身分 指定該網域註冊時的預設身分，未指定時為 RegularUser
+例如: mail.ntou.edu.tw,staff.ntou.edu.tw=Admin
+*/
+pub fn get_allowed_email_domains() -> &'static HashMap<String, Option<user::UserRole>> {
+  static ALLOWED_EMAIL_DOMAINS: OnceLock<HashMap<String, Option<user::UserRole>>> = OnceLock::new();
+
+  ALLOWED_EMAIL_DOMAINS.get_or_init(|| {
+    let config = env::var("ALLOWED_EMAIL_DOMAINS").unwrap_or_default();
+
+    config
+      .split(',')
+      .map(str::trim)
+      .filter(|entry| !entry.is_empty())
+      .map(|entry| match entry.split_once('=') {
+        Some((domain, role)) => {
+          let role = role.trim().parse().unwrap_or_else(|e| {
+            log::error!("Invalid role for email domain '{}': {}", entry, e);
+            panic!("Invalid role for email domain '{}': {}", entry, e);
+          });
+
+          (domain.trim().to_lowercase(), Some(role))
+        }
+        None => (entry.to_lowercase(), None),
+      })
+      .collect()
+  })
+}
+
+fn get_email_domain(email: &str) -> String {
+  email
+    .rsplit_once('@')
+    .map_or("", |(_, domain)| domain)
+    .to_lowercase()
+}
+
+pub fn is_email_domain_allowed(email: &str) -> bool {
+  let allowed_domains = get_allowed_email_domains();
+
+  allowed_domains.is_empty() || allowed_domains.contains_key(&get_email_domain(email))
+}
+
+// 依 email 網域決定註冊時的預設身分
+pub fn get_email_domain_role(email: &str) -> user::UserRole {
+  get_allowed_email_domains()
+    .get(&get_email_domain(email))
+    .cloned()
+    .flatten()
+    .unwrap_or(user::UserRole::RegularUser)
+}
+
+pub fn get_issue_photo_dir() -> String {
+  format!("{}/uploads/issues", get_root())
+}
+
+// 儲存座位問題回報的照片，回傳檔名
+pub async fn save_issue_photo(photo: &mut TempFile<'_>) -> Result<String, Status> {
+  if photo.len() > constant::MAX_ISSUE_PHOTO_SIZE {
+    log::warn!("The photo size: {} exceeds the limit", photo.len());
+    return Err(Status::PayloadTooLarge);
+  }
+
+  let extension = match photo.content_type() {
+    Some(content_type) if *content_type == ContentType::JPEG => "jpg",
+    Some(content_type) if *content_type == ContentType::PNG => "png",
+    Some(content_type) if *content_type == ContentType::WEBP => "webp",
+    _ => {
+      log::warn!("Unsupported photo content type: {:?}", photo.content_type());
+      return Err(Status::UnsupportedMediaType);
+    }
+  };
+
+  let dir = get_issue_photo_dir();
+  handle(fs::create_dir_all(&dir), "Creating issue photo directory")?;
+
+  let file_name = format!("{}.{}", Uuid::new_v4(), extension);
+  handle(
+    photo.copy_to(Path::new(&dir).join(&file_name)).await,
+    "Saving issue photo",
   )?;
 
-  let user_email = handle(user_email.parse::<Mailbox>(), "Parsing user email")?;
+  Ok(file_name)
+}
+
+// 寄送純文字郵件，寄送方式由 mailer 依設定決定
+pub fn send_email(to: &str, subject: &str, body: String) -> Result<(), Status> {
+  mailer::get_mailer().send(&mailer::Email {
+    to: to.to_string(),
+    subject: subject.to_string(),
+    body,
+  })
+}
+
+pub fn send_verification_email(user_email: &str, verification_token: &str) -> Result<(), Status> {
   let url = format!(
     "{}/api/verify?verification_token={}",
     get_base_url(),
     verification_token
   );
 
-  let email = handle(
-    Message::builder()
-      .to(user_email)
-      .from(email_address)
-      .subject("Verify your email")
-      .body(format!(
-        "Please click on the link to verify your email: {}",
-        url
-      )),
-    "Building email",
+  send_email(
+    user_email,
+    "Verify your email",
+    format!("Please click on the link to verify your email: {}", url),
+  )
+}
+
+// 重設密碼頁面的網址，可由 PASSWORD_RESET_URL 設定，token 會附加在 query string
+pub fn get_password_reset_url() -> String {
+  env::var("PASSWORD_RESET_URL").unwrap_or_else(|_| format!("{}/reset_password", get_base_url()))
+}
+
+pub fn send_password_reset_email(user_email: &str, reset_token: &str) -> Result<(), Status> {
+  let url = format!("{}?token={}", get_password_reset_url(), reset_token);
+
+  send_email(
+    user_email,
+    "Reset your password",
+    format!(
+      "Please click on the link to reset your password within {} minutes: {}\n\
+      If you did not request a password reset, please ignore this email.",
+      constant::PASSWORD_RESET_TOKEN_MINUTES,
+      url
+    ),
+  )
+}
+
+// 帳號因連續登入失敗被鎖定時通知帳號擁有者
+pub fn send_account_locked_email(
+  user_email: &str,
+  user_name: &str,
+  locked_until: i64,
+) -> Result<(), Status> {
+  send_email(
+    user_email,
+    "Your account has been temporarily locked",
+    format!(
+      "Your account '{}' has been locked until {} after too many failed login attempts.\n\
+      If this was not you, please reset your password.",
+      user_name,
+      time_to_string(locked_until)?
+    ),
+  )
+}
+
+// 資料庫只儲存 token 的 SHA-256 雜湊值，避免資料外洩時 token 可被直接使用
+pub fn hash_token(token: &str) -> String {
+  format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+// Argon2id 的參數，可由 ARGON2_MEMORY_KIB、ARGON2_ITERATIONS、ARGON2_PARALLELISM 設定
+pub fn get_argon2_params() -> &'static Params {
+  static ARGON2_PARAMS: OnceLock<Params> = OnceLock::new();
+
+  ARGON2_PARAMS.get_or_init(|| {
+    let get_param = |name: &str, default: u32| {
+      env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+    };
+
+    Params::new(
+      get_param("ARGON2_MEMORY_KIB", constant::DEFAULT_ARGON2_MEMORY_KIB),
+      get_param("ARGON2_ITERATIONS", constant::DEFAULT_ARGON2_ITERATIONS),
+      get_param("ARGON2_PARALLELISM", constant::DEFAULT_ARGON2_PARALLELISM),
+      None,
+    )
+    .unwrap_or_else(|e| {
+      log::error!("Invalid Argon2 parameters: {}", e);
+      panic!("Invalid Argon2 parameters: {}", e);
+    })
+  })
+}
+
+fn get_argon2() -> Argon2<'static> {
+  Argon2::new(
+    argon2::Algorithm::Argon2id,
+    argon2::Version::V0x13,
+    get_argon2_params().clone(),
+  )
+}
+
+// 以 Argon2id 雜湊密碼
+pub fn hash_password(password: &str) -> Result<String, Status> {
+  let salt = SaltString::generate(&mut OsRng);
+
+  let password_hash = handle(
+    get_argon2().hash_password(password.as_bytes(), &salt),
+    "Hashing password",
   )?;
 
-  let creds = Credentials::new(email_address_str, email_password);
+  Ok(password_hash.to_string())
+}
+
+// 確認密碼與雜湊值是否相符，除了 Argon2 也支援舊版的 bcrypt 雜湊值
+pub fn verify_password_hash(password: &str, password_hash: &str) -> Result<bool, Status> {
+  if !password_hash.starts_with("$argon2") {
+    return handle(verify(password, password_hash), "Verifying password");
+  }
+
+  let password_hash = handle(PasswordHash::new(password_hash), "Parsing password hash")?;
+
+  // 以雜湊值中記錄的參數驗證，設定變更前的雜湊值仍可驗證
+  Ok(
+    get_argon2()
+      .verify_password(password.as_bytes(), &password_hash)
+      .is_ok(),
+  )
+}
+
+// 舊版的 bcrypt 雜湊值，或參數與目前設定不同的 Argon2 雜湊值，需要重新雜湊
+pub fn password_needs_rehash(password_hash: &str) -> bool {
+  let password_hash = match PasswordHash::new(password_hash) {
+    Ok(password_hash) => password_hash,
+    Err(_) => return true,
+  };
 
-  let mailer = handle(
-    SmtpTransport::relay(&format!("smtp.{}.com", email_domain)),
-    "Setting SMTP server address",
-  )?
-  .credentials(creds)
-  .build();
+  if password_hash.algorithm != argon2::Algorithm::Argon2id.ident() {
+    return true;
+  }
 
-  handle(mailer.send(&email), "Sending email")?;
+  let params = get_argon2_params();
+
+  Params::try_from(&password_hash).map_or(true, |hash_params| {
+    hash_params.m_cost() != params.m_cost()
+      || hash_params.t_cost() != params.t_cost()
+      || hash_params.p_cost() != params.p_cost()
+  })
+}
+
+// 確認密碼是否正確，錯誤時回傳 Unauthorized
+pub fn verify_password(password: &str, password_hash: &str) -> Result<(), Status> {
+  if password_hash.is_empty() {
+    log::warn!("The user has no password");
+    return Err(Status::Unauthorized);
+  }
+
+  let password_matches = verify_password_hash(password, password_hash)?;
+
+  if !password_matches {
+    log::warn!("Password is incorrect");
+    return Err(Status::Unauthorized);
+  }
+
+  Ok(())
+}
+
+// 變更帳號資料前重新確認身分，有密碼的帳號需輸入密碼
+/*
+以 OIDC 建立、沒有密碼的帳號無法輸入密碼，改為要求目前的 session 在 REAUTH_MINUTES 分鐘內登入
+超過時間回傳 Unauthorized，由用戶端重新以 OIDC 登入後再操作
+*/
+pub fn verify_reauthentication(
+  password: &str,
+  password_hash: &str,
+  session_created_at: i64,
+) -> Result<(), Status> {
+  if !password_hash.is_empty() {
+    return verify_password(password, password_hash);
+  }
+
+  if get_now_timestamp() - session_created_at > constant::REAUTH_MINUTES * 60 {
+    log::warn!("The user has no password and the session is not recent");
+    return Err(Status::Unauthorized);
+  }
 
   Ok(())
 }
 
-pub fn create_userinfo_token(user_name: &str, user_role: user::UserRole) -> Result<String, Status> {
+// 登入 session 的到期時間，refresh token 在此之前都可換發 access token
+pub fn get_session_expiration(user_role: &user::UserRole) -> Result<i64, Status> {
   let duration: Duration = match user_role {
-    user::UserRole::Admin => Duration::hours(24), // 1 天後過期
-    user::UserRole::RegularUser => Duration::hours(1), // 1 小時後過期
+    user::UserRole::Admin | user::UserRole::Staff => Duration::hours(constant::ADMIN_SESSION_HOURS),
+    user::UserRole::RegularUser | user::UserRole::Kiosk => {
+      Duration::hours(constant::USER_SESSION_HOURS)
+    }
   };
 
+  Ok(get_now_timestamp() + duration.num_seconds())
+}
+
+pub fn create_userinfo_token(
+  user_name: &str,
+  user_role: user::UserRole,
+  session_id: &str,
+) -> Result<String, Status> {
   let exp = Utc::now()
-    .checked_add_signed(duration)
+    .checked_add_signed(Duration::minutes(constant::ACCESS_TOKEN_MINUTES))
     .expect("valid timestamp")
     .timestamp() as usize;
 
   let claim = token::UserInfoClaim {
     user: user_name.to_string(),
     role: user_role,
-    exp: exp,
+    sid: session_id.to_string(),
+    exp,
   };
 
-  let header = Header::new(Algorithm::HS256);
-  let key = env::var("SECRET_KEY").expect("Failed to get secret key");
-
-  let encoding_key = EncodingKey::from_secret(key.as_ref());
+  jwt::encode_token(&claim)
+}
 
-  let token = handle(encode(&header, &claim, &encoding_key), "Encoding JWT")?;
+#[cfg(test)]
+mod tests {
+  use super::*;
 
-  Ok(token)
-}
+  fn hash_with(algorithm: argon2::Algorithm, params: Params) -> String {
+    let salt = SaltString::generate(&mut OsRng);
 
-pub fn create_resend_verification_token(
-  email: &str,
-  verification_token: &str,
-  is_resend: bool,
-) -> Result<String, Status> {
-  let exp = Utc::now()
-    .checked_add_signed(Duration::hours(1)) // 1 小時後過期
-    .expect("valid timestamp")
-    .timestamp() as usize; // or u64
-
-  let mut expiration = 0;
-  if is_resend {
-    expiration = Utc::now()
-      .checked_add_signed(Duration::minutes(1))
-      .expect("valid timestamp")
-      .timestamp() as i64;
+    Argon2::new(algorithm, argon2::Version::V0x13, params)
+      .hash_password(b"password123", &salt)
+      .unwrap()
+      .to_string()
   }
 
-  let claim = token::ResendVerificationClaim {
-    email: email.to_string(),
-    verification_token: verification_token.to_string(),
-    expiration: expiration,
-    exp: exp,
-  };
+  #[test]
+  fn password_hashed_with_current_params_needs_no_rehash() {
+    let password_hash = hash_password("password123").unwrap();
 
-  let header = Header::new(Algorithm::HS256);
-  let key = env::var("SECRET_KEY").expect("Failed to get secret key");
+    assert!(!password_needs_rehash(&password_hash));
+    assert!(verify_password_hash("password123", &password_hash).unwrap());
+  }
 
-  let encoding_key = EncodingKey::from_secret(key.as_ref());
+  #[test]
+  fn legacy_or_outdated_password_hash_needs_rehash() {
+    let params = get_argon2_params();
+
+    // 舊版的 bcrypt 雜湊值
+    let bcrypt_hash = bcrypt::hash("password123", 4).unwrap();
+    assert!(password_needs_rehash(&bcrypt_hash));
+    assert!(verify_password_hash("password123", &bcrypt_hash).unwrap());
+
+    // 非 Argon2id 的演算法
+    assert!(password_needs_rehash(&hash_with(
+      argon2::Algorithm::Argon2i,
+      params.clone()
+    )));
+
+    // 參數與目前設定不同
+    let outdated_params =
+      Params::new(params.m_cost() * 2, params.t_cost(), params.p_cost(), None).unwrap();
+    assert!(password_needs_rehash(&hash_with(
+      argon2::Algorithm::Argon2id,
+      outdated_params
+    )));
+
+    assert!(password_needs_rehash("not a password hash"));
+  }
 
-  let token = handle(encode(&header, &claim, &encoding_key), "Encoding JWT")?;
+  #[test]
+  fn account_without_password_reauthenticates_with_recent_session() {
+    let now = get_now_timestamp();
+    let expired = now - constant::REAUTH_MINUTES * 60 - 1;
 
-  Ok(token)
+    assert!(verify_reauthentication("", "", now).is_ok());
+    assert_eq!(
+      verify_reauthentication("", "", expired).unwrap_err(),
+      Status::Unauthorized
+    );
+
+    // 有密碼的帳號即使剛登入也需輸入密碼
+    let password_hash = hash_password("password123").unwrap();
+    assert!(verify_reauthentication("password123", &password_hash, expired).is_ok());
+    assert_eq!(
+      verify_reauthentication("", &password_hash, now).unwrap_err(),
+      Status::Unauthorized
+    );
+  }
 }